@@ -0,0 +1,433 @@
+//! EDF+ annotations, stored as time-stamped annotation lists (TALs) in the
+//! `EDF Annotations` signal of each data record.
+
+use std::cmp::Ordering;
+use std::io::Write;
+use std::str;
+use std::time::Duration;
+
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+
+use crate::edit::unrepresentable;
+use crate::error::{Error, ErrorKind, Result};
+use crate::pipeline::header_field;
+use crate::reader::{format_seconds, Header};
+
+/// Separates the onset from the duration.
+const DURATION: u8 = 0x15;
+/// Ends the timestamp and each annotation text.
+const TEXT: u8 = 0x14;
+/// Ends a TAL.
+const END: u8 = 0x00;
+
+/// An event in a recording, such as a sleep stage or a button press.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+	/// The onset in seconds from the start of the recording.
+	pub onset: f64,
+	/// The duration in seconds, if given.
+	pub duration: Option<f64>,
+	pub text: String,
+}
+
+/// Annotations to write, laid out as the TALs of the data records of an
+/// annotation signal.
+///
+/// The annotations are written in order of onset, and one with the same
+/// onset, duration and text as another is written once. Each goes in the
+/// data record its onset falls in, unless that record is full, in which
+/// case it goes in the next one with room for it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnnotationList {
+	annotations: Vec<Annotation>,
+	max_record_len: Option<usize>,
+}
+
+impl AnnotationList {
+	pub fn new() -> AnnotationList {
+		AnnotationList::default()
+	}
+
+	/// Adds an annotation.
+	///
+	/// An onset that is not finite, or a duration that is negative or not
+	/// finite, is an `Unrepresentable` error.
+	pub fn push(&mut self, annotation: Annotation) -> Result<&mut AnnotationList> {
+		if !annotation.onset.is_finite() {
+			return Err(unrepresentable(
+				"annotation onset",
+				annotation.onset.to_string(),
+			));
+		}
+		if let Some(duration) = annotation
+			.duration
+			.filter(|d| !(d.is_finite() && *d >= 0.0))
+		{
+			return Err(unrepresentable("annotation duration", duration.to_string()));
+		}
+		self.annotations.push(annotation);
+		Ok(self)
+	}
+
+	/// Sets the most bytes of TALs a data record may hold, including the
+	/// time-keeping TAL. There is no limit by default.
+	pub fn max_record_len(&mut self, len: usize) -> &mut AnnotationList {
+		self.max_record_len = Some(len);
+		self
+	}
+
+	/// Returns the annotations in the order they are written, without
+	/// duplicates. Annotations with the same onset are ordered by text.
+	pub fn annotations(&self) -> Vec<Annotation> {
+		let mut annotations = self.annotations.clone();
+		annotations.sort_by(|a, b| {
+			a.onset
+				.total_cmp(&b.onset)
+				.then_with(|| a.text.cmp(&b.text))
+				.then_with(|| {
+					a.duration
+						.partial_cmp(&b.duration)
+						.unwrap_or(Ordering::Equal)
+				})
+		});
+		annotations.dedup();
+		annotations
+	}
+
+	/// Lays out the TALs of each of the given number of data records, each
+	/// starting with its time-keeping TAL.
+	///
+	/// An annotation that does not fit in the record its onset falls in or
+	/// any after it is an `Unrepresentable` error.
+	fn records(&self, duration: Duration, records_len: usize) -> Result<Vec<Vec<u8>>> {
+		let max_len = self.max_record_len.unwrap_or(usize::MAX);
+		let mut records = Vec::with_capacity(records_len);
+		for i in 0..records_len {
+			let onset = u32::try_from(i)
+				.ok()
+				.and_then(|i| duration.checked_mul(i))
+				.ok_or_else(|| {
+					unrepresentable("number of data records", records_len.to_string())
+				})?;
+			let tal = format!("+{}\x14\x14\0", format_seconds(onset));
+			if tal.len() > max_len {
+				return Err(unrepresentable("annotation signal", tal));
+			}
+			records.push(tal.into_bytes());
+		}
+		let seconds = duration.as_secs_f64();
+		let mut record = 0;
+		for annotation in self.annotations() {
+			if seconds > 0.0 {
+				let first = (annotation.onset / seconds).max(0.0) as usize;
+				record = record.max(first.min(records_len - 1));
+			}
+			let text = annotation.text.replace(['\x14', '\x15', '\0'], " ");
+			let tal = match annotation.duration {
+				Some(duration) => {
+					format!("{:+}\x15{}\x14{}\x14\0", annotation.onset, duration, text)
+				}
+				None => format!("{:+}\x14{}\x14\0", annotation.onset, text),
+			};
+			while record < records_len && records[record].len() + tal.len() > max_len {
+				record += 1;
+			}
+			match records.get_mut(record) {
+				Some(bytes) => bytes.extend(tal.bytes()),
+				None => return Err(unrepresentable("annotation signal", tal)),
+			}
+		}
+		Ok(records)
+	}
+}
+
+/// Parses the TALs of an annotation signal in a data record.
+///
+/// A TAL with several texts gives an annotation per text, all with the same
+/// onset and duration. Empty texts, such as those of the time-keeping TAL
+/// that starts each record, are skipped. Unused bytes at the end of the
+/// signal are zero.
+pub fn parse_tals(bytes: &[u8]) -> Result<Vec<Annotation>> {
+	let mut annotations = Vec::new();
+	for tal in bytes.split(|&b| b == END).filter(|tal| !tal.is_empty()) {
+		let invalid = || Error::new(ErrorKind::Annotation { raw: tal.to_vec() });
+		let mut parts = tal.split(|&b| b == TEXT);
+		let timestamp = parts.next().ok_or_else(invalid)?;
+		let (onset, duration) = match timestamp.iter().position(|&b| b == DURATION) {
+			Some(i) => (&timestamp[..i], Some(&timestamp[i + 1..])),
+			None => (timestamp, None),
+		};
+		if !matches!(onset.first(), Some(b'+' | b'-')) {
+			return Err(invalid());
+		}
+		let onset = parse_seconds(onset).ok_or_else(invalid)?;
+		let duration = match duration {
+			Some(duration) if !matches!(duration.first(), Some(b'+' | b'-')) => {
+				Some(parse_seconds(duration).ok_or_else(invalid)?)
+			}
+			Some(_) => return Err(invalid()),
+			None => None,
+		};
+		// The TAL ends with TEXT, so the last part is always empty.
+		if tal.last() != Some(&TEXT) {
+			return Err(invalid());
+		}
+		for text in parts.filter(|text| !text.is_empty()) {
+			annotations.push(Annotation {
+				onset,
+				duration,
+				text: str::from_utf8(text)?.to_string(),
+			});
+		}
+	}
+	Ok(annotations)
+}
+
+/// Writes annotations as an EDF+ file that holds only an `EDF Annotations`
+/// signal, so that they can be reviewed in a viewer next to the recording
+/// they were found in.
+///
+/// The file takes the identification, start, record duration and number of
+/// data records of the header of the recording, so that its records line up
+/// with those of the recording. The annotations are laid out as described
+/// for [`AnnotationList`], in the last record if their onset is past the
+/// end. Bytes in the texts that would end a TAL are written as spaces.
+pub fn write_annotation_file<W: Write>(
+	mut wtr: W,
+	hdr: &Header,
+	annotations: &AnnotationList,
+) -> Result<()> {
+	let start = hdr.start_datetime;
+	if !(1985..=2084).contains(&start.year()) {
+		return Err(unrepresentable("startdate", start.date().to_string()));
+	}
+	let duration = hdr.duration.as_secs_f64();
+	let records_len = match hdr.effective_records_len() {
+		Some(len) if duration > 0.0 => len.max(1),
+		_ => 1,
+	};
+	let records = annotations.records(hdr.duration, records_len)?;
+	let len = records.iter().map(Vec::len).max().unwrap_or(0);
+	let len = len + len % 2;
+
+	let mut header = Vec::with_capacity(512);
+	header_field(&mut header, "version", "0", 8)?;
+	header_field(
+		&mut header,
+		"local patient identification",
+		&hdr.patient_info,
+		80,
+	)?;
+	header_field(
+		&mut header,
+		"local recording identification",
+		&hdr.recording_id,
+		80,
+	)?;
+	header_field(
+		&mut header,
+		"startdate",
+		&start.format("%d.%m.%y").to_string(),
+		8,
+	)?;
+	header_field(
+		&mut header,
+		"starttime",
+		&start.format("%H.%M.%S").to_string(),
+		8,
+	)?;
+	header_field(&mut header, "number of bytes in header", "512", 8)?;
+	header_field(&mut header, "reserved", "EDF+C", 44)?;
+	header_field(
+		&mut header,
+		"number of data records",
+		&records_len.to_string(),
+		8,
+	)?;
+	header_field(
+		&mut header,
+		"duration of a data record",
+		&format_seconds(hdr.duration),
+		8,
+	)?;
+	header_field(&mut header, "number of signals", "1", 4)?;
+	header_field(&mut header, "label", "EDF Annotations", 16)?;
+	header_field(&mut header, "transducer type", "", 80)?;
+	header_field(&mut header, "physical dimension", "", 8)?;
+	header_field(&mut header, "physical minimum", "-1", 8)?;
+	header_field(&mut header, "physical maximum", "1", 8)?;
+	header_field(&mut header, "digital minimum", "-32768", 8)?;
+	header_field(&mut header, "digital maximum", "32767", 8)?;
+	header_field(&mut header, "prefiltering", "", 80)?;
+	header_field(
+		&mut header,
+		"number of samples in each data record",
+		&(len / 2).to_string(),
+		8,
+	)?;
+	header_field(&mut header, "reserved", "", 32)?;
+	wtr.write_all(&header)?;
+	for mut record in records {
+		record.resize(len, 0);
+		wtr.write_all(&record)?;
+	}
+	wtr.flush()?;
+	Ok(())
+}
+
+/// Parses the onset of the time-keeping TAL that starts an annotation
+/// signal: the start of the data record in seconds from the starttime.
+///
+/// Returns `None` if the signal holds no TALs. A record cannot start before
+/// the starttime, so a negative onset is an error.
+pub(crate) fn parse_timekeeping(bytes: &[u8]) -> Result<Option<f64>> {
+	let tal = match bytes.split(|&b| b == END).find(|tal| !tal.is_empty()) {
+		Some(tal) => tal,
+		None => return Ok(None),
+	};
+	let invalid = || Error::new(ErrorKind::Annotation { raw: tal.to_vec() });
+	// The onset is followed by an empty text, and has no duration.
+	let mut parts = tal.split(|&b| b == TEXT);
+	let onset = parts.next().ok_or_else(invalid)?;
+	if onset.first() != Some(&b'+') || parts.next() != Some(&[]) {
+		return Err(invalid());
+	}
+	parse_seconds(onset).map(Some).ok_or_else(invalid)
+}
+
+/// Parses a number of seconds, such as `+1800.2` or `25.5`.
+///
+/// Only digits and a decimal point may follow the optional sign, so that
+/// Rust float syntax such as `inf`, `NaN` or `1e11` is not accepted.
+fn parse_seconds(bytes: &[u8]) -> Option<f64> {
+	let digits = match bytes.first() {
+		Some(b'+' | b'-') => &bytes[1..],
+		_ => bytes,
+	};
+	if digits.is_empty() || !digits.iter().all(|&b| b.is_ascii_digit() || b == b'.') {
+		return None;
+	}
+	str::from_utf8(bytes)
+		.ok()?
+		.parse()
+		.ok()
+		.filter(|seconds: &f64| seconds.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::{parse_tals, parse_timekeeping, write_annotation_file, Annotation, AnnotationList};
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::Reader;
+
+	#[test]
+	fn parse_tals_per_spec() {
+		let bytes = b"+180\x14\x14\0+180\x14Lights off\x14Close door\x14\0+1800.2\x1525.5\x14Apnea\x14\0\0\0";
+		let annotation = |onset, duration, text: &str| Annotation {
+			onset,
+			duration,
+			text: text.to_string(),
+		};
+		assert_eq!(
+			parse_tals(bytes).unwrap(),
+			[
+				annotation(180.0, None, "Lights off"),
+				annotation(180.0, None, "Close door"),
+				annotation(1800.2, Some(25.5), "Apnea"),
+			]
+		);
+		assert_eq!(
+			parse_tals(b"180\x14Lights off\x14\0")
+				.unwrap_err()
+				.to_string(),
+			r#"malformed annotation list: "180\x14Lights off\x14""#
+		);
+		assert!(parse_tals(b"+180\x14Lights off\0").is_err());
+		assert_eq!(parse_timekeeping(bytes).unwrap(), Some(180.0));
+		assert_eq!(
+			parse_timekeeping(b"+0.25\x14\x14Start\x14\0").unwrap(),
+			Some(0.25)
+		);
+		assert_eq!(parse_timekeeping(b"\0\0").unwrap(), None);
+		assert!(parse_timekeeping(b"+180\x14Lights off\x14\0").is_err());
+		assert!(parse_timekeeping(b"+inf\x14\x14\0").is_err());
+		assert!(parse_timekeeping(b"-1\x14\x14\0").is_err());
+		assert!(parse_tals(b"+1e11\x14Apnea\x14\0").is_err());
+		assert!(parse_tals(b"+1\x15-5\x14Apnea\x14\0").is_err());
+	}
+
+	#[test]
+	fn write_annotation_file_for_recording() {
+		let mut recording = Vec::new();
+		Generator::new()
+			.records(4)
+			.duration(Duration::from_secs(30))
+			.channel("Flow", 30, Pattern::Noise)
+			.write(&mut recording)
+			.unwrap();
+		let hdr = Reader::from_bytes(&recording).unwrap().into_header();
+		let events = [
+			Annotation {
+				onset: 95.0,
+				duration: None,
+				text: "Desaturation\x14".to_string(),
+			},
+			Annotation {
+				onset: 42.5,
+				duration: Some(12.0),
+				text: "Apnea".to_string(),
+			},
+		];
+		let mut list = AnnotationList::new();
+		for event in events.iter().chain(&events) {
+			list.push(event.clone()).unwrap();
+		}
+		let mut bytes = Vec::new();
+		write_annotation_file(&mut bytes, &hdr, &list).unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let annotations = reader.read_annotations().unwrap();
+		let written = reader.into_header();
+
+		assert_eq!(written.records_len, Some(4));
+		assert_eq!(written.duration, hdr.duration);
+		assert_eq!(written.start_datetime, hdr.start_datetime);
+		assert_eq!(written.annotation_signals(), [0]);
+		assert_eq!(annotations.len(), 2);
+		assert_eq!(annotations[0], events[1]);
+		assert_eq!(annotations[1].onset, 95.0);
+		assert_eq!(annotations[1].text, "Desaturation ");
+	}
+
+	#[test]
+	fn annotation_list_splits_records() {
+		let mut list = AnnotationList::new();
+		list.max_record_len(24);
+		for text in ["Apnea", "Arousal", "Snore"] {
+			list.push(Annotation {
+				onset: 0.5,
+				duration: None,
+				text: text.to_string(),
+			})
+			.unwrap();
+		}
+		let records = list.records(Duration::from_secs(1), 3).unwrap();
+
+		// Each record has room for its time-keeping TAL and one annotation.
+		assert_eq!(records[0], b"+0\x14\x14\0+0.5\x14Apnea\x14\0");
+		assert_eq!(records[1], b"+1\x14\x14\0+0.5\x14Arousal\x14\0");
+		assert_eq!(records[2], b"+2\x14\x14\0+0.5\x14Snore\x14\0");
+		assert!(list.records(Duration::from_secs(1), 2).is_err());
+		let negative = Annotation {
+			onset: 1.0,
+			duration: Some(-1.0),
+			text: "Apnea".to_string(),
+		};
+		assert_eq!(
+			list.push(negative).unwrap_err().to_string(),
+			"cannot write -1 to the annotation duration field"
+		);
+	}
+}
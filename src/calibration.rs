@@ -0,0 +1,108 @@
+//! The digital-to-physical calibration of each signal.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::reader::Header;
+
+/// The calibration of a signal, as derived from its physical and digital
+/// ranges.
+///
+/// This is what to check first when amplitudes look wrong, e.g. a physical
+/// dimension of `mV` where `uV` was meant, or a swapped physical range.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Calibration {
+	pub label: String,
+	/// The physical dimension, e.g. `uV`.
+	pub unit: String,
+	/// The physical units per digital step. Negative if the signal is
+	/// inverted.
+	pub gain: f64,
+	/// The physical value of a digital zero.
+	pub offset: f64,
+	/// The smallest physical difference that can be represented, in
+	/// physical units per bit.
+	pub resolution: f64,
+	/// The dynamic range in dB, from the number of digital levels.
+	pub dynamic_range: f64,
+}
+
+impl fmt::Display for Calibration {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{}: gain {} {unit}/bit, offset {} {unit}, resolution {} {unit}, dynamic range {:.1} dB",
+			self.label,
+			self.gain,
+			self.offset,
+			self.resolution,
+			self.dynamic_range,
+			unit = self.unit
+		)
+	}
+}
+
+/// Computes the calibration of every signal except annotation signals.
+pub fn calibration(hdr: &Header) -> Vec<Calibration> {
+	hdr.signals
+		.iter()
+		.filter(|s| !s.is_annotation())
+		.map(|s| {
+			let levels = s.digital_max as f64 - s.digital_min as f64 + 1.0;
+			Calibration {
+				label: s.label.clone(),
+				unit: s.physical_dimension.clone(),
+				gain: s.gain(),
+				offset: s.offset(),
+				resolution: s.gain().abs(),
+				dynamic_range: 20.0 * levels.log10(),
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use chrono::NaiveDate;
+
+	use super::calibration;
+	use crate::reader::{FileFormat, Header};
+	use crate::signal::SignalHeader;
+
+	#[test]
+	fn calibration_of_inverted_signal() {
+		let mut hdr = Header::new(
+			FileFormat::Edf,
+			String::new(),
+			String::new(),
+			NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(),
+			Default::default(),
+			512,
+			String::new(),
+			Some(1),
+			Duration::from_secs(1),
+			1,
+		);
+		hdr.signals.push(SignalHeader {
+			label: "EEG Fpz-Cz".to_string(),
+			transducer: String::new(),
+			physical_dimension: "uV".to_string(),
+			physical_min: 512.0,
+			physical_max: -512.0,
+			digital_min: -2048,
+			digital_max: 2047,
+			prefiltering: String::new(),
+			samples_per_record: 100,
+			reserved: String::new(),
+		});
+
+		let calibration = calibration(&hdr);
+		assert_eq!(calibration.len(), 1);
+		assert!((calibration[0].gain + 0.25).abs() < 1e-3);
+		assert_eq!(calibration[0].resolution, -calibration[0].gain);
+		assert!((calibration[0].dynamic_range - 72.2).abs() < 0.1);
+	}
+}
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use crate::error::Result;
+use crate::session::read_headers;
+use crate::sniff::Format;
+
+/// Lightweight metadata about a file, for indexing archives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogEntry {
+	pub path: PathBuf,
+	/// The format of the file. If the path could not be read, then it is
+	/// `None`.
+	pub format: Option<Format>,
+	/// The EDF+ patient code, if the patient field follows EDF+.
+	pub patient_code: Option<String>,
+	pub start: Option<NaiveDateTime>,
+	/// The duration of the recording. If the number of records is unknown,
+	/// then it is `None`.
+	pub duration: Option<Duration>,
+	/// The number of signals.
+	pub channels: Option<u32>,
+	/// Problems found while reading the header.
+	pub issues: Vec<String>,
+}
+
+/// Lists every EDF-family file under a directory, including subdirectories.
+///
+/// Files are recognized by their magic bytes rather than their extension.
+/// Symbolic links to directories are followed. Headers are read in parallel.
+/// Files whose header cannot be read are still listed, with the error among
+/// their issues, and so are the files and subdirectories that cannot be
+/// read at all, without a format. Only failing to read the directory itself
+/// is an error.
+pub fn catalog<P: AsRef<Path>>(dir: P) -> Result<Vec<CatalogEntry>> {
+	let mut files = Vec::new();
+	find_files(dir.as_ref(), &mut HashSet::new(), &mut files)?;
+	files.sort_by(|a, b| a.0.cmp(&b.0));
+
+	let paths: Vec<PathBuf> = files
+		.iter()
+		.filter(|(_, format)| format.is_ok())
+		.map(|(path, _)| path.clone())
+		.collect();
+	let mut headers = read_headers(&paths).into_iter();
+	let entries = files
+		.into_iter()
+		.map(|(path, format)| {
+			let mut entry = CatalogEntry {
+				path,
+				format: None,
+				patient_code: None,
+				start: None,
+				duration: None,
+				channels: None,
+				issues: Vec::new(),
+			};
+			let header = match format {
+				Ok(format) => {
+					entry.format = Some(format);
+					headers.next().expect("a header per readable file")
+				}
+				Err(err) => Err(err),
+			};
+			match header {
+				Ok(hdr) => {
+					entry.patient_code = hdr.patient().ok().and_then(|p| p.code);
+					entry.start = Some(hdr.start_datetime);
+					entry.duration = hdr
+						.effective_records_len()
+						.and_then(|n| hdr.duration.checked_mul(u32::try_from(n).ok()?));
+					entry.channels = Some(hdr.signals_len);
+					match (hdr.records_len, hdr.inferred_records_len) {
+						(None, Some(n)) => entry.issues.push(format!(
+							"unknown number of data records, {} inferred from the file size",
+							n
+						)),
+						(None, None) => entry
+							.issues
+							.push("unknown number of data records".to_string()),
+						_ => {}
+					}
+				}
+				Err(err) => entry.issues.push(err.to_string()),
+			}
+			entry
+		})
+		.collect();
+	Ok(entries)
+}
+
+/// Collects the EDF-family files under a directory with their formats, and
+/// the paths under it that cannot be read with the error.
+///
+/// Each directory is walked once, so that a link to a parent directory does
+/// not loop.
+fn find_files(
+	dir: &Path,
+	visited: &mut HashSet<PathBuf>,
+	files: &mut Vec<(PathBuf, Result<Format>)>,
+) -> Result<()> {
+	if !visited.insert(fs::canonicalize(dir)?) {
+		return Ok(());
+	}
+	for entry in fs::read_dir(dir)? {
+		let path = match entry {
+			Ok(entry) => entry.path(),
+			Err(err) => {
+				files.push((dir.to_path_buf(), Err(err.into())));
+				continue;
+			}
+		};
+		// Unlike the file type of the entry, the metadata follows links.
+		let is_dir = match fs::metadata(&path) {
+			Ok(metadata) => metadata.is_dir(),
+			Err(err) => {
+				files.push((path, Err(err.into())));
+				continue;
+			}
+		};
+		if is_dir {
+			if let Err(err) = find_files(&path, visited, files) {
+				files.push((path, Err(err)));
+			}
+			continue;
+		}
+		match Format::sniff_path(&path) {
+			Ok(Some(format @ (Format::Edf | Format::Bdf | Format::Gdf))) => {
+				files.push((path, Ok(format)))
+			}
+			Ok(_) => {}
+			Err(err) => files.push((path, Err(err))),
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::time::Duration;
+
+	use super::catalog;
+	use crate::fixture::TestHeader;
+	use crate::sniff::Format;
+
+	#[test]
+	fn catalog_walks_subdirectories() {
+		let dir = std::env::temp_dir().join(format!("edf-catalog-{}", std::process::id()));
+		fs::create_dir_all(dir.join("sub-01")).unwrap();
+		let header = TestHeader {
+			patient_info: "MCH-0234567 F X X",
+			recording_id: "",
+			records_len: "10",
+			duration: "30",
+			..TestHeader::default()
+		};
+		fs::write(dir.join("sub-01").join("night.rec"), header.to_bytes()).unwrap();
+		fs::write(dir.join("broken.gdf"), b"GDF 2.20").unwrap();
+		fs::write(dir.join("notes.txt"), "").unwrap();
+
+		let entries = catalog(&dir).unwrap();
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].format, Some(Format::Gdf));
+		assert_eq!(entries[0].issues, ["GDF files are not supported"]);
+		assert_eq!(entries[1].patient_code.as_deref(), Some("MCH-0234567"));
+		assert_eq!(entries[1].duration, Some(Duration::from_secs(300)));
+		assert_eq!(entries[1].channels, Some(0));
+		assert!(entries[1].issues.is_empty());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn catalog_follows_links() {
+		use std::os::unix::fs::symlink;
+
+		use crate::generator::Generator;
+
+		let dir = std::env::temp_dir().join(format!("edf-catalog-links-{}", std::process::id()));
+		let (archive, elsewhere) = (dir.join("archive"), dir.join("elsewhere"));
+		fs::create_dir_all(&archive).unwrap();
+		fs::create_dir_all(&elsewhere).unwrap();
+		Generator::new()
+			.records(1)
+			.to_path(elsewhere.join("night.edf"))
+			.unwrap();
+		symlink(&elsewhere, archive.join("linked")).unwrap();
+		symlink(&archive, archive.join("parent")).unwrap();
+		symlink(dir.join("missing"), archive.join("dangling")).unwrap();
+
+		let entries = catalog(&archive).unwrap();
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].path, archive.join("dangling"));
+		assert_eq!(entries[0].format, None);
+		assert_eq!(entries[0].issues.len(), 1);
+		assert_eq!(entries[1].path, archive.join("linked").join("night.edf"));
+		assert_eq!(entries[1].format, Some(Format::Edf));
+		assert!(entries[1].issues.is_empty());
+	}
+}
@@ -0,0 +1,316 @@
+//! Checksum manifests over the sample data of EDF files.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::reader::Reader;
+
+/// Computes the SHA-256 digest of the data records of a file, as lowercase hex.
+///
+/// Only the bytes following the header are hashed, so edits to the header,
+/// such as anonymization, leave the digest unchanged.
+pub fn data_digest<P: AsRef<Path>>(path: P) -> Result<String> {
+	let path = path.as_ref();
+	let hdr = Reader::from_path(path)?.into_header();
+	let mut f = File::open(path)?;
+	f.seek(SeekFrom::Start(hdr.size as u64))?;
+	let mut hasher = Sha256::new();
+	io::copy(&mut f, &mut hasher)?;
+	Ok(to_hex(&hasher.finalize()))
+}
+
+/// Computes the SHA-256 digest of each data record of a file, as lowercase
+/// hex.
+///
+/// A partial record at the end of the file is left out.
+pub fn record_digests<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+	let path = path.as_ref();
+	let hdr = Reader::from_path(path)?.into_header();
+	let records_len = hdr.effective_records_len().unwrap_or(0);
+	let mut f = BufReader::new(File::open(path)?);
+	f.seek(SeekFrom::Start(hdr.size as u64))?;
+	let mut buffer = vec![0; hdr.record_size().unwrap_or(0)];
+	let mut digests = Vec::new();
+	for _ in 0..records_len {
+		match f.read_exact(&mut buffer) {
+			Ok(()) => digests.push(to_hex(&Sha256::digest(&buffer))),
+			Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+			Err(err) => return Err(err.into()),
+		}
+	}
+	Ok(digests)
+}
+
+/// Computes the SHA-256 digest of the raw digital samples of each signal of
+/// a file, as lowercase hex, in signal order.
+///
+/// Only the samples are hashed, so a signal keeps its digest across copies
+/// whose headers or other signals differ, such as an anonymized copy. A
+/// partial record at the end of the file is left out.
+pub fn signal_digests<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+	let path = path.as_ref();
+	let hdr = Reader::from_path(path)?.into_header();
+	let records_len = hdr.effective_records_len().unwrap_or(0);
+	let bytes_per_sample = hdr.format.bytes_per_sample();
+	let mut f = BufReader::new(File::open(path)?);
+	f.seek(SeekFrom::Start(hdr.size as u64))?;
+	let mut buffer = vec![0; hdr.record_size().unwrap_or(0)];
+	let mut hashers = vec![Sha256::new(); hdr.signals.len()];
+	for _ in 0..records_len {
+		match f.read_exact(&mut buffer) {
+			Ok(()) => {}
+			Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+			Err(err) => return Err(err.into()),
+		}
+		let mut offset = 0;
+		for (hasher, signal) in hashers.iter_mut().zip(&hdr.signals) {
+			let len = signal.samples_per_record * bytes_per_sample;
+			hasher.update(&buffer[offset..offset + len]);
+			offset += len;
+		}
+	}
+	Ok(hashers
+		.into_iter()
+		.map(|hasher| to_hex(&hasher.finalize()))
+		.collect())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A line of a checksum manifest.
+///
+/// Entries are written in the same layout as `sha256sum`: the digest, two
+/// spaces and the path. Entries for a single data record have its index
+/// after the digest, separated by one space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+	/// The digest of the data records, as lowercase hex.
+	pub digest: String,
+	pub path: PathBuf,
+	/// The index of the data record the digest is of, or `None` if it is of
+	/// all of them.
+	pub record: Option<usize>,
+}
+
+impl ManifestEntry {
+	/// Computes the entry for a file.
+	pub fn compute<P: AsRef<Path>>(path: P) -> Result<ManifestEntry> {
+		let path = path.as_ref();
+		let digest = data_digest(path)?;
+		Ok(ManifestEntry {
+			digest,
+			path: path.to_path_buf(),
+			record: None,
+		})
+	}
+
+	/// Computes an entry for each data record of a file, so that a
+	/// verification can tell which records changed.
+	pub fn compute_records<P: AsRef<Path>>(path: P) -> Result<Vec<ManifestEntry>> {
+		let path = path.as_ref();
+		let digests = record_digests(path)?;
+		Ok(digests
+			.into_iter()
+			.enumerate()
+			.map(|(i, digest)| ManifestEntry {
+				digest,
+				path: path.to_path_buf(),
+				record: Some(i),
+			})
+			.collect())
+	}
+
+	/// Recomputes the digest of the file or record and checks it against the
+	/// entry.
+	pub fn verify(&self) -> Result<bool> {
+		Ok(verify_file(&self.path, std::slice::from_ref(self))?.is_empty())
+	}
+}
+
+impl fmt::Display for ManifestEntry {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.record {
+			Some(record) => write!(f, "{} {}  {}", self.digest, record, self.path.display()),
+			None => write!(f, "{}  {}", self.digest, self.path.display()),
+		}
+	}
+}
+
+/// Checks manifest entries against a file, returning the entries that do
+/// not match.
+///
+/// The entries are expected to be for the given path. The digests of the
+/// data records are computed at most once, however many entries there are,
+/// and a record that is no longer in the file does not match.
+pub fn verify_file<P: AsRef<Path>>(
+	path: P,
+	entries: &[ManifestEntry],
+) -> Result<Vec<&ManifestEntry>> {
+	let path = path.as_ref();
+	let mut mismatches = Vec::new();
+	let mut data = None;
+	let mut records = None;
+	for entry in entries {
+		let digest = match entry.record {
+			None => {
+				if data.is_none() {
+					data = Some(data_digest(path)?);
+				}
+				data.as_ref()
+			}
+			Some(i) => {
+				if records.is_none() {
+					records = Some(record_digests(path)?);
+				}
+				records
+					.as_ref()
+					.and_then(|digests: &Vec<String>| digests.get(i))
+			}
+		};
+		if digest != Some(&entry.digest) {
+			mismatches.push(entry);
+		}
+	}
+	Ok(mismatches)
+}
+
+/// Writes a manifest, one entry per line.
+pub fn write_manifest<W: Write>(mut wtr: W, entries: &[ManifestEntry]) -> Result<()> {
+	for entry in entries {
+		writeln!(wtr, "{}", entry)?;
+	}
+	Ok(())
+}
+
+/// Reads a manifest written by `write_manifest`.
+///
+/// Blank lines are skipped.
+pub fn read_manifest<R: BufRead>(rdr: R) -> Result<Vec<ManifestEntry>> {
+	let mut entries = Vec::new();
+	for (i, line) in rdr.lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		let entry = parse_manifest_line(&line)
+			.ok_or_else(|| Error::new(ErrorKind::Manifest { line: i + 1 }))?;
+		entries.push(entry);
+	}
+	Ok(entries)
+}
+
+fn parse_manifest_line(line: &str) -> Option<ManifestEntry> {
+	let (digest, path) = line.split_once("  ")?;
+	let (digest, record) = match digest.split_once(' ') {
+		Some((digest, record)) => (digest, Some(record.parse().ok()?)),
+		None => (digest, None),
+	};
+	if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return None;
+	}
+	Some(ManifestEntry {
+		digest: digest.to_ascii_lowercase(),
+		path: PathBuf::from(path),
+		record,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::{parse_manifest_line, read_manifest, signal_digests, verify_file, ManifestEntry};
+	use crate::generator::{Generator, Pattern};
+
+	const DIGEST: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+	#[test]
+	fn manifest_round_trip() {
+		let entry = ManifestEntry {
+			digest: DIGEST.to_string(),
+			path: PathBuf::from("night 1/sub-01.edf"),
+			record: None,
+		};
+		assert_eq!(parse_manifest_line(&entry.to_string()), Some(entry));
+		let entry = ManifestEntry {
+			digest: DIGEST.to_string(),
+			path: PathBuf::from("night 1/sub-01.edf"),
+			record: Some(12),
+		};
+		assert_eq!(
+			entry.to_string(),
+			format!("{} 12  night 1/sub-01.edf", DIGEST)
+		);
+		assert_eq!(parse_manifest_line(&entry.to_string()), Some(entry));
+	}
+
+	#[test]
+	fn verify_records() {
+		let path = std::env::temp_dir().join(format!("edf-checksum-{}.edf", std::process::id()));
+		Generator::new()
+			.records(3)
+			.channel("Noise", 4, Pattern::Noise)
+			.to_path(&path)
+			.unwrap();
+		let mut entries = ManifestEntry::compute_records(&path).unwrap();
+		entries.push(ManifestEntry::compute(&path).unwrap());
+		assert_eq!(entries.len(), 4);
+		assert!(verify_file(&path, &entries).unwrap().is_empty());
+
+		// Flip a bit in the second record.
+		let mut bytes = std::fs::read(&path).unwrap();
+		let signals: usize = std::str::from_utf8(&bytes[252..256])
+			.unwrap()
+			.trim()
+			.parse()
+			.unwrap();
+		let header_size = 256 * (signals + 1);
+		let second_record = header_size + (bytes.len() - header_size) / 3;
+		bytes[second_record] ^= 1;
+		std::fs::write(&path, &bytes).unwrap();
+		let mismatches = verify_file(&path, &entries).unwrap();
+		assert_eq!(mismatches, [&entries[1], &entries[3]]);
+
+		std::fs::remove_file(&path).unwrap();
+		assert!(entries[0].verify().is_err());
+	}
+
+	#[test]
+	fn signal_digests_ignore_header() {
+		let path = std::env::temp_dir().join(format!("edf-signals-{}.edf", std::process::id()));
+		Generator::new()
+			.records(2)
+			.channel("Noise", 4, Pattern::Noise)
+			.channel("Sine", 2, Pattern::Sine { frequency: 1.0 })
+			.to_path(&path)
+			.unwrap();
+		let before = signal_digests(&path).unwrap();
+
+		// Change the patient field and the last sample of the second signal.
+		let mut bytes = std::fs::read(&path).unwrap();
+		bytes[8..16].copy_from_slice(b"anon    ");
+		let last = bytes.len() - 1;
+		bytes[last] ^= 1;
+		std::fs::write(&path, &bytes).unwrap();
+		let after = signal_digests(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(before.len(), 2);
+		assert_eq!(after[0], before[0]);
+		assert_ne!(after[1], before[1]);
+	}
+
+	#[test]
+	fn manifest_rejects_bad_digest() {
+		assert_eq!(parse_manifest_line("abc  sub-01.edf"), None);
+		let err = read_manifest(format!("{}  a.edf\n\nnope\n", DIGEST).as_bytes()).unwrap_err();
+		assert_eq!(err.to_string(), "malformed manifest entry on line 3");
+	}
+}
@@ -0,0 +1,93 @@
+//! Per-record CRC-32 sidecars, for validating transfers record by record.
+
+use std::io::{BufRead, Write};
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// The lookup table of CRC-32 (IEEE 802.3), one entry per byte value.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+	let mut table = [0; 256];
+	let mut i = 0;
+	while i < 256 {
+		let mut crc = i as u32;
+		let mut bit = 0;
+		while bit < 8 {
+			crc = if crc & 1 == 1 {
+				0xedb8_8320 ^ (crc >> 1)
+			} else {
+				crc >> 1
+			};
+			bit += 1;
+		}
+		table[i] = crc;
+		i += 1;
+	}
+	table
+}
+
+/// Computes the CRC-32 of the bytes, as used by zip and gzip.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+	crc32_update(0, bytes)
+}
+
+/// Extends the CRC-32 of some bytes to the bytes that follow them, for
+/// checksumming data read in chunks.
+pub(crate) fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+	!bytes.iter().fold(!crc, |crc, &b| {
+		CRC32_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+	})
+}
+
+/// Writes the CRC-32 of each data record as a sidecar, one record per line.
+///
+/// Each line holds the index of the record and its CRC as 8 hex digits,
+/// separated by a tab. A record cut short at the end is left out.
+pub fn write_record_crcs<W: Write>(mut wtr: W, data: &[u8], record_size: usize) -> Result<()> {
+	if record_size == 0 {
+		return Ok(());
+	}
+	for (i, record) in data.chunks_exact(record_size).enumerate() {
+		writeln!(wtr, "{}\t{:08x}", i, crc32(record))?;
+	}
+	Ok(())
+}
+
+/// Reads a sidecar written by `write_record_crcs`.
+///
+/// The records must be listed in order. Blank lines are skipped.
+pub fn read_record_crcs<R: BufRead>(rdr: R) -> Result<Vec<u32>> {
+	let mut crcs = Vec::new();
+	for (i, line) in rdr.lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		let crc = line
+			.split_once('\t')
+			.filter(|(index, _)| index.parse() == Ok(crcs.len()))
+			.and_then(|(_, crc)| u32::from_str_radix(crc, 16).ok())
+			.ok_or_else(|| Error::new(ErrorKind::Manifest { line: i + 1 }))?;
+		crcs.push(crc);
+	}
+	Ok(crcs)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{crc32, read_record_crcs, write_record_crcs};
+
+	#[test]
+	fn record_crcs_round_trip() {
+		assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+		let mut sidecar = Vec::new();
+		write_record_crcs(&mut sidecar, b"abcdefg", 3).unwrap();
+		assert_eq!(sidecar, b"0\t352441c2\n1\t0cc4e161\n");
+		assert_eq!(
+			read_record_crcs(sidecar.as_slice()).unwrap(),
+			[0x3524_41c2, 0x0cc4_e161]
+		);
+		assert!(read_record_crcs(&b"1\t352441c2\n"[..]).is_err());
+	}
+}
@@ -0,0 +1,505 @@
+//! In-place edits of the header of an EDF file.
+//!
+//! Edits only ever overwrite fixed-width header fields, so the layout of the
+//! file and its data records are left untouched.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDateTime};
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::identification::RecordingId;
+use crate::layout::{field_name, header_fields};
+use crate::reader::{FileType, Header, Reader};
+
+/// A header field changed by an edit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+	/// The field, named as by [`offsets`](crate::offsets), e.g.
+	/// `signal 1 physical minimum`.
+	pub field: String,
+	/// The offset of the field from the start of the file.
+	pub offset: u64,
+	/// The bytes of the field before the edit, including padding.
+	pub old: Vec<u8>,
+	/// The bytes of the field after the edit, including padding.
+	pub new: Vec<u8>,
+}
+
+impl fmt::Display for FieldChange {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} at byte {}: \"{}\" -> \"{}\"",
+			self.field,
+			self.offset,
+			self.old.escape_ascii(),
+			self.new.escape_ascii()
+		)
+	}
+}
+
+/// Edits of the header of a file, made in memory until they are saved.
+///
+/// The changes can be listed before anything is written, as for a dry run.
+/// Each edit sees the ones made before it, so several edits of the same
+/// signal combine as they would when saved one at a time.
+#[derive(Debug)]
+pub struct HeaderEditor {
+	path: PathBuf,
+	hdr: Header,
+	original: Vec<u8>,
+	buffer: Vec<u8>,
+}
+
+impl HeaderEditor {
+	/// Reads the header of the file at the given path for editing.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<HeaderEditor> {
+		let path = path.as_ref();
+		let hdr = Reader::from_path(path)?.into_header();
+		let mut original = vec![0; hdr.size];
+		File::open(path)?.read_exact(&mut original)?;
+		Ok(HeaderEditor {
+			path: path.to_path_buf(),
+			hdr,
+			buffer: original.clone(),
+			original,
+		})
+	}
+
+	/// Returns the header with the edits made so far.
+	pub fn header(&self) -> &Header {
+		&self.hdr
+	}
+
+	/// Sets the start date and time of the recording.
+	///
+	/// The EDF+ startdate subfield of the recording identification is updated
+	/// as well, so the two stay consistent. Plain EDF recording fields are
+	/// left as they are.
+	///
+	/// The two-digit year of the start date can only represent 1985 to 2084.
+	/// Later years are written as `yy`, as EDF+ specifies, if the file is
+	/// EDF+ and has a startdate subfield to give the full date.
+	pub fn set_start(&mut self, start: NaiveDateTime) -> Result<()> {
+		let id = RecordingId::parse(&self.hdr.recording_id).ok();
+		let edf_plus = self.hdr.file_type() != FileType::Edf && id.is_some();
+		let year = match start.year() {
+			1985..=2084 => start.format("%y").to_string(),
+			year if year > 2084 && edf_plus => "yy".to_string(),
+			_ => return Err(unrepresentable("startdate", start.date().to_string())),
+		};
+		if let Some(mut id) = id {
+			id.startdate = Some(start.date());
+			let id = id.to_string();
+			if id.len() > 80 {
+				return Err(unrepresentable("local recording identification", id));
+			}
+			self.write_field("local recording identification", None, &id);
+			self.hdr.recording_id = id;
+		}
+		let startdate = format!("{}.{}", start.format("%d.%m"), year);
+		self.write_field("startdate", None, &startdate);
+		self.write_field("starttime", None, &start.format("%H.%M.%S").to_string());
+		self.hdr.start_datetime = start;
+		Ok(())
+	}
+
+	/// Sets the physical dimension of a signal. See [`set_physical_dimension`].
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at the given index.
+	pub fn set_physical_dimension(&mut self, signal: usize, unit: &str) -> Result<()> {
+		assert!(signal < self.hdr.signals.len(), "no signal {}", signal);
+		if unit.len() > 8 || !unit.is_ascii() {
+			return Err(unrepresentable("physical dimension", unit.to_string()));
+		}
+		self.write_field("physical dimension", Some(signal), unit);
+		self.hdr.signals[signal].physical_dimension = unit.to_string();
+		Ok(())
+	}
+
+	/// Inverts the polarity of a signal. See [`invert_polarity`].
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at the given index.
+	pub fn invert_polarity(&mut self, signal: usize) -> Result<()> {
+		assert!(signal < self.hdr.signals.len(), "no signal {}", signal);
+		let s = &self.hdr.signals[signal];
+		// Adding zero turns a negated zero into a plain one.
+		self.set_physical_range(signal, -s.physical_min + 0.0, -s.physical_max + 0.0)
+	}
+
+	/// Rescales a signal by a factor. See [`scale_signal`].
+	///
+	/// A factor that is zero or not finite would leave no physical range, so
+	/// it is an `Unrepresentable` error.
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at the given index.
+	pub fn scale_signal(&mut self, signal: usize, factor: f64) -> Result<()> {
+		assert!(signal < self.hdr.signals.len(), "no signal {}", signal);
+		let s = &self.hdr.signals[signal];
+		let (min, max) = (s.physical_min * factor, s.physical_max * factor);
+		if !factor.is_finite() || factor == 0.0 {
+			return Err(unrepresentable("physical minimum", (min + 0.0).to_string()));
+		}
+		self.set_physical_range(signal, min, max)
+	}
+
+	/// Blanks the reserved field and the reserved field of each signal, where
+	/// some vendors store patient or study identifiers.
+	///
+	/// The `EDF+C` or `EDF+D` that marks an EDF+ file is kept, as it is what
+	/// makes the file EDF+.
+	pub fn blank_reserved(&mut self) {
+		let reserved = match self.hdr.file_type() {
+			FileType::Edf => String::new(),
+			_ => self.hdr.reserved[..5].to_string(),
+		};
+		self.write_field("reserved", None, &reserved);
+		self.hdr.reserved = reserved;
+		for i in 0..self.hdr.signals.len() {
+			self.write_field("reserved", Some(i), "");
+			self.hdr.signals[i].reserved.clear();
+		}
+	}
+
+	/// Lists the header fields that differ from the file, in file order.
+	pub fn changes(&self) -> Vec<FieldChange> {
+		header_fields(self.hdr.signals.len())
+			.filter(|&(_, _, offset, len)| offset + len <= self.buffer.len() as u64)
+			.filter_map(|(name, signal, offset, len)| {
+				let range = offset as usize..(offset + len) as usize;
+				let (old, new) = (&self.original[range.clone()], &self.buffer[range]);
+				(old != new).then(|| FieldChange {
+					field: field_name(name, signal),
+					offset,
+					old: old.to_vec(),
+					new: new.to_vec(),
+				})
+			})
+			.collect()
+	}
+
+	/// Writes the changed fields to the file.
+	pub fn save(&self) -> Result<()> {
+		let mut f = OpenOptions::new().write(true).open(&self.path)?;
+		for change in self.changes() {
+			f.seek(SeekFrom::Start(change.offset))?;
+			f.write_all(&change.new)?;
+		}
+		Ok(())
+	}
+
+	/// Overwrites the physical minimum and maximum of a signal.
+	fn set_physical_range(&mut self, signal: usize, min: f64, max: f64) -> Result<()> {
+		let min = format_number(min)
+			.ok_or_else(|| unrepresentable("physical minimum", min.to_string()))?;
+		let max = format_number(max)
+			.ok_or_else(|| unrepresentable("physical maximum", max.to_string()))?;
+		self.write_field("physical minimum", Some(signal), &min);
+		self.write_field("physical maximum", Some(signal), &max);
+		// Keep the values as written, so that later edits start from them.
+		let s = &mut self.hdr.signals[signal];
+		s.physical_min = min.parse().unwrap_or(s.physical_min);
+		s.physical_max = max.parse().unwrap_or(s.physical_max);
+		Ok(())
+	}
+
+	/// Writes a value to a header field, padded with spaces. The value must
+	/// fit the field.
+	fn write_field(&mut self, name: &str, signal: Option<usize>, value: &str) {
+		let (_, _, offset, width) = header_fields(self.hdr.signals.len())
+			.find(|&(n, s, _, _)| n == name && s == signal)
+			.expect("a header field");
+		let (offset, width) = (offset as usize, width as usize);
+		self.buffer[offset..offset + width]
+			.copy_from_slice(format!("{:<1$}", value, width).as_bytes());
+	}
+}
+
+/// Sets the start date and time of a recording.
+///
+/// The EDF+ startdate subfield of the recording identification is updated as
+/// well, so the two stay consistent. Plain EDF recording fields are left
+/// as they are.
+///
+/// The two-digit year of the start date can only represent 1985 to 2084.
+pub fn set_start<P: AsRef<Path>>(path: P, start: NaiveDateTime) -> Result<()> {
+	let mut editor = HeaderEditor::open(path)?;
+	editor.set_start(start)?;
+	editor.save()
+}
+
+/// Sets the physical dimension of a signal, e.g. to fix a `uV` signal that
+/// was labeled `mV`.
+///
+/// Only the unit changes. The physical range and thus the samples are left
+/// as they are.
+///
+/// # Panics
+///
+/// Panics if there is no signal at the given index.
+pub fn set_physical_dimension<P: AsRef<Path>>(path: P, signal: usize, unit: &str) -> Result<()> {
+	let mut editor = HeaderEditor::open(path)?;
+	editor.set_physical_dimension(signal, unit)?;
+	editor.save()
+}
+
+/// Inverts the polarity of a signal, for recordings where the electrodes
+/// were wired backwards.
+///
+/// The physical minimum and maximum are negated, which negates every
+/// physical sample without touching the data records. The physical maximum
+/// is then below the physical minimum, which the spec allows.
+///
+/// # Panics
+///
+/// Panics if there is no signal at the given index.
+pub fn invert_polarity<P: AsRef<Path>>(path: P, signal: usize) -> Result<()> {
+	let mut editor = HeaderEditor::open(path)?;
+	editor.invert_polarity(signal)?;
+	editor.save()
+}
+
+/// Rescales a signal by a factor, e.g. 1000 for a `uV` signal whose values
+/// were recorded in `mV`.
+///
+/// The physical minimum and maximum are multiplied by the factor, which
+/// scales every physical sample without touching the data records. They are
+/// rounded to fit their eight-character fields, so the factor can be off by
+/// a few parts per million. Use [`set_physical_dimension`] to change the unit
+/// as well.
+///
+/// # Panics
+///
+/// Panics if there is no signal at the given index.
+pub fn scale_signal<P: AsRef<Path>>(path: P, signal: usize, factor: f64) -> Result<()> {
+	let mut editor = HeaderEditor::open(path)?;
+	editor.scale_signal(signal, factor)?;
+	editor.save()
+}
+
+/// Blanks the reserved field and the reserved field of each signal, where
+/// some vendors store patient or study identifiers. The `EDF+C` or `EDF+D`
+/// that marks an EDF+ file is kept.
+pub fn blank_reserved<P: AsRef<Path>>(path: P) -> Result<()> {
+	let mut editor = HeaderEditor::open(path)?;
+	editor.blank_reserved();
+	editor.save()
+}
+
+/// Formats a number to fit an eight-character field, rounding off decimals
+/// as needed.
+pub(crate) fn format_number(value: f64) -> Option<String> {
+	if !value.is_finite() {
+		return None;
+	}
+	let exact = value.to_string();
+	if exact.len() <= 8 {
+		return Some(exact);
+	}
+	(0..8).rev().find_map(|decimals| {
+		let s = format!("{:.*}", decimals, value);
+		let s = if s.contains('.') {
+			s.trim_end_matches('0').trim_end_matches('.')
+		} else {
+			&s
+		};
+		(s.len() <= 8 && s != "0" && s != "-0").then(|| s.to_string())
+	})
+}
+
+pub(crate) fn unrepresentable(field: &'static str, value: String) -> Error {
+	Error::new(ErrorKind::Unrepresentable { field, value })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use chrono::{NaiveDate, NaiveDateTime};
+
+	use super::{
+		blank_reserved, format_number, invert_polarity, scale_signal, set_physical_dimension,
+		set_start, HeaderEditor,
+	};
+	use crate::error::ErrorKind;
+	use crate::fixture::TestHeader;
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::Reader;
+
+	fn datetime(y: i32, m: u32, d: u32, h: u32) -> NaiveDateTime {
+		NaiveDate::from_ymd_opt(y, m, d)
+			.unwrap()
+			.and_hms_opt(h, 0, 0)
+			.unwrap()
+	}
+
+	#[test]
+	fn set_start_updates_startdate_subfield() {
+		let path = std::env::temp_dir().join(format!("edf-set-start-{}.edf", std::process::id()));
+		let header = TestHeader {
+			recording_id: "Startdate 02-MAR-2002 PSG-1234/2002 NN Telemetry03",
+			startdate: "02.03.02",
+			starttime: "13.00.00",
+			..TestHeader::default()
+		};
+		fs::write(&path, header.to_bytes()).unwrap();
+
+		set_start(&path, datetime(2024, 3, 1, 22)).unwrap();
+		let hdr = Reader::from_path(&path).unwrap().into_header();
+		let err = set_start(&path, datetime(2090, 1, 1, 0)).unwrap_err();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(hdr.start_datetime, datetime(2024, 3, 1, 22));
+		assert_eq!(
+			hdr.recording_id,
+			"Startdate 01-MAR-2024 PSG-1234/2002 NN Telemetry03"
+		);
+		assert_eq!(
+			err.to_string(),
+			"cannot write 2090-01-01 to the startdate field"
+		);
+	}
+
+	#[test]
+	fn set_start_after_2084() {
+		let path = std::env::temp_dir().join(format!("edf-set-yy-{}.edf", std::process::id()));
+		Generator::new()
+			.channel("EEG Fpz-Cz", 4, Pattern::Noise)
+			.annotation(0.5, "Lights off")
+			.to_path(&path)
+			.unwrap();
+
+		set_start(&path, datetime(2090, 1, 2, 3)).unwrap();
+		let hdr = Reader::from_path(&path).unwrap().into_header();
+		let bytes = fs::read(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(&bytes[168..176], b"02.01.yy");
+		assert_eq!(hdr.start_datetime, datetime(2090, 1, 2, 3));
+		assert!(hdr.recording_id.starts_with("Startdate 02-JAN-2090 "));
+	}
+
+	#[test]
+	fn set_physical_dimension_of_one_signal() {
+		let path = std::env::temp_dir().join(format!("edf-set-unit-{}.edf", std::process::id()));
+		Generator::new()
+			.channel("EEG Fpz-Cz", 4, Pattern::Noise)
+			.channel("EEG Pz-Oz", 4, Pattern::Noise)
+			.to_path(&path)
+			.unwrap();
+		set_physical_dimension(&path, 1, "mV").unwrap();
+		let hdr = Reader::from_path(&path).unwrap().into_header();
+		let err = set_physical_dimension(&path, 0, "microvolt").unwrap_err();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(hdr.signals[0].physical_dimension, "uV");
+		assert_eq!(hdr.signals[1].physical_dimension, "mV");
+		assert_eq!(
+			err.to_string(),
+			"cannot write microvolt to the physical dimension field"
+		);
+	}
+
+	#[test]
+	fn invert_and_scale() {
+		let path = std::env::temp_dir().join(format!("edf-invert-{}.edf", std::process::id()));
+		Generator::new()
+			.channel("EEG Fpz-Cz", 4, Pattern::Sine { frequency: 1.0 })
+			.channel("EEG Pz-Oz", 4, Pattern::Sine { frequency: 1.0 })
+			.to_path(&path)
+			.unwrap();
+		let before = Reader::from_path(&path).unwrap().read_signal(0).unwrap();
+		invert_polarity(&path, 0).unwrap();
+		scale_signal(&path, 1, 0.001).unwrap();
+		let zero = scale_signal(&path, 1, 0.0).unwrap_err();
+		let nan = scale_signal(&path, 1, f64::NAN).unwrap_err();
+		let mut reader = Reader::from_path(&path).unwrap();
+		let inverted = reader.read_signal(0).unwrap();
+		let scaled = reader.read_signal(1).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		let hdr = reader.header();
+		assert_eq!(hdr.signals[0].physical_min, 1000.0);
+		assert_eq!(hdr.signals[0].physical_max, -1000.0);
+		assert_eq!(hdr.signals[1].physical_max, 1.0);
+		assert_eq!(
+			zero.to_string(),
+			"cannot write 0 to the physical minimum field"
+		);
+		assert!(matches!(nan.kind(), ErrorKind::Unrepresentable { .. }));
+		for ((b, i), s) in before.iter().zip(&inverted).zip(&scaled) {
+			assert!((b + i).abs() < 1e-9);
+			assert!((b / 1000.0 - s).abs() < 1e-9);
+		}
+		assert_eq!(format_number(1.0 / 3.0).as_deref(), Some("0.333333"));
+		assert_eq!(format_number(-1234.5678).as_deref(), Some("-1234.57"));
+		assert_eq!(format_number(1e-9), None);
+		assert_eq!(format_number(1e12), None);
+	}
+
+	#[test]
+	fn blank_reserved_fields() {
+		let path = std::env::temp_dir().join(format!("edf-reserved-{}.edf", std::process::id()));
+		let mut bytes = Vec::new();
+		Generator::new()
+			.channel("EEG Fpz-Cz", 4, Pattern::Noise)
+			.annotation(0.5, "Lights off")
+			.write(&mut bytes)
+			.unwrap();
+		bytes[197..210].copy_from_slice(b" MRN 12345678");
+		// The reserved field of the first signal.
+		bytes[704..712].copy_from_slice(b"12345678");
+		fs::write(&path, &bytes).unwrap();
+
+		blank_reserved(&path).unwrap();
+		let hdr = Reader::from_path(&path).unwrap().into_header();
+		let blanked = fs::read(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(hdr.reserved, "EDF+C");
+		assert!(hdr.signals.iter().all(|s| s.reserved.is_empty()));
+		assert_eq!(&blanked[192..236], format!("{:<44}", "EDF+C").as_bytes());
+		assert_eq!(&blanked[704..768], [b' '; 64]);
+		assert_eq!(blanked[768..], bytes[768..]);
+	}
+
+	#[test]
+	fn list_changes_before_saving() {
+		let path = std::env::temp_dir().join(format!("edf-dry-run-{}.edf", std::process::id()));
+		Generator::new()
+			.channel("EEG Fpz-Cz", 4, Pattern::Noise)
+			.to_path(&path)
+			.unwrap();
+		let before = fs::read(&path).unwrap();
+		let mut editor = HeaderEditor::open(&path).unwrap();
+		editor.invert_polarity(0).unwrap();
+		editor.scale_signal(0, 2.0).unwrap();
+		editor.set_physical_dimension(0, "uV").unwrap();
+		let changes: Vec<String> = editor.changes().iter().map(|c| c.to_string()).collect();
+		let unchanged = fs::read(&path).unwrap() == before;
+		editor.save().unwrap();
+		let hdr = Reader::from_path(&path).unwrap().into_header();
+		fs::remove_file(&path).unwrap();
+
+		// Setting the unit it already has changes nothing.
+		assert_eq!(
+			changes,
+			[
+				r#"signal 0 physical minimum at byte 360: "-1000   " -> "2000    ""#,
+				r#"signal 0 physical maximum at byte 368: "1000    " -> "-2000   ""#,
+			]
+		);
+		assert!(unchanged);
+		assert_eq!(hdr.signals[0].physical_min, 2000.0);
+	}
+}
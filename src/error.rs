@@ -5,7 +5,9 @@ use std::result;
 use std::str;
 use std::string;
 
-use chrono::ParseError;
+use chrono::NaiveDate;
+
+use crate::sniff::Format;
 
 /// A type alias for `Result<T, edf::Error>`
 pub type Result<T> = result::Result<T, Error>;
@@ -19,6 +21,16 @@ impl Error {
 	pub(crate) fn new(kind: ErrorKind) -> Error {
 		Error(Box::new(kind))
 	}
+
+	/// Return the specific type of this error.
+	pub fn kind(&self) -> &ErrorKind {
+		&self.0
+	}
+
+	/// Unwrap this error into its underlying type.
+	pub fn into_kind(self) -> ErrorKind {
+		*self.0
+	}
 }
 
 /// The specific type of an error.
@@ -28,6 +40,60 @@ pub enum ErrorKind {
 	Io(io::Error),
 	Utf8(str::Utf8Error),
 	Header(HeaderError),
+	/// A line of a checksum manifest could not be parsed.
+	Manifest {
+		line: usize,
+	},
+	/// A line of an export state could not be parsed.
+	ExportState {
+		line: usize,
+	},
+	/// A line of a preview sidecar could not be parsed.
+	Preview {
+		line: usize,
+	},
+	/// A line of an annotation mapping could not be parsed.
+	Mapping {
+		line: usize,
+	},
+	/// A line of a WFDB header could not be parsed.
+	Wfdb {
+		line: usize,
+	},
+	/// An EDF+ annotation list could not be parsed.
+	Annotation {
+		/// The bytes of the time-stamped annotation list.
+		raw: Vec<u8>,
+	},
+	/// The file is in a format that cannot be read yet.
+	UnsupportedFormat(Format),
+	/// A value cannot be written to a header field, because it is out of
+	/// range or too long.
+	Unrepresentable {
+		field: &'static str,
+		value: String,
+	},
+	/// A stage of a pipeline cannot handle its input.
+	Pipeline {
+		/// The kind of stage, e.g. `montage`.
+		stage: &'static str,
+		message: String,
+	},
+	/// A montage profile could not be parsed.
+	#[cfg(feature = "profile")]
+	Profile(toml::de::Error),
+	/// A pipeline could not be parsed.
+	#[cfg(feature = "pipeline")]
+	PipelineConfig(toml::de::Error),
+	/// An error that occurred while encoding MessagePack.
+	#[cfg(feature = "msgpack")]
+	MsgPack(rmp_serde::encode::Error),
+	/// An error that occurred while encoding CBOR.
+	#[cfg(feature = "cbor")]
+	Cbor(ciborium::ser::Error<io::Error>),
+	/// An error that occurred while writing Parquet.
+	#[cfg(feature = "parquet")]
+	Parquet(parquet::errors::ParquetError),
 }
 
 impl From<io::Error> for Error {
@@ -48,6 +114,27 @@ impl From<string::FromUtf8Error> for Error {
 	}
 }
 
+#[cfg(feature = "msgpack")]
+impl From<rmp_serde::encode::Error> for Error {
+	fn from(err: rmp_serde::encode::Error) -> Error {
+		Error::new(ErrorKind::MsgPack(err))
+	}
+}
+
+#[cfg(feature = "cbor")]
+impl From<ciborium::ser::Error<io::Error>> for Error {
+	fn from(err: ciborium::ser::Error<io::Error>) -> Error {
+		Error::new(ErrorKind::Cbor(err))
+	}
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for Error {
+	fn from(err: parquet::errors::ParquetError) -> Error {
+		Error::new(ErrorKind::Parquet(err))
+	}
+}
+
 impl StdError for Error {}
 
 impl fmt::Display for Error {
@@ -56,20 +143,176 @@ impl fmt::Display for Error {
 			ErrorKind::Io(ref err) => err.fmt(f),
 			ErrorKind::Utf8(ref err) => err.fmt(f),
 			ErrorKind::Header(ref err) => err.fmt(f),
+			ErrorKind::Manifest { line } => {
+				write!(f, "malformed manifest entry on line {}", line)
+			}
+			ErrorKind::ExportState { line } => {
+				write!(f, "malformed export state on line {}", line)
+			}
+			ErrorKind::Preview { line } => {
+				write!(f, "malformed preview on line {}", line)
+			}
+			ErrorKind::Mapping { line } => {
+				write!(f, "malformed annotation mapping on line {}", line)
+			}
+			ErrorKind::Wfdb { line } => {
+				write!(f, "malformed WFDB header on line {}", line)
+			}
+			ErrorKind::Annotation { ref raw } => {
+				write!(f, "malformed annotation list: \"{}\"", raw.escape_ascii())
+			}
+			ErrorKind::UnsupportedFormat(format) => {
+				write!(f, "{} files are not supported", format)
+			}
+			ErrorKind::Unrepresentable { field, ref value } => {
+				write!(f, "cannot write {} to the {} field", value, field)
+			}
+			ErrorKind::Pipeline { stage, ref message } => write!(f, "{}: {}", stage, message),
+			#[cfg(feature = "profile")]
+			ErrorKind::Profile(ref err) => write!(f, "invalid profile: {}", err),
+			#[cfg(feature = "pipeline")]
+			ErrorKind::PipelineConfig(ref err) => write!(f, "invalid pipeline: {}", err),
+			#[cfg(feature = "msgpack")]
+			ErrorKind::MsgPack(ref err) => err.fmt(f),
+			#[cfg(feature = "cbor")]
+			ErrorKind::Cbor(ref err) => err.fmt(f),
+			#[cfg(feature = "parquet")]
+			ErrorKind::Parquet(ref err) => err.fmt(f),
 		}
 	}
 }
 
 /// An error that occured while reading the header.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HeaderError {
 	Version,
+	/// The startdate field is not a valid `dd.mm.yy` date.
+	InvalidDate {
+		/// The bytes of the field as they appear in the file.
+		raw: Vec<u8>,
+	},
+	/// The starttime field is not a valid `hh.mm.ss` time.
+	InvalidTime {
+		/// The bytes of the field as they appear in the file.
+		raw: Vec<u8>,
+	},
+	/// A numeric field could not be parsed.
+	InvalidNumber {
+		/// The name of the field, as in the EDF spec.
+		field: &'static str,
+		/// The bytes of the field as they appear in the file.
+		raw: Vec<u8>,
+		/// A description of the expected value, e.g. `an integer`.
+		expected: &'static str,
+	},
+	/// The number of data records is negative but not -1, which means
+	/// unknown.
+	NegativeRecordCount {
+		value: i64,
+	},
+	/// A text field contains bytes outside of ASCII.
+	InvalidText {
+		/// The name of the field, as in the EDF spec.
+		field: &'static str,
+		/// The bytes of the field as they appear in the file.
+		raw: Vec<u8>,
+	},
+	/// A subfield of the EDF+ patient or recording identification could not
+	/// be parsed.
+	InvalidField {
+		/// The name of the field, as in the EDF spec.
+		field: &'static str,
+		/// The bytes of the field as they appear in the file.
+		raw: Vec<u8>,
+		/// A description of the expected format, e.g. `dd.mm.yy`.
+		expected: &'static str,
+	},
+	/// The startdate field disagrees with the EDF+ startdate subfield of the
+	/// recording identification.
+	StartdateMismatch {
+		date: NaiveDate,
+		subfield: NaiveDate,
+	},
+	/// The number of bytes in the header does not match the number of
+	/// signals, which makes the header 256 bytes plus 256 bytes per signal.
+	SizeMismatch {
+		declared: usize,
+		expected: usize,
+	},
+	/// The data records are larger than the reader accepts, or their size
+	/// overflows.
+	RecordTooLarge {
+		/// The largest accepted data record, in bytes.
+		limit: usize,
+	},
+	/// An error in the header of a signal.
+	Signal {
+		/// The index of the signal.
+		index: usize,
+		err: Box<HeaderError>,
+	},
 }
 
 impl StdError for HeaderError {}
 
 impl fmt::Display for HeaderError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "invalid version")
+		match *self {
+			HeaderError::Version => write!(f, "invalid version"),
+			HeaderError::InvalidDate { ref raw } => write!(
+				f,
+				"invalid startdate: \"{}\" (expected dd.mm.yy)",
+				raw.escape_ascii()
+			),
+			HeaderError::InvalidTime { ref raw } => write!(
+				f,
+				"invalid starttime: \"{}\" (expected hh.mm.ss)",
+				raw.escape_ascii()
+			),
+			HeaderError::InvalidText { field, ref raw } => write!(
+				f,
+				"invalid {}: \"{}\" (expected ASCII text)",
+				field,
+				raw.escape_ascii()
+			),
+			HeaderError::NegativeRecordCount { value } => write!(
+				f,
+				"invalid number of data records: {} (expected a non-negative integer or -1)",
+				value
+			),
+			HeaderError::InvalidNumber {
+				field,
+				ref raw,
+				expected,
+			}
+			| HeaderError::InvalidField {
+				field,
+				ref raw,
+				expected,
+			} => write!(
+				f,
+				"invalid {}: \"{}\" (expected {})",
+				field,
+				raw.escape_ascii(),
+				expected
+			),
+			HeaderError::StartdateMismatch { date, subfield } => write!(
+				f,
+				"startdate {} does not match the startdate subfield {}",
+				date.format("%d.%m.%y"),
+				subfield.format("%d-%b-%Y").to_string().to_uppercase()
+			),
+			HeaderError::SizeMismatch { declared, expected } => write!(
+				f,
+				"header size of {} bytes does not match the {} bytes expected for {} signals",
+				declared,
+				expected,
+				expected / 256 - 1
+			),
+			HeaderError::RecordTooLarge { limit } => {
+				write!(f, "data records exceed the limit of {} bytes", limit)
+			}
+			HeaderError::Signal { index, ref err } => write!(f, "signal {}: {}", index, err),
+		}
 	}
 }
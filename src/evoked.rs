@@ -0,0 +1,127 @@
+//! Averaging of signal windows time-locked to annotations, as for evoked
+//! responses.
+
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::reader::Reader;
+
+/// The average of a signal over windows around matching annotations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evoked {
+	/// The averaged physical samples.
+	pub samples: Vec<f64>,
+	/// The time of the first sample relative to the events, in seconds.
+	pub start: f64,
+	/// The sampling rate in Hz.
+	pub rate: f64,
+	/// The number of events that were averaged.
+	pub events: usize,
+}
+
+impl Evoked {
+	/// The time of a sample relative to the events, in seconds.
+	pub fn time(&self, sample: usize) -> f64 {
+		self.start + sample as f64 / self.rate
+	}
+}
+
+/// Averages the windows of a signal around every annotation whose text
+/// contains the pattern.
+///
+/// The window is in seconds relative to the onset of each annotation, e.g.
+/// `-0.2..0.8`. If a baseline is given, also relative to the onset, the
+/// mean of each window over the baseline is subtracted before averaging.
+/// Events whose window does not lie within the recording or contains missing
+/// samples are skipped. If no event is averaged, the samples are NaN.
+///
+/// Times assume the data records are contiguous.
+///
+/// # Panics
+///
+/// Panics if there is no signal at the given index, or if the window or the
+/// baseline is empty.
+pub fn evoked_average<R: Read + Seek>(
+	reader: &mut Reader<R>,
+	signal: usize,
+	pattern: &str,
+	window: Range<f64>,
+	baseline: Option<Range<f64>>,
+) -> Result<Evoked> {
+	assert!(window.start < window.end, "empty window {:?}", window);
+	if let Some(ref baseline) = baseline {
+		assert!(
+			baseline.start < baseline.end,
+			"empty baseline {:?}",
+			baseline
+		);
+	}
+	let rate = reader.header().sampling_rate(signal).unwrap_or(0.0);
+	let samples = reader.read_signal(signal)?;
+	let annotations = reader.read_annotations()?;
+
+	let index = |seconds: f64| (seconds * rate).round() as i64;
+	let len = index(window.end - window.start).max(0) as usize;
+	let baseline = baseline.map(|b| {
+		let start = index(b.start - window.start).clamp(0, len as i64) as usize;
+		let end = index(b.end - window.start).clamp(0, len as i64) as usize;
+		start..end
+	});
+	let mut sum = vec![0.0; len];
+	let mut events = 0;
+	for annotation in annotations.iter().filter(|a| a.text.contains(pattern)) {
+		let start = index(annotation.onset + window.start);
+		let epoch = match usize::try_from(start)
+			.ok()
+			.and_then(|start| samples.get(start..start + len))
+		{
+			Some(epoch) if !epoch.iter().any(|s| s.is_nan()) => epoch,
+			_ => continue,
+		};
+		let offset = match baseline {
+			Some(ref b) if !b.is_empty() => epoch[b.clone()].iter().sum::<f64>() / b.len() as f64,
+			_ => 0.0,
+		};
+		for (sum, sample) in sum.iter_mut().zip(epoch) {
+			*sum += sample - offset;
+		}
+		events += 1;
+	}
+	Ok(Evoked {
+		samples: sum.into_iter().map(|s| s / events as f64).collect(),
+		start: window.start,
+		rate,
+		events,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::evoked_average;
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::Reader;
+
+	#[test]
+	fn average_around_spikes() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.channel("EEG Cz", 10, Pattern::Spikes { interval: 1.0 })
+			.annotation(1.0, "Stimulus A")
+			.annotation(3.0, "Stimulus B")
+			.annotation(5.5, "Response")
+			.annotation(9.9, "Stimulus C")
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let evoked =
+			evoked_average(&mut reader, 0, "Stimulus", -0.2..0.5, Some(-0.2..0.0)).unwrap();
+
+		// The last stimulus is too close to the end to be averaged.
+		assert_eq!(evoked.events, 2);
+		assert_eq!(evoked.samples.len(), 7);
+		assert_eq!(evoked.samples[2], 1000.0);
+		assert_eq!(evoked.samples[3], 0.0);
+		assert!(evoked.time(2).abs() < 1e-9);
+	}
+}
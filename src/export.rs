@@ -0,0 +1,418 @@
+//! Exports of EDF metadata to other formats.
+//!
+//! Every export is deterministic: the same header or annotations always give
+//! the same bytes, with fields in a fixed order and no time of export.
+
+use std::io::Write;
+
+use crate::annotation::Annotation;
+use crate::error::Result;
+use crate::reader::{format_seconds, Header};
+
+/// How long subtitles of annotations without a duration stay on screen, in
+/// seconds.
+const SUBTITLE_DURATION: f64 = 1.0;
+
+/// Writes the header and the annotations as XML.
+///
+/// The layout follows the one used by EDFbrowser's header export: a single
+/// `<edf_header>` element with one child per header field, named after the
+/// field in the EDF spec. It ends with an `<annotations>` element holding an
+/// `<annotation>` per annotation, with its `<onset>` and `<duration>` in
+/// seconds and its `<text>`. The duration is empty if there is none.
+pub fn write_xml<W: Write>(mut wtr: W, header: &Header, annotations: &[Annotation]) -> Result<()> {
+	let records_len = match header.records_len {
+		None => "-1".to_string(),
+		Some(v) => v.to_string(),
+	};
+	writeln!(wtr, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+	writeln!(wtr, "<edf_header>")?;
+	write_xml_element(&mut wtr, 2, "patient", &header.patient_info)?;
+	write_xml_element(&mut wtr, 2, "recording", &header.recording_id)?;
+	write_xml_element(
+		&mut wtr,
+		2,
+		"startdate",
+		&header.start_datetime.format("%d.%m.%y").to_string(),
+	)?;
+	write_xml_element(
+		&mut wtr,
+		2,
+		"starttime",
+		&header.start_datetime.format("%H.%M.%S").to_string(),
+	)?;
+	write_xml_element(&mut wtr, 2, "header_size", &header.size.to_string())?;
+	write_xml_element(&mut wtr, 2, "reserved", &header.reserved)?;
+	write_xml_element(&mut wtr, 2, "datarecords", &records_len)?;
+	write_xml_element(
+		&mut wtr,
+		2,
+		"datarecord_duration",
+		&format_seconds(header.duration),
+	)?;
+	write_xml_element(&mut wtr, 2, "signals", &header.signals_len.to_string())?;
+	for (i, signal) in header.signals.iter().enumerate() {
+		writeln!(wtr, "  <signal index=\"{}\">", i)?;
+		write_xml_element(&mut wtr, 4, "label", &signal.label)?;
+		write_xml_element(&mut wtr, 4, "transducer", &signal.transducer)?;
+		write_xml_element(
+			&mut wtr,
+			4,
+			"physical_dimension",
+			&signal.physical_dimension,
+		)?;
+		write_xml_element(
+			&mut wtr,
+			4,
+			"physical_minimum",
+			&signal.physical_min.to_string(),
+		)?;
+		write_xml_element(
+			&mut wtr,
+			4,
+			"physical_maximum",
+			&signal.physical_max.to_string(),
+		)?;
+		write_xml_element(
+			&mut wtr,
+			4,
+			"digital_minimum",
+			&signal.digital_min.to_string(),
+		)?;
+		write_xml_element(
+			&mut wtr,
+			4,
+			"digital_maximum",
+			&signal.digital_max.to_string(),
+		)?;
+		write_xml_element(&mut wtr, 4, "prefiltering", &signal.prefiltering)?;
+		write_xml_element(
+			&mut wtr,
+			4,
+			"samples_per_datarecord",
+			&signal.samples_per_record.to_string(),
+		)?;
+		writeln!(wtr, "  </signal>")?;
+	}
+	writeln!(wtr, "  <annotations>")?;
+	for annotation in annotations {
+		writeln!(wtr, "    <annotation>")?;
+		write_xml_element(&mut wtr, 6, "onset", &annotation.onset.to_string())?;
+		let duration = annotation
+			.duration
+			.map(|d| d.to_string())
+			.unwrap_or_default();
+		write_xml_element(&mut wtr, 6, "duration", &duration)?;
+		write_xml_element(&mut wtr, 6, "text", &annotation.text)?;
+		writeln!(wtr, "    </annotation>")?;
+	}
+	writeln!(wtr, "  </annotations>")?;
+	writeln!(wtr, "</edf_header>")?;
+	Ok(())
+}
+
+/// Writes a single element indented by `indent` spaces, escaping its text
+/// content.
+fn write_xml_element<W: Write>(wtr: &mut W, indent: usize, name: &str, text: &str) -> Result<()> {
+	writeln!(wtr, "{:indent$}<{name}>{}</{name}>", "", escape_xml(text))?;
+	Ok(())
+}
+
+/// Escapes the characters that cannot appear literally in XML text.
+///
+/// Control characters other than tab, line feed and carriage return cannot
+/// appear in XML 1.0 at all, even escaped, so they are replaced with U+FFFD,
+/// as are the noncharacters U+FFFE and U+FFFF.
+fn escape_xml(s: &str) -> String {
+	let mut escaped = String::with_capacity(s.len());
+	for c in s.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&apos;"),
+			'\t' | '\n' | '\r' => escaped.push(c),
+			'\0'..='\x1f' | '\u{fffe}' | '\u{ffff}' => escaped.push(char::REPLACEMENT_CHARACTER),
+			_ => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+/// Writes annotations as an Audacity label track.
+///
+/// Each line holds the start and end in seconds and the text, separated by
+/// tabs. Annotations without a duration become point labels.
+pub fn write_audacity_labels<W: Write>(mut wtr: W, annotations: &[Annotation]) -> Result<()> {
+	for annotation in annotations {
+		let end = annotation.onset + annotation.duration.unwrap_or(0.0);
+		writeln!(
+			wtr,
+			"{:.6}\t{:.6}\t{}",
+			annotation.onset,
+			end,
+			single_line(&annotation.text)
+		)?;
+	}
+	Ok(())
+}
+
+/// Writes annotations as SubRip (SRT) subtitles, timed from the start of the
+/// recording.
+///
+/// Annotations without a duration are shown for one second.
+pub fn write_srt<W: Write>(mut wtr: W, annotations: &[Annotation]) -> Result<()> {
+	for (i, annotation) in annotations.iter().enumerate() {
+		let (start, end) = subtitle_span(annotation);
+		writeln!(
+			wtr,
+			"{}\n{} --> {}\n{}\n",
+			i + 1,
+			subtitle_time(start, ','),
+			subtitle_time(end, ','),
+			single_line(&annotation.text)
+		)?;
+	}
+	Ok(())
+}
+
+/// Writes annotations as WebVTT subtitles, timed from the start of the
+/// recording.
+///
+/// Annotations without a duration are shown for one second.
+pub fn write_vtt<W: Write>(mut wtr: W, annotations: &[Annotation]) -> Result<()> {
+	writeln!(wtr, "WEBVTT\n")?;
+	for annotation in annotations {
+		let (start, end) = subtitle_span(annotation);
+		writeln!(
+			wtr,
+			"{} --> {}\n{}\n",
+			subtitle_time(start, '.'),
+			subtitle_time(end, '.'),
+			// A cue must not contain the arrow, so break it up.
+			single_line(&annotation.text).replace("-->", "->")
+		)?;
+	}
+	Ok(())
+}
+
+/// The start and end of the subtitle of an annotation, in seconds.
+fn subtitle_span(annotation: &Annotation) -> (f64, f64) {
+	let start = annotation.onset.max(0.0);
+	let duration = match annotation.duration {
+		Some(duration) if duration > 0.0 => duration,
+		_ => SUBTITLE_DURATION,
+	};
+	(start, start + duration)
+}
+
+/// Formats seconds as `hh:mm:ss` followed by the separator and milliseconds.
+fn subtitle_time(seconds: f64, separator: char) -> String {
+	let millis = (seconds * 1000.0).round() as u64;
+	format!(
+		"{:02}:{:02}:{:02}{}{:03}",
+		millis / 3_600_000,
+		millis / 60_000 % 60,
+		millis / 1000 % 60,
+		separator,
+		millis % 1000
+	)
+}
+
+/// Replaces line breaks, which end a label or cue early.
+fn single_line(text: &str) -> String {
+	text.replace(['\r', '\n'], " ")
+}
+
+/// The metadata written by the binary exports: a map with the header under
+/// `header` and the annotations under `annotations`.
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+#[derive(serde::Serialize)]
+struct Metadata<'a> {
+	header: &'a Header,
+	annotations: &'a [Annotation],
+}
+
+/// Writes the header and the annotations as MessagePack.
+///
+/// Structs are encoded as maps so that consumers can look fields up by name.
+#[cfg(feature = "msgpack")]
+pub fn write_msgpack<W: Write>(
+	mut wtr: W,
+	header: &Header,
+	annotations: &[Annotation],
+) -> Result<()> {
+	let mut ser = rmp_serde::Serializer::new(&mut wtr).with_struct_map();
+	let metadata = Metadata {
+		header,
+		annotations,
+	};
+	serde::Serialize::serialize(&metadata, &mut ser)?;
+	Ok(())
+}
+
+/// Writes the header and the annotations as CBOR, laid out as by
+/// `write_msgpack`.
+#[cfg(feature = "cbor")]
+pub fn write_cbor<W: Write>(wtr: W, header: &Header, annotations: &[Annotation]) -> Result<()> {
+	let metadata = Metadata {
+		header,
+		annotations,
+	};
+	ciborium::ser::into_writer(&metadata, wtr)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use chrono::{NaiveDate, NaiveTime};
+
+	use crate::annotation::Annotation;
+	use crate::reader::{FileFormat, Header};
+
+	fn header() -> Header {
+		Header::new(
+			FileFormat::Edf,
+			String::from("MCH-0234567 F 02-MAY-1951 Haagse_Harry"),
+			String::from("Startdate 02-MAR-2002 PSG-1234/2002 NN Telemetry03"),
+			NaiveDate::from_ymd_opt(2002, 3, 2).unwrap(),
+			NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+			768,
+			String::new(),
+			Some(10),
+			Duration::from_secs(30),
+			2,
+		)
+	}
+
+	#[test]
+	fn xml_escapes_text() {
+		assert_eq!(
+			super::escape_xml("a<b & \"c\""),
+			"a&lt;b &amp; &quot;c&quot;"
+		);
+		assert_eq!(
+			super::escape_xml("a\x14b\0\tc\n"),
+			"a\u{fffd}b\u{fffd}\tc\n"
+		);
+	}
+
+	#[test]
+	fn xml_header() {
+		let annotations = [
+			Annotation {
+				onset: 3725.5,
+				duration: None,
+				text: "Lights off".to_string(),
+			},
+			Annotation {
+				onset: 3800.0,
+				duration: Some(25.25),
+				text: "Apnea & arousal".to_string(),
+			},
+		];
+		let mut buf = Vec::new();
+		super::write_xml(&mut buf, &header(), &annotations).unwrap();
+		let xml = String::from_utf8(buf).unwrap();
+		assert!(xml.contains("  <startdate>02.03.02</startdate>\n"));
+		assert!(xml.contains("  <datarecords>10</datarecords>\n"));
+		assert!(xml.ends_with(
+			"  <annotations>
+    <annotation>
+      <onset>3725.5</onset>
+      <duration></duration>
+      <text>Lights off</text>
+    </annotation>
+    <annotation>
+      <onset>3800</onset>
+      <duration>25.25</duration>
+      <text>Apnea &amp; arousal</text>
+    </annotation>
+  </annotations>
+</edf_header>
+"
+		));
+	}
+
+	#[test]
+	fn annotation_timelines() {
+		let annotations = [
+			Annotation {
+				onset: 3725.5,
+				duration: None,
+				text: "Lights off".to_string(),
+			},
+			Annotation {
+				onset: 3800.0,
+				duration: Some(25.25),
+				text: "Apnea\nobstructive".to_string(),
+			},
+		];
+		let (mut labels, mut srt, mut vtt) = (Vec::new(), Vec::new(), Vec::new());
+		super::write_audacity_labels(&mut labels, &annotations).unwrap();
+		super::write_srt(&mut srt, &annotations).unwrap();
+		super::write_vtt(&mut vtt, &annotations).unwrap();
+
+		assert_eq!(
+			String::from_utf8(labels).unwrap(),
+			"3725.500000\t3725.500000\tLights off\n3800.000000\t3825.250000\tApnea obstructive\n"
+		);
+		assert_eq!(
+			String::from_utf8(srt).unwrap(),
+			"1\n01:02:05,500 --> 01:02:06,500\nLights off\n\n2\n01:03:20,000 --> 01:03:45,250\nApnea obstructive\n\n"
+		);
+		assert!(String::from_utf8(vtt)
+			.unwrap()
+			.starts_with("WEBVTT\n\n01:02:05.500 --> 01:02:06.500\nLights off\n\n"));
+	}
+
+	/// The metadata of the binary exports, as a consumer would decode it.
+	#[cfg(any(feature = "msgpack", feature = "cbor"))]
+	#[derive(Debug, PartialEq, serde::Deserialize)]
+	struct Metadata {
+		header: Header,
+		annotations: Vec<Annotation>,
+	}
+
+	#[cfg(any(feature = "msgpack", feature = "cbor"))]
+	fn metadata() -> Metadata {
+		Metadata {
+			header: header(),
+			annotations: vec![
+				Annotation {
+					onset: 0.0,
+					duration: None,
+					text: "Lights off".to_string(),
+				},
+				Annotation {
+					onset: 3800.0,
+					duration: Some(25.25),
+					text: "Apnea".to_string(),
+				},
+			],
+		}
+	}
+
+	#[cfg(feature = "msgpack")]
+	#[test]
+	fn msgpack_round_trip() {
+		let metadata = metadata();
+		let mut buf = Vec::new();
+		super::write_msgpack(&mut buf, &metadata.header, &metadata.annotations).unwrap();
+		let decoded: Metadata = rmp_serde::from_slice(&buf).unwrap();
+		assert_eq!(decoded, metadata);
+	}
+
+	#[cfg(feature = "cbor")]
+	#[test]
+	fn cbor_round_trip() {
+		let metadata = metadata();
+		let mut buf = Vec::new();
+		super::write_cbor(&mut buf, &metadata.header, &metadata.annotations).unwrap();
+		let decoded: Metadata = ciborium::de::from_reader(buf.as_slice()).unwrap();
+		assert_eq!(decoded, metadata);
+	}
+}
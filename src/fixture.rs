@@ -0,0 +1,114 @@
+//! Hand-written headers for tests that need fields `Generator` would not
+//! write, such as malformed or unusual ones.
+
+/// The fields of a header, written as given and padded to their widths.
+///
+/// The number of signals follows from `signals`, as does the header size
+/// unless `size` is set. The reserved field of each signal is left blank.
+pub(crate) struct TestHeader<'a> {
+	pub(crate) version: &'a str,
+	pub(crate) patient_info: &'a str,
+	pub(crate) recording_id: &'a str,
+	pub(crate) startdate: &'a str,
+	pub(crate) starttime: &'a str,
+	pub(crate) size: Option<&'a str>,
+	pub(crate) reserved: &'a str,
+	pub(crate) records_len: &'a str,
+	pub(crate) duration: &'a str,
+	pub(crate) signals: Vec<TestSignal<'a>>,
+}
+
+/// The fields of a signal header, written as given.
+pub(crate) struct TestSignal<'a> {
+	pub(crate) label: &'a str,
+	pub(crate) transducer: &'a str,
+	pub(crate) physical_dimension: &'a str,
+	pub(crate) physical_min: &'a str,
+	pub(crate) physical_max: &'a str,
+	pub(crate) digital_min: &'a str,
+	pub(crate) digital_max: &'a str,
+	pub(crate) prefiltering: &'a str,
+	pub(crate) samples_per_record: &'a str,
+}
+
+impl Default for TestHeader<'_> {
+	fn default() -> Self {
+		TestHeader {
+			version: "0",
+			patient_info: "X X X X",
+			recording_id: "Startdate X X X X",
+			startdate: "01.03.21",
+			starttime: "22.00.00",
+			size: None,
+			reserved: "",
+			records_len: "0",
+			duration: "1",
+			signals: Vec::new(),
+		}
+	}
+}
+
+impl Default for TestSignal<'_> {
+	fn default() -> Self {
+		TestSignal {
+			label: "EEG",
+			transducer: "",
+			physical_dimension: "uV",
+			physical_min: "-1",
+			physical_max: "1",
+			digital_min: "-32768",
+			digital_max: "32767",
+			prefiltering: "",
+			samples_per_record: "1",
+		}
+	}
+}
+
+impl TestHeader<'_> {
+	/// Writes the header.
+	pub(crate) fn to_bytes(&self) -> Vec<u8> {
+		let size = (256 * (self.signals.len() + 1)).to_string();
+		let mut bytes = format!(
+			"{:<8}{:<80}{:<80}{:<8}{:<8}{:<8}{:<44}{:<8}{:<8}{:<4}",
+			self.version,
+			self.patient_info,
+			self.recording_id,
+			self.startdate,
+			self.starttime,
+			self.size.unwrap_or(&size),
+			self.reserved,
+			self.records_len,
+			self.duration,
+			self.signals.len()
+		)
+		.into_bytes();
+		let widths = [16, 80, 8, 8, 8, 8, 8, 80, 8, 32];
+		for (i, width) in widths.into_iter().enumerate() {
+			for signal in &self.signals {
+				bytes.extend(format!("{:<width$}", signal.field(i), width = width).bytes());
+			}
+		}
+		bytes
+	}
+}
+
+impl TestSignal<'_> {
+	/// The field at the given position in the layout of the header, blank for
+	/// the reserved field.
+	fn field(&self, i: usize) -> &str {
+		[
+			self.label,
+			self.transducer,
+			self.physical_dimension,
+			self.physical_min,
+			self.physical_max,
+			self.digital_min,
+			self.digital_max,
+			self.prefiltering,
+			self.samples_per_record,
+		]
+		.get(i)
+		.copied()
+		.unwrap_or("")
+	}
+}
@@ -0,0 +1,465 @@
+//! Synthetic recordings for testing viewers, pipelines and this crate.
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::crc::write_record_crcs;
+use crate::error::{Error, ErrorKind, Result};
+use crate::reader::format_seconds;
+
+/// The label of the EDF+ annotation signal.
+const ANNOTATIONS_LABEL: &str = "EDF Annotations";
+
+/// The waveform of a generated signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+	/// A sine wave of the given frequency in Hz, spanning the physical range.
+	Sine { frequency: f64 },
+	/// Uniform white noise over the physical range.
+	Noise,
+	/// A flat line with a full-scale spike every `interval` seconds.
+	Spikes { interval: f64 },
+}
+
+/// A deliberate defect in a generated file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+	/// The last data record is cut short. Without data records, this has no
+	/// effect.
+	TruncatedRecord,
+	/// The startdate field is not a valid date.
+	InvalidStartDate,
+	/// The header claims one more data record than the file contains.
+	RecordCountMismatch,
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+	label: String,
+	samples_per_record: usize,
+	pattern: Pattern,
+}
+
+/// Builds a synthetic EDF recording.
+///
+/// Signals are written with a physical range of -1000 to 1000 uV over a
+/// digital range of -32767 to 32767, so that zero is exactly zero. If any
+/// annotations are added, the file is written as EDF+C with an
+/// `EDF Annotations` signal.
+///
+/// The output is byte-for-byte deterministic: the same settings always give
+/// the same file, so generated files can be content-hashed. Nothing about
+/// the time or machine of generation is written.
+#[derive(Debug, Clone)]
+pub struct Generator {
+	start: NaiveDateTime,
+	records_len: usize,
+	duration: Duration,
+	channels: Vec<Channel>,
+	annotations: Vec<(f64, String)>,
+	annotation_signals: usize,
+	corruptions: Vec<Corruption>,
+	seed: u64,
+	crc_sidecar: bool,
+}
+
+impl Default for Generator {
+	fn default() -> Generator {
+		Generator {
+			start: NaiveDate::from_ymd_opt(2002, 3, 2)
+				.unwrap()
+				.and_hms_opt(13, 0, 0)
+				.unwrap(),
+			records_len: 10,
+			duration: Duration::from_secs(1),
+			channels: Vec::new(),
+			annotations: Vec::new(),
+			annotation_signals: 1,
+			corruptions: Vec::new(),
+			seed: 1,
+			crc_sidecar: false,
+		}
+	}
+}
+
+impl Generator {
+	/// Create a new generator for a recording of 10 one-second data records
+	/// without signals.
+	pub fn new() -> Generator {
+		Generator::default()
+	}
+
+	/// The start date and time of the recording.
+	pub fn start(&mut self, start: NaiveDateTime) -> &mut Generator {
+		self.start = start;
+		self
+	}
+
+	/// The number of data records.
+	pub fn records(&mut self, records_len: usize) -> &mut Generator {
+		self.records_len = records_len;
+		self
+	}
+
+	/// The duration of a data record, 1 second by default.
+	pub fn duration(&mut self, duration: Duration) -> &mut Generator {
+		self.duration = duration;
+		self
+	}
+
+	/// Adds a signal with the given number of samples in each data record.
+	pub fn channel(
+		&mut self,
+		label: &str,
+		samples_per_record: usize,
+		pattern: Pattern,
+	) -> &mut Generator {
+		self.channels.push(Channel {
+			label: label.to_string(),
+			samples_per_record,
+			pattern,
+		});
+		self
+	}
+
+	/// Adds an annotation at the given onset in seconds from the start.
+	pub fn annotation(&mut self, onset: f64, text: &str) -> &mut Generator {
+		self.annotations.push((onset, text.to_string()));
+		self
+	}
+
+	/// The number of annotation signals the annotations are spread over, in
+	/// turn. The first also holds the time-keeping TALs. This is 1 by
+	/// default.
+	///
+	/// # Panics
+	///
+	/// Panics if the number is zero.
+	pub fn annotation_signals(&mut self, signals_len: usize) -> &mut Generator {
+		assert!(signals_len > 0, "no annotation signals");
+		self.annotation_signals = signals_len;
+		self
+	}
+
+	/// Introduces a defect into the generated file.
+	pub fn corrupt(&mut self, corruption: Corruption) -> &mut Generator {
+		self.corruptions.push(corruption);
+		self
+	}
+
+	/// The seed of the noise generator. The same seed gives the same noise.
+	pub fn seed(&mut self, seed: u64) -> &mut Generator {
+		self.seed = seed;
+		self
+	}
+
+	/// Whether `to_path` also writes the CRC-32 of each data record to a
+	/// sidecar, named after the file with `.crc` appended.
+	///
+	/// The sidecar lets a copy be verified record by record with
+	/// `Reader::verify_records`. This is disabled by default.
+	pub fn crc_sidecar(&mut self, yes: bool) -> &mut Generator {
+		self.crc_sidecar = yes;
+		self
+	}
+
+	/// Writes the recording to a file.
+	pub fn to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+		let path = path.as_ref();
+		let (header, data, record_size) = self.encode()?;
+		let mut wtr = BufWriter::new(File::create(path)?);
+		wtr.write_all(&header)?;
+		wtr.write_all(&data)?;
+		wtr.flush()?;
+		if self.crc_sidecar {
+			let mut sidecar = path.as_os_str().to_owned();
+			sidecar.push(".crc");
+			let mut wtr = BufWriter::new(File::create(sidecar)?);
+			write_record_crcs(&mut wtr, &data, record_size)?;
+			wtr.flush()?;
+		}
+		Ok(())
+	}
+
+	/// Writes the recording.
+	pub fn write<W: Write>(&self, mut wtr: W) -> Result<()> {
+		let (header, data, _) = self.encode()?;
+		wtr.write_all(&header)?;
+		wtr.write_all(&data)?;
+		Ok(())
+	}
+
+	/// Encodes the header and the data records, returning both with the size
+	/// of a data record.
+	fn encode(&self) -> Result<(Vec<u8>, Vec<u8>, usize)> {
+		let mut wtr = Vec::new();
+		let annotations = self.annotation_records()?;
+		let mut signals: Vec<(&str, &str, usize)> = self
+			.channels
+			.iter()
+			.map(|c| (c.label.as_str(), "uV", c.samples_per_record))
+			.collect();
+		for records in &annotations {
+			signals.push((ANNOTATIONS_LABEL, "", records[0].len() / 2));
+		}
+		let has = |c| self.corruptions.contains(&c);
+
+		let start_date = if has(Corruption::InvalidStartDate) {
+			"32.13.02".to_string()
+		} else {
+			self.start.format("%d.%m.%y").to_string()
+		};
+		let records_len = self.records_len + has(Corruption::RecordCountMismatch) as usize;
+		let startdate = self.start.format("%d-%b-%Y").to_string().to_uppercase();
+		write!(wtr, "{:<8}", "0")?;
+		write!(wtr, "{:<80}", "X X X Synthetic")?;
+		write_field(
+			&mut wtr,
+			&format!("Startdate {} X X edf-generator", startdate),
+			80,
+		)?;
+		write!(wtr, "{:<8}", start_date)?;
+		write!(wtr, "{:<8}", self.start.format("%H.%M.%S"))?;
+		write_field(&mut wtr, &(256 * (signals.len() + 1)).to_string(), 8)?;
+		write!(
+			wtr,
+			"{:<44}",
+			if annotations.is_empty() { "" } else { "EDF+C" }
+		)?;
+		write_field(&mut wtr, &records_len.to_string(), 8)?;
+		write_field(&mut wtr, &format_seconds(self.duration), 8)?;
+		write_field(&mut wtr, &signals.len().to_string(), 4)?;
+		for &(label, _, _) in &signals {
+			write_field(&mut wtr, label, 16)?;
+		}
+		for _ in &signals {
+			write!(wtr, "{:<80}", "")?;
+		}
+		for &(_, unit, _) in &signals {
+			write!(wtr, "{:<8}", unit)?;
+		}
+		for &(label, _, _) in &signals {
+			let annotation = label == ANNOTATIONS_LABEL;
+			write!(wtr, "{:<8}", if annotation { "-1" } else { "-1000" })?;
+		}
+		for &(label, _, _) in &signals {
+			let annotation = label == ANNOTATIONS_LABEL;
+			write!(wtr, "{:<8}", if annotation { "1" } else { "1000" })?;
+		}
+		for _ in &signals {
+			write!(wtr, "{:<8}", -i16::MAX)?;
+		}
+		for _ in &signals {
+			write!(wtr, "{:<8}", i16::MAX)?;
+		}
+		for _ in &signals {
+			write!(wtr, "{:<80}", "")?;
+		}
+		for &(_, _, samples_per_record) in &signals {
+			write_field(&mut wtr, &samples_per_record.to_string(), 8)?;
+		}
+		for _ in &signals {
+			write!(wtr, "{:<32}", "")?;
+		}
+
+		let duration = self.duration.as_secs_f64();
+		let mut noise = XorShift(self.seed.max(1));
+		let mut data = Vec::new();
+		for i in 0..self.records_len {
+			for channel in &self.channels {
+				for k in 0..channel.samples_per_record {
+					let t = (i * channel.samples_per_record + k) as f64
+						/ (channel.samples_per_record as f64 / duration);
+					let value = match channel.pattern {
+						Pattern::Sine { frequency } => (2.0 * PI * frequency * t).sin(),
+						Pattern::Noise => noise.next_f64() * 2.0 - 1.0,
+						Pattern::Spikes { interval } => {
+							let step = duration / channel.samples_per_record as f64;
+							if t % interval < step {
+								1.0
+							} else {
+								0.0
+							}
+						}
+					};
+					let sample = (value * i16::MAX as f64).round() as i16;
+					data.extend_from_slice(&sample.to_le_bytes());
+				}
+			}
+			for records in &annotations {
+				data.extend_from_slice(&records[i]);
+			}
+		}
+		let record_size = signals.iter().map(|s| s.2).sum::<usize>() * 2;
+		if has(Corruption::TruncatedRecord) {
+			// Without records there is nothing to cut short.
+			data.truncate(data.len().saturating_sub(record_size / 2));
+		}
+		Ok((wtr, data, record_size))
+	}
+
+	/// Encodes the time-stamped annotation lists of each data record, for
+	/// each annotation signal.
+	///
+	/// Returns no signals if there are no annotations.
+	fn annotation_records(&self) -> Result<Vec<Vec<Vec<u8>>>> {
+		if self.annotations.is_empty() {
+			return Ok(Vec::new());
+		}
+		let duration = self.duration.as_secs_f64();
+		let end = self.records_len as f64 * duration;
+		if let Some((onset, _)) = self
+			.annotations
+			.iter()
+			.find(|(onset, _)| !(0.0..end).contains(onset))
+		{
+			return Err(Error::new(ErrorKind::Unrepresentable {
+				field: "annotation onset",
+				value: onset.to_string(),
+			}));
+		}
+		let mut signals: Vec<Vec<Vec<u8>>> = (0..self.annotation_signals)
+			.map(|s| {
+				(0..self.records_len)
+					.map(|i| match s {
+						0 => {
+							let onset = self.duration * i as u32;
+							format!("+{}\x14\x14\0", format_seconds(onset)).into_bytes()
+						}
+						_ => Vec::new(),
+					})
+					.collect()
+			})
+			.collect();
+		for (i, (onset, text)) in self.annotations.iter().enumerate() {
+			let record = &mut signals[i % self.annotation_signals][(onset / duration) as usize];
+			record.extend(format!("+{}\x14{}\x14\0", onset, text).bytes());
+		}
+		// Every record holds at least an empty annotation list, and the signal
+		// needs a whole number of 2-byte samples.
+		for records in &mut signals {
+			let len = records.iter().map(Vec::len).max().unwrap_or(0).max(1);
+			let len = len + len % 2;
+			for record in records.iter_mut() {
+				record.resize(len, 0);
+			}
+		}
+		Ok(signals)
+	}
+}
+
+/// Writes a header field, failing if the value does not fit.
+fn write_field<W: Write>(wtr: &mut W, value: &str, width: usize) -> io::Result<()> {
+	if value.len() > width {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("\"{}\" does not fit in {} bytes", value, width),
+		));
+	}
+	write!(wtr, "{:<width$}", value, width = width)
+}
+
+/// A small, fast pseudorandom number generator, good enough for noise.
+struct XorShift(u64);
+
+impl XorShift {
+	/// Returns a number in `[0, 1)`.
+	fn next_f64(&mut self) -> f64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		(self.0 >> 11) as f64 / (1u64 << 53) as f64
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::io;
+
+	use super::{Corruption, Generator, Pattern};
+	use crate::crc::crc32;
+	use crate::error::ErrorKind;
+	use crate::reader::Reader;
+
+	#[test]
+	fn generate_and_read_back() {
+		let path = std::env::temp_dir().join(format!("edf-generate-{}.edf", std::process::id()));
+		let mut generator = Generator::new();
+		generator
+			.records(3)
+			.channel("Sine", 4, Pattern::Sine { frequency: 1.0 })
+			.channel("Noise", 2, Pattern::Noise)
+			.annotation(1.5, "Lights off");
+		generator.to_path(&path).unwrap();
+		let mut reader = Reader::from_path(&path).unwrap();
+		let records = reader.read_records().unwrap();
+		let annotations = reader.read_annotations().unwrap();
+		generator
+			.corrupt(Corruption::TruncatedRecord)
+			.to_path(&path)
+			.unwrap();
+		let err = Reader::from_path(&path)
+			.unwrap()
+			.read_records()
+			.unwrap_err();
+		fs::remove_file(&path).unwrap();
+
+		let hdr = reader.header();
+		assert_eq!(hdr.reserved, "EDF+C");
+		assert_eq!(hdr.records_len, Some(3));
+		assert_eq!(hdr.signals[2].label, "EDF Annotations");
+		assert_eq!(records.len(), 3);
+		assert_eq!(records[0].samples(0), Some(&[0, 32767, 0, -32767][..]));
+		let tals: Vec<u8> = records[1]
+			.samples(2)
+			.unwrap()
+			.iter()
+			.flat_map(|&s| (s as i16).to_le_bytes())
+			.collect();
+		assert!(tals.starts_with(b"+1\x14\x14\0+1.5\x14Lights off\x14\0"));
+		assert_eq!(annotations.len(), 1);
+		assert_eq!(annotations[0].onset, 1.5);
+		assert_eq!(annotations[0].text, "Lights off");
+		assert!(matches!(err.kind(), ErrorKind::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+	}
+
+	#[test]
+	fn deterministic_output() {
+		let generate = || {
+			let mut bytes = Vec::new();
+			Generator::new()
+				.channel("Noise", 7, Pattern::Noise)
+				.annotation(0.1 + 0.2, "Lights off")
+				.seed(42)
+				.write(&mut bytes)
+				.unwrap();
+			bytes
+		};
+		let bytes = generate();
+		let hdr = Reader::from_bytes(&bytes).unwrap().into_header();
+
+		assert_eq!(generate(), bytes);
+		// Any change to the output of the same settings changes this, and so
+		// the hashes of previously generated files.
+		assert_eq!(crc32(&bytes), 0x4b20_2c99);
+		assert_eq!(hdr.signals[0].label, "Noise");
+	}
+
+	#[test]
+	fn truncate_without_records() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(0)
+			.channel("Noise", 1, Pattern::Noise)
+			.corrupt(Corruption::TruncatedRecord)
+			.write(&mut bytes)
+			.unwrap();
+		assert_eq!(bytes.len(), 512);
+	}
+}
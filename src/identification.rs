@@ -0,0 +1,458 @@
+//! The subfields of the EDF+ local patient and recording identification.
+//!
+//! EDF+ splits both 80-byte identification fields into space-separated
+//! subfields. Unknown subfields are written as `X`.
+
+use std::fmt;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorKind, HeaderError, Result};
+
+/// The date format of the birthdate and startdate subfields, e.g. `02-MAY-1951`.
+const DATE_FORMAT: &str = "%d-%b-%Y";
+
+/// The English month abbreviations used in date subfields.
+pub(crate) const MONTHS: [&str; 12] = [
+	"JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+/// The local patient identification of an EDF+ file.
+///
+/// Each subfield is `None` if it is unknown (value is `X`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatientId {
+	/// The hospital administration code of the patient.
+	pub code: Option<String>,
+	pub sex: Option<Sex>,
+	pub birthdate: Option<NaiveDate>,
+	/// The name of the patient, with spaces replaced by underscores.
+	pub name: Option<String>,
+	/// Any subfields following the name.
+	pub additional: Vec<String>,
+}
+
+impl PatientId {
+	/// Parses the local patient identification field.
+	pub fn parse(s: &str) -> Result<PatientId> {
+		let mut subfields = s.split_whitespace();
+		let (code, sex, birthdate, name) = match (
+			subfields.next(),
+			subfields.next(),
+			subfields.next(),
+			subfields.next(),
+		) {
+			(Some(code), Some(sex), Some(birthdate), Some(name)) => (code, sex, birthdate, name),
+			_ => {
+				return Err(invalid_field(
+					"local patient identification",
+					s,
+					"code, sex, birthdate and name subfields",
+				))
+			}
+		};
+		Ok(PatientId {
+			code: parse_text(code),
+			sex: parse_sex(sex)?,
+			birthdate: parse_date(birthdate, "birthdate")?,
+			name: parse_text(name),
+			additional: subfields.map(String::from).collect(),
+		})
+	}
+
+	/// Returns the age of the patient in whole years on the given date.
+	///
+	/// Returns `None` if the birthdate is unknown or after the date.
+	pub fn age_at(&self, date: NaiveDate) -> Option<u32> {
+		let birthdate = self.birthdate?;
+		let mut years = date.year() - birthdate.year();
+		if (date.month(), date.day()) < (birthdate.month(), birthdate.day()) {
+			years -= 1;
+		}
+		u32::try_from(years).ok()
+	}
+}
+
+impl fmt::Display for PatientId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} {} {} {}",
+			Subfield(self.code.as_ref()),
+			Subfield(self.sex.as_ref()),
+			DateSubfield(self.birthdate),
+			Subfield(self.name.as_ref())
+		)?;
+		for subfield in &self.additional {
+			write!(f, " {}", subfield)?;
+		}
+		Ok(())
+	}
+}
+
+/// The local patient identification of a file, structured if it follows
+/// EDF+.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PatientInfo {
+	/// The EDF+ subfields.
+	Structured(PatientId),
+	/// The field as free text, trimmed, for plain EDF files or fields that
+	/// do not follow EDF+.
+	Raw(String),
+}
+
+impl PatientInfo {
+	/// Parses the local patient identification field, falling back to the raw
+	/// text if it does not follow EDF+.
+	pub fn parse(s: &str) -> PatientInfo {
+		match PatientId::parse(s) {
+			Ok(id) => PatientInfo::Structured(id),
+			Err(_) => PatientInfo::Raw(s.trim().to_string()),
+		}
+	}
+
+	/// Returns the EDF+ subfields, if the field follows EDF+.
+	pub fn structured(&self) -> Option<&PatientId> {
+		match *self {
+			PatientInfo::Structured(ref id) => Some(id),
+			PatientInfo::Raw(_) => None,
+		}
+	}
+}
+
+impl fmt::Display for PatientInfo {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			PatientInfo::Structured(ref id) => id.fmt(f),
+			PatientInfo::Raw(ref s) => s.fmt(f),
+		}
+	}
+}
+
+/// The sex of the patient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sex {
+	Female,
+	Male,
+}
+
+impl fmt::Display for Sex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Sex::Female => write!(f, "F"),
+			Sex::Male => write!(f, "M"),
+		}
+	}
+}
+
+/// The local recording identification of an EDF+ file.
+///
+/// Each subfield is `None` if it is unknown (value is `X`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordingId {
+	pub startdate: Option<NaiveDate>,
+	/// The hospital administration code of the investigation.
+	pub admin_code: Option<String>,
+	/// The code of the responsible technician.
+	pub technician: Option<String>,
+	/// The code of the equipment used.
+	pub equipment: Option<String>,
+	/// Any subfields following the equipment code.
+	pub additional: Vec<String>,
+}
+
+impl RecordingId {
+	/// Parses the local recording identification field.
+	pub fn parse(s: &str) -> Result<RecordingId> {
+		let mut subfields = s.split_whitespace();
+		let (startdate, admin_code, technician, equipment) = match (
+			subfields.next(),
+			subfields.next(),
+			subfields.next(),
+			subfields.next(),
+			subfields.next(),
+		) {
+			(Some("Startdate"), Some(startdate), Some(admin_code), Some(technician), Some(equipment)) => {
+				(startdate, admin_code, technician, equipment)
+			}
+			_ => {
+				return Err(invalid_field(
+					"local recording identification",
+					s,
+					"Startdate followed by startdate, administration code, technician and equipment subfields",
+				))
+			}
+		};
+		Ok(RecordingId {
+			startdate: parse_date(startdate, "startdate subfield")?,
+			admin_code: parse_text(admin_code),
+			technician: parse_text(technician),
+			equipment: parse_text(equipment),
+			additional: subfields.map(String::from).collect(),
+		})
+	}
+}
+
+impl fmt::Display for RecordingId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"Startdate {} {} {} {}",
+			DateSubfield(self.startdate),
+			Subfield(self.admin_code.as_ref()),
+			Subfield(self.technician.as_ref()),
+			Subfield(self.equipment.as_ref())
+		)?;
+		for subfield in &self.additional {
+			write!(f, " {}", subfield)?;
+		}
+		Ok(())
+	}
+}
+
+/// The local recording identification of a file, structured if it follows
+/// EDF+.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordingInfo {
+	/// The EDF+ subfields.
+	Structured(RecordingId),
+	/// The field as free text, trimmed, for plain EDF files or fields that
+	/// do not follow EDF+.
+	Raw(String),
+}
+
+impl RecordingInfo {
+	/// Parses the local recording identification field, falling back to the
+	/// raw text if it does not follow EDF+.
+	pub fn parse(s: &str) -> RecordingInfo {
+		match RecordingId::parse(s) {
+			Ok(id) => RecordingInfo::Structured(id),
+			Err(_) => RecordingInfo::Raw(s.trim().to_string()),
+		}
+	}
+
+	/// Returns the EDF+ subfields, if the field follows EDF+.
+	pub fn structured(&self) -> Option<&RecordingId> {
+		match *self {
+			RecordingInfo::Structured(ref id) => Some(id),
+			RecordingInfo::Raw(_) => None,
+		}
+	}
+}
+
+impl fmt::Display for RecordingInfo {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RecordingInfo::Structured(ref id) => id.fmt(f),
+			RecordingInfo::Raw(ref s) => s.fmt(f),
+		}
+	}
+}
+
+/// Parses a free-text subfield, mapping `X` to `None`.
+fn parse_text(s: &str) -> Option<String> {
+	if s == "X" {
+		None
+	} else {
+		Some(s.to_string())
+	}
+}
+
+/// Parses the sex subfield, mapping `X` to `None`.
+fn parse_sex(s: &str) -> Result<Option<Sex>> {
+	match s {
+		"X" => Ok(None),
+		"F" => Ok(Some(Sex::Female)),
+		"M" => Ok(Some(Sex::Male)),
+		_ => Err(invalid_field("sex", s, "F, M or X")),
+	}
+}
+
+/// Parses a date subfield, mapping `X` to `None`.
+///
+/// Month names are matched case-insensitively, since some recorders write
+/// `02-May-1951` rather than `02-MAY-1951`.
+fn parse_date(s: &str, field: &'static str) -> Result<Option<NaiveDate>> {
+	if s == "X" {
+		return Ok(None);
+	}
+	parse_month_name_date(s)
+		.map(Some)
+		.ok_or_else(|| invalid_field(field, s, "dd-MMM-yyyy (e.g. 02-MAY-1951) or X"))
+}
+
+fn parse_month_name_date(s: &str) -> Option<NaiveDate> {
+	let mut parts = s.split('-');
+	let (day, month, year) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+		(Some(day), Some(month), Some(year), None) => (day, month, year),
+		_ => return None,
+	};
+	if day.len() != 2 || year.len() != 4 {
+		return None;
+	}
+	let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))?;
+	let day = day.parse().ok()?;
+	let year = year.parse().ok()?;
+	NaiveDate::from_ymd_opt(year, month as u32 + 1, day)
+}
+
+fn invalid_field(field: &'static str, raw: &str, expected: &'static str) -> Error {
+	Error::new(ErrorKind::Header(HeaderError::InvalidField {
+		field,
+		raw: raw.as_bytes().to_vec(),
+		expected,
+	}))
+}
+
+/// Displays a subfield, writing `X` if it is unknown.
+struct Subfield<T>(Option<T>);
+
+impl<T: fmt::Display> fmt::Display for Subfield<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.0 {
+			None => write!(f, "X"),
+			Some(ref v) => v.fmt(f),
+		}
+	}
+}
+
+/// Displays a date subfield, writing `X` if the date is unknown.
+struct DateSubfield(Option<NaiveDate>);
+
+impl fmt::Display for DateSubfield {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.0 {
+			None => write!(f, "X"),
+			Some(date) => write!(f, "{}", date.format(DATE_FORMAT).to_string().to_uppercase()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::NaiveDate;
+
+	use super::{PatientId, PatientInfo, RecordingId, RecordingInfo, Sex};
+
+	#[test]
+	fn parse_patient_id() {
+		let id = PatientId::parse("MCH-0234567 F 02-MAY-1951 Haagse_Harry").unwrap();
+		assert_eq!(id.birthdate, NaiveDate::from_ymd_opt(1951, 5, 2));
+		assert_eq!(id.sex, Some(Sex::Female));
+		assert_eq!(id.name.as_deref(), Some("Haagse_Harry"));
+		assert_eq!(id.to_string(), "MCH-0234567 F 02-MAY-1951 Haagse_Harry");
+	}
+
+	#[test]
+	fn parse_unknown_dates() {
+		let id = PatientId::parse("X X X X").unwrap();
+		assert_eq!(
+			id,
+			PatientId {
+				code: None,
+				sex: None,
+				birthdate: None,
+				name: None,
+				additional: Vec::new(),
+			}
+		);
+		assert_eq!(id.to_string(), "X X X X");
+
+		let id = RecordingId::parse("Startdate X X X X").unwrap();
+		assert_eq!(
+			id,
+			RecordingId {
+				startdate: None,
+				admin_code: None,
+				technician: None,
+				equipment: None,
+				additional: Vec::new(),
+			}
+		);
+		assert_eq!(id.to_string(), "Startdate X X X X");
+	}
+
+	#[test]
+	fn parse_recording_id() {
+		let s = "Startdate 02-MAR-2002 PSG-1234/2002 NN Telemetry03";
+		let id = RecordingId::parse(s).unwrap();
+		assert_eq!(id.startdate, NaiveDate::from_ymd_opt(2002, 3, 2));
+		assert_eq!(id.technician.as_deref(), Some("NN"));
+		assert_eq!(id.equipment.as_deref(), Some("Telemetry03"));
+		assert_eq!(id.to_string(), s);
+	}
+
+	#[test]
+	fn parse_date_case_insensitive() {
+		let id = PatientId::parse("X X 02-May-1951 X").unwrap();
+		assert_eq!(id.birthdate, NaiveDate::from_ymd_opt(1951, 5, 2));
+		assert_eq!(id.to_string(), "X X 02-MAY-1951 X");
+	}
+
+	#[test]
+	fn parse_invalid_dates() {
+		for s in [
+			"31-FEB-1951",
+			"02-MAI-1951",
+			"2-MAY-1951",
+			"02-MAY-51",
+			"02.05.1951",
+		] {
+			let err = PatientId::parse(&format!("X X {} X", s)).unwrap_err();
+			assert_eq!(
+				err.to_string(),
+				format!(
+					"invalid birthdate: \"{}\" (expected dd-MMM-yyyy (e.g. 02-MAY-1951) or X)",
+					s
+				)
+			);
+		}
+	}
+
+	#[test]
+	fn age_at() {
+		let id = PatientId::parse("X X 02-MAY-1951 X").unwrap();
+		let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+		assert_eq!(id.age_at(date(2002, 5, 1)), Some(50));
+		assert_eq!(id.age_at(date(2002, 5, 2)), Some(51));
+		assert_eq!(id.age_at(date(1950, 1, 1)), None);
+		assert_eq!(
+			PatientId::parse("X X X X")
+				.unwrap()
+				.age_at(date(2002, 5, 2)),
+			None
+		);
+	}
+
+	#[test]
+	fn parse_plain_edf() {
+		assert!(RecordingId::parse("Some free text recording").is_err());
+		assert!(PatientId::parse("Anonymous").is_err());
+		assert!(PatientId::parse("MCH-0234567 female 02-MAY-1951 Haagse_Harry").is_err());
+	}
+
+	#[test]
+	fn patient_info_falls_back_to_raw() {
+		let info = PatientInfo::parse("MCH-0234567 F X X  ");
+		assert_eq!(
+			info.structured().and_then(|id| id.code.as_deref()),
+			Some("MCH-0234567")
+		);
+		let info = PatientInfo::parse("Anonymous patient   ");
+		assert_eq!(info, PatientInfo::Raw("Anonymous patient".to_string()));
+		assert_eq!(info.structured(), None);
+	}
+
+	#[test]
+	fn recording_info_falls_back_to_raw() {
+		let info = RecordingInfo::parse("Startdate 02-MAR-2002 PSG-1234/2002 NN Telemetry03  ");
+		assert_eq!(
+			info.structured().and_then(|id| id.admin_code.as_deref()),
+			Some("PSG-1234/2002")
+		);
+		let info = RecordingInfo::parse("Overnight PSG   ");
+		assert_eq!(info, RecordingInfo::Raw("Overnight PSG".to_string()));
+		assert_eq!(info.to_string(), "Overnight PSG");
+	}
+}
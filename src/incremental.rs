@@ -0,0 +1,283 @@
+//! State files that let repeated exports of a growing archive skip the
+//! recordings that have not changed since the last run.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::crc::crc32_update;
+use crate::error::{Error, ErrorKind, Result};
+
+/// What was known about each file when it was last exported.
+///
+/// A file needs exporting if it is new, or if its size or modification
+/// time differ from the last export and its contents do too. Comparing
+/// contents only when the cheap checks fail means an unchanged archive is
+/// checked without reading any file, and a file that was merely touched or
+/// copied is not exported again.
+///
+/// The state is written as text, one file per line: the CRC-32 of the file
+/// as 8 hex digits, its size in bytes, its modification time in seconds
+/// since the Unix epoch and its path, separated by tabs. Paths are escaped
+/// like Rust byte strings, so that tabs, line breaks and bytes that are not
+/// ASCII survive the round trip.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportState {
+	files: BTreeMap<PathBuf, Snapshot>,
+}
+
+/// The size, modification time and CRC-32 of a file, taken by
+/// `ExportState::needs_export` before the file is exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+	crc: u32,
+	len: u64,
+	modified: Duration,
+}
+
+impl ExportState {
+	/// Creates an empty state, under which every file needs exporting.
+	pub fn new() -> ExportState {
+		ExportState::default()
+	}
+
+	/// Reads a state written by `write`. Blank lines are skipped.
+	pub fn read<R: BufRead>(rdr: R) -> Result<ExportState> {
+		let mut files = BTreeMap::new();
+		for (i, line) in rdr.lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+			let mut fields = line.splitn(4, '\t');
+			let entry = (|| {
+				let crc = u32::from_str_radix(fields.next()?, 16).ok()?;
+				let len = fields.next()?.parse().ok()?;
+				let (secs, nanos) = fields.next()?.split_once('.')?;
+				let modified = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+				let path = path_from_bytes(unescape(fields.next()?)?)?;
+				Some((path, Snapshot { crc, len, modified }))
+			})();
+			let (path, state) =
+				entry.ok_or_else(|| Error::new(ErrorKind::ExportState { line: i + 1 }))?;
+			files.insert(path, state);
+		}
+		Ok(ExportState { files })
+	}
+
+	/// Writes the state, sorted by path.
+	///
+	/// On platforms other than Unix, paths that are not Unicode cannot be
+	/// written.
+	pub fn write<W: Write>(&self, mut wtr: W) -> Result<()> {
+		for (path, state) in &self.files {
+			let bytes = path_bytes(path).ok_or_else(|| {
+				Error::new(ErrorKind::Unrepresentable {
+					field: "path",
+					value: path.display().to_string(),
+				})
+			})?;
+			writeln!(
+				wtr,
+				"{:08x}\t{}\t{}.{:09}\t{}",
+				state.crc,
+				state.len,
+				state.modified.as_secs(),
+				state.modified.subsec_nanos(),
+				bytes.escape_ascii()
+			)?;
+		}
+		wtr.flush()?;
+		Ok(())
+	}
+
+	/// Checks whether the file at the given path is new or has changed since
+	/// it was last marked as exported, returning a snapshot of it if so.
+	///
+	/// The snapshot is to be passed to `mark_exported` once the export has
+	/// succeeded. It is taken before the file is exported and its CRC covers
+	/// only the bytes up to its size, so bytes appended to a growing file
+	/// during the export count as a change on the next run.
+	///
+	/// If only the size and modification time of the file have changed but
+	/// its contents have not, the new size and time are kept so that the
+	/// file is not read again next time.
+	pub fn needs_export<P: AsRef<Path>>(&mut self, path: P) -> Result<Option<Snapshot>> {
+		let path = path.as_ref();
+		let known = self.files.get(path).copied();
+		let (len, modified) = metadata(path)?;
+		if let Some(known) = known {
+			if (len, modified) == (known.len, known.modified) {
+				return Ok(None);
+			}
+		}
+		let snapshot = Snapshot {
+			crc: file_crc(path, len)?,
+			len,
+			modified,
+		};
+		match known {
+			Some(known) if known.crc == snapshot.crc && known.len == len => {
+				self.files.insert(path.to_path_buf(), snapshot);
+				Ok(None)
+			}
+			_ => Ok(Some(snapshot)),
+		}
+	}
+
+	/// Records a snapshot of the file at the given path, as returned by
+	/// `needs_export`, after the file has been exported.
+	pub fn mark_exported<P: AsRef<Path>>(&mut self, path: P, snapshot: Snapshot) {
+		self.files.insert(path.as_ref().to_path_buf(), snapshot);
+	}
+}
+
+/// Returns the size of a file and its modification time since the Unix
+/// epoch.
+fn metadata(path: &Path) -> Result<(u64, Duration)> {
+	let metadata = fs::metadata(path)?;
+	let modified = metadata
+		.modified()?
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default();
+	Ok((metadata.len(), modified))
+}
+
+/// Returns the bytes of a path.
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Option<&[u8]> {
+	use std::os::unix::ffi::OsStrExt;
+	Some(path.as_os_str().as_bytes())
+}
+
+/// Returns the bytes of a path, if it is Unicode.
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Option<&[u8]> {
+	path.to_str().map(str::as_bytes)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+	use std::os::unix::ffi::OsStringExt;
+	Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+	String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Reverses `escape_ascii`, returning `None` for an invalid escape.
+fn unescape(s: &str) -> Option<Vec<u8>> {
+	let mut bytes = Vec::with_capacity(s.len());
+	let mut rest = s.as_bytes();
+	while let Some((&b, tail)) = rest.split_first() {
+		rest = tail;
+		if b != b'\\' {
+			bytes.push(b);
+			continue;
+		}
+		let (&escape, tail) = rest.split_first()?;
+		rest = tail;
+		bytes.push(match escape {
+			b't' => b'\t',
+			b'r' => b'\r',
+			b'n' => b'\n',
+			b'\\' | b'\'' | b'"' => escape,
+			b'x' => {
+				let hex = str::from_utf8(rest.get(..2)?).ok()?;
+				rest = &rest[2..];
+				u8::from_str_radix(hex, 16).ok()?
+			}
+			_ => return None,
+		});
+	}
+	Some(bytes)
+}
+
+/// Computes the CRC-32 of the first `len` bytes of a file, reading it in
+/// chunks.
+fn file_crc(path: &Path, len: u64) -> Result<u32> {
+	let mut f = File::open(path)?.take(len);
+	let mut buffer = vec![0; 64 * 1024];
+	let mut crc = 0;
+	loop {
+		match f.read(&mut buffer)? {
+			0 => return Ok(crc),
+			n => crc = crc32_update(crc, &buffer[..n]),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::io::Write;
+
+	use super::ExportState;
+
+	#[test]
+	fn skip_unchanged_files() {
+		let path = std::env::temp_dir().join(format!("edf-incremental-{}.edf", std::process::id()));
+		fs::write(&path, b"first").unwrap();
+		let mut state = ExportState::new();
+		let new = state.needs_export(&path).unwrap();
+		state.mark_exported(&path, new.unwrap());
+		let unchanged = state.needs_export(&path).unwrap();
+		let mut text = Vec::new();
+		state.write(&mut text).unwrap();
+		let mut read = ExportState::read(text.as_slice()).unwrap();
+		let round_trip = read == state;
+		// Rewriting the same contents changes only the modification time.
+		fs::write(&path, b"first").unwrap();
+		let rewritten = read.needs_export(&path).unwrap();
+		fs::write(&path, b"second").unwrap();
+		let modified = read.needs_export(&path).unwrap();
+		// Bytes appended while the file is exported are not marked exported.
+		let mut appended = fs::OpenOptions::new().append(true).open(&path).unwrap();
+		appended.write_all(b" record").unwrap();
+		read.mark_exported(&path, modified.unwrap());
+		let grown = read.needs_export(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		assert!(new.is_some());
+		assert!(unchanged.is_none());
+		assert!(round_trip);
+		assert!(rewritten.is_none());
+		assert!(grown.is_some());
+		assert!(ExportState::read(&b"zz\t5\t0.0\tx.edf\n"[..]).is_err());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn escape_paths() {
+		use std::ffi::OsStr;
+		use std::os::unix::ffi::OsStrExt;
+		use std::path::PathBuf;
+		use std::time::Duration;
+
+		use super::Snapshot;
+
+		let path = PathBuf::from(OsStr::from_bytes(b"night\t1\n\\\xff.edf"));
+		let file = Snapshot {
+			crc: 0xcbf4_3926,
+			len: 9,
+			modified: Duration::new(1, 5),
+		};
+		let state = ExportState {
+			files: [(path, file)].into_iter().collect(),
+		};
+		let mut text = Vec::new();
+		state.write(&mut text).unwrap();
+
+		assert_eq!(
+			text,
+			b"cbf43926\t9\t1.000000005\tnight\\t1\\n\\\\\\xff.edf\n"
+		);
+		assert_eq!(ExportState::read(text.as_slice()).unwrap(), state);
+		assert!(ExportState::read(&b"cbf43926\t9\t1.0\tx\\q.edf\n"[..]).is_err());
+	}
+}
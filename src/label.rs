@@ -0,0 +1,156 @@
+//! The EDF+ convention for signal labels: a signal type followed by a
+//! specification, e.g. `EEG Fpz-Cz` or `Temp rectal`.
+
+use std::fmt;
+
+use crate::signal::SignalHeader;
+
+/// The type of a signal, as given by the first word of its label.
+///
+/// The standard types are those of the EDF+ spec. Labels of other types are
+/// kept as written.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SignalKind {
+	Eeg,
+	Ecg,
+	Eog,
+	Erg,
+	Emg,
+	Meg,
+	/// Magnetocardiogram.
+	Mcg,
+	/// Evoked potential.
+	Ep,
+	/// Temperature.
+	Temp,
+	/// Respiration.
+	Resp,
+	/// Oxygen saturation.
+	SaO2,
+	Light,
+	Sound,
+	Event,
+	Freq,
+	Dist,
+	Area,
+	Vol,
+	Dur,
+	Vel,
+	Mass,
+	Angle,
+	/// A percentage, written `%`.
+	Percent,
+	Value,
+	/// A type that is not standard, such as `SpO2` or `Pleth`.
+	Other(String),
+}
+
+/// The standard types and how the spec writes them.
+const KINDS: [(&str, SignalKind); 24] = [
+	("EEG", SignalKind::Eeg),
+	("ECG", SignalKind::Ecg),
+	("EOG", SignalKind::Eog),
+	("ERG", SignalKind::Erg),
+	("EMG", SignalKind::Emg),
+	("MEG", SignalKind::Meg),
+	("MCG", SignalKind::Mcg),
+	("EP", SignalKind::Ep),
+	("Temp", SignalKind::Temp),
+	("Resp", SignalKind::Resp),
+	("SaO2", SignalKind::SaO2),
+	("Light", SignalKind::Light),
+	("Sound", SignalKind::Sound),
+	("Event", SignalKind::Event),
+	("Freq", SignalKind::Freq),
+	("Dist", SignalKind::Dist),
+	("Area", SignalKind::Area),
+	("Vol", SignalKind::Vol),
+	("Dur", SignalKind::Dur),
+	("Vel", SignalKind::Vel),
+	("Mass", SignalKind::Mass),
+	("Angle", SignalKind::Angle),
+	("%", SignalKind::Percent),
+	("Value", SignalKind::Value),
+];
+
+impl fmt::Display for SignalKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			SignalKind::Other(ref kind) => kind.fmt(f),
+			ref kind => {
+				let (name, _) = KINDS.iter().find(|(_, k)| k == kind).unwrap();
+				name.fmt(f)
+			}
+		}
+	}
+}
+
+/// A signal label split into its type and specification.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignalLabel {
+	pub kind: SignalKind,
+	/// The electrode derivation or sensor, e.g. `Fpz-Cz` or `rectal`, or
+	/// `None` if the label is only a type, like `ECG`.
+	pub specification: Option<String>,
+}
+
+impl SignalLabel {
+	/// Splits a label at its first space.
+	///
+	/// Standard types are matched case-insensitively, since some recorders
+	/// write `Eeg` or `eeg`.
+	pub fn parse(label: &str) -> SignalLabel {
+		let label = label.trim();
+		let (kind, specification) = match label.split_once(' ') {
+			Some((kind, specification)) => (kind, Some(specification.trim_start())),
+			None => (label, None),
+		};
+		let kind = KINDS
+			.iter()
+			.find(|(name, _)| name.eq_ignore_ascii_case(kind))
+			.map_or_else(|| SignalKind::Other(kind.to_string()), |(_, k)| k.clone());
+		SignalLabel {
+			kind,
+			specification: specification.filter(|s| !s.is_empty()).map(String::from),
+		}
+	}
+}
+
+impl SignalHeader {
+	/// The type of the signal, from its label. Filtering on this finds, for
+	/// example, all EEG signals however their derivations are written.
+	pub fn kind(&self) -> SignalKind {
+		SignalLabel::parse(&self.label).kind
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{SignalKind, SignalLabel};
+
+	#[test]
+	fn parse_labels() {
+		for (label, kind, specification) in [
+			("EEG Fpz-Cz", SignalKind::Eeg, Some("Fpz-Cz")),
+			("ECG", SignalKind::Ecg, None),
+			("Temp rectal ", SignalKind::Temp, Some("rectal")),
+			("eeg C3-M2", SignalKind::Eeg, Some("C3-M2")),
+			("% SaO2", SignalKind::Percent, Some("SaO2")),
+			(
+				"SpO2 finger",
+				SignalKind::Other("SpO2".to_string()),
+				Some("finger"),
+			),
+		] {
+			let parsed = SignalLabel::parse(label);
+			assert_eq!(parsed.kind, kind, "{:?}", label);
+			assert_eq!(
+				parsed.specification.as_deref(),
+				specification,
+				"{:?}",
+				label
+			);
+		}
+		assert_eq!(SignalKind::SaO2.to_string(), "SaO2");
+	}
+}
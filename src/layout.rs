@@ -0,0 +1,187 @@
+//! The byte layout of a file, for hex-level debugging and patch tools.
+
+use crate::reader::Header;
+
+/// The fixed part of the header, as field names and widths in bytes.
+const FIXED_FIELDS: [(&str, u64); 10] = [
+	("version", 8),
+	("local patient identification", 80),
+	("local recording identification", 80),
+	("startdate", 8),
+	("starttime", 8),
+	("number of bytes in header", 8),
+	("reserved", 44),
+	("number of data records", 8),
+	("duration of a data record", 8),
+	("number of signals", 4),
+];
+
+/// The per-signal part of the header. Each field is stored for all signals
+/// before the next field starts.
+const SIGNAL_FIELDS: [(&str, u64); 10] = [
+	("label", 16),
+	("transducer type", 80),
+	("physical dimension", 8),
+	("physical minimum", 8),
+	("physical maximum", 8),
+	("digital minimum", 8),
+	("digital maximum", 8),
+	("prefiltering", 80),
+	("number of samples in each data record", 8),
+	("reserved", 32),
+];
+
+/// A contiguous range of bytes in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+	/// What the bytes hold, e.g. `signal 2 label` or `record 0 signal 1`.
+	pub name: String,
+	/// The offset of the first byte from the start of the file.
+	pub offset: u64,
+	/// The number of bytes.
+	pub len: u64,
+}
+
+/// Lists the byte ranges of every header field, data record and signal
+/// block within a data record, in file order.
+///
+/// The header size is taken from the header rather than derived from the
+/// number of signals, so the records are where a reader would look for
+/// them. Records are only listed if their number is known or could be
+/// inferred from the size of the file.
+pub fn offsets(hdr: &Header) -> Vec<Region> {
+	let mut regions: Vec<Region> = header_fields(hdr.signals.len())
+		.map(|(name, signal, offset, len)| Region {
+			name: field_name(name, signal),
+			offset,
+			len,
+		})
+		.collect();
+
+	let bytes_per_sample = hdr.format.bytes_per_sample() as u64;
+	let record_size = hdr.record_size().unwrap_or(0) as u64;
+	let records_len = if hdr.record_size().is_some() {
+		hdr.effective_records_len().unwrap_or(0) as u64
+	} else {
+		0
+	};
+	for r in 0..records_len {
+		let start = hdr.size as u64 + r * record_size;
+		regions.push(Region {
+			name: format!("record {}", r),
+			offset: start,
+			len: record_size,
+		});
+		let mut offset = start;
+		for (i, signal) in hdr.signals.iter().enumerate() {
+			let len = signal.samples_per_record as u64 * bytes_per_sample;
+			regions.push(Region {
+				name: format!("record {} signal {}", r, i),
+				offset,
+				len,
+			});
+			offset += len;
+		}
+	}
+	regions
+}
+
+/// Lists the header fields in file order, as their name in the spec, the
+/// index of the signal for signal fields, their offset and their length.
+///
+/// Unlike `offsets`, this formats no names, so it is cheap to scan.
+pub(crate) fn header_fields(
+	signals_len: usize,
+) -> impl Iterator<Item = (&'static str, Option<usize>, u64, u64)> {
+	let fixed = FIXED_FIELDS.iter().map(|&(name, len)| (name, None, len));
+	let signal = SIGNAL_FIELDS
+		.iter()
+		.flat_map(move |&(name, len)| (0..signals_len).map(move |i| (name, Some(i), len)));
+	let mut offset = 0;
+	fixed.chain(signal).map(move |(name, signal, len)| {
+		let field = (name, signal, offset, len);
+		offset += len;
+		field
+	})
+}
+
+/// The name of a header field as listed by `offsets`, e.g. `startdate` or
+/// `signal 2 label`.
+pub(crate) fn field_name(name: &str, signal: Option<usize>) -> String {
+	match signal {
+		Some(signal) => format!("signal {} {}", signal, name),
+		None => name.to_string(),
+	}
+}
+
+/// The offset of a header field, by its name in the spec. Signal fields are
+/// given with the index of the signal.
+pub(crate) fn field_offset(signals_len: usize, name: &str, signal: Option<usize>) -> Option<u64> {
+	let fixed_size: u64 = FIXED_FIELDS.iter().map(|(_, len)| len).sum();
+	match signal {
+		None => {
+			let i = FIXED_FIELDS.iter().position(|&(n, _)| n == name)?;
+			Some(FIXED_FIELDS[..i].iter().map(|(_, len)| len).sum())
+		}
+		Some(signal) => {
+			let i = SIGNAL_FIELDS.iter().position(|&(n, _)| n == name)?;
+			let before: u64 = SIGNAL_FIELDS[..i].iter().map(|(_, len)| len).sum();
+			Some(fixed_size + before * signals_len as u64 + SIGNAL_FIELDS[i].1 * signal as u64)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use chrono::NaiveDate;
+
+	use super::{offsets, Region};
+	use crate::reader::{FileFormat, Header};
+	use crate::signal::SignalHeader;
+
+	#[test]
+	fn offsets_of_bdf() {
+		let mut hdr = Header::new(
+			FileFormat::Bdf,
+			String::new(),
+			String::new(),
+			NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(),
+			Default::default(),
+			768,
+			String::new(),
+			Some(2),
+			Duration::from_secs(1),
+			2,
+		);
+		for samples_per_record in [4, 2] {
+			hdr.signals.push(SignalHeader {
+				label: String::new(),
+				transducer: String::new(),
+				physical_dimension: String::new(),
+				physical_min: -1.0,
+				physical_max: 1.0,
+				digital_min: -1,
+				digital_max: 1,
+				prefiltering: String::new(),
+				samples_per_record,
+				reserved: String::new(),
+			});
+		}
+
+		let regions = offsets(&hdr);
+		let region = |name: &str| regions.iter().find(|r| r.name == name).unwrap().clone();
+		let r = |name: &str, offset, len| Region {
+			name: name.to_string(),
+			offset,
+			len,
+		};
+		assert_eq!(region("startdate"), r("startdate", 168, 8));
+		assert_eq!(region("signal 1 label"), r("signal 1 label", 272, 16));
+		assert_eq!(region("signal 1 reserved"), r("signal 1 reserved", 736, 32));
+		assert_eq!(region("record 1"), r("record 1", 786, 18));
+		assert_eq!(region("record 1 signal 1"), r("record 1 signal 1", 798, 6));
+		assert_eq!(regions.len(), 10 + 20 + 2 * 3);
+	}
+}
@@ -1,5 +1,103 @@
-pub use crate::error::{Error, ErrorKind};
-pub use crate::reader::Reader;
+pub use crate::annotation::{parse_tals, write_annotation_file, Annotation, AnnotationList};
+pub use crate::calibration::{calibration, Calibration};
+pub use crate::catalog::{catalog, CatalogEntry};
+#[cfg(feature = "checksum")]
+pub use crate::checksum::{
+	data_digest, read_manifest, record_digests, signal_digests, verify_file, write_manifest,
+	ManifestEntry,
+};
+pub use crate::crc::{read_record_crcs, write_record_crcs};
+pub use crate::edit::{
+	blank_reserved, invert_polarity, scale_signal, set_physical_dimension, set_start, FieldChange,
+	HeaderEditor,
+};
+pub use crate::error::{Error, ErrorKind, HeaderError, Result};
+#[cfg(feature = "analysis")]
+pub use crate::evoked::{evoked_average, Evoked};
+#[cfg(feature = "cbor")]
+pub use crate::export::write_cbor;
+#[cfg(feature = "msgpack")]
+pub use crate::export::write_msgpack;
+#[cfg(feature = "export")]
+pub use crate::export::{write_audacity_labels, write_srt, write_vtt, write_xml};
+pub use crate::generator::{Corruption, Generator, Pattern};
+pub use crate::identification::{PatientId, PatientInfo, RecordingId, RecordingInfo, Sex};
+pub use crate::incremental::{ExportState, Snapshot};
+pub use crate::label::{SignalKind, SignalLabel};
+pub use crate::layout::{offsets, Region};
+pub use crate::load::{load_all, Channel};
+pub use crate::mapping::AnnotationMap;
+pub use crate::missing::{sample_stats, signal_stats, SampleStats, Sentinel};
+pub use crate::phi::scan_phi;
+#[cfg(feature = "parquet")]
+pub use crate::pipeline::ParquetSink;
+pub use crate::pipeline::{
+	Block, CsvSink, Decimate, Derivation, EdfSink, Montage, Pipeline, Select, Sink, SplitEdfSink,
+	SplitLimit, StreamSignal, Transform,
+};
+#[cfg(feature = "pipeline")]
+pub use crate::pipeline::{OutputConfig, PipelineConfig, StageConfig};
+pub use crate::preview::{Envelope, Preview, PreviewChannel, Pyramid};
+#[cfg(feature = "profile")]
+pub use crate::profile::{Deviation, Profile, SignalProfile};
+pub use crate::qc::{repeated_records, unit_suggestions, RepeatedRun, UnitSuggestion};
+pub use crate::reader::{
+	FileFormat, FileType, Header, Reader, ReaderBuilder, Records, StartdatePrecedence,
+	TextEncoding, MAX_RECORD_SIZE,
+};
+pub use crate::record::Record;
+pub use crate::replay::Replay;
+#[cfg(feature = "respiratory")]
+pub use crate::respiratory::{detect_respiratory_events, RespiratorySignals};
+pub use crate::session::{Session, SessionEntry};
+pub use crate::signal::SignalHeader;
+#[cfg(feature = "analysis")]
+pub use crate::sleep::{sleep_report, SleepReport, Stage, StagePercentages};
+pub use crate::sniff::{open_auto, Format, Source};
+pub use crate::timeline::{Gap, GapFill, Timeline};
+pub use crate::unit::{BaseUnit, Prefix, Unit};
+pub use crate::validation::{validate, Issue, Severity};
+pub use crate::wfdb::{import_wfdb, wfdb_to_edf, WfdbHeader, WfdbSignal};
 
+mod annotation;
+mod calibration;
+mod catalog;
+#[cfg(feature = "checksum")]
+mod checksum;
+mod crc;
+mod edit;
 mod error;
+#[cfg(feature = "analysis")]
+mod evoked;
+#[cfg(feature = "export")]
+mod export;
+#[cfg(test)]
+mod fixture;
+mod generator;
+mod identification;
+mod incremental;
+mod label;
+mod layout;
+mod load;
+mod mapping;
+mod missing;
+mod phi;
+mod pipeline;
+mod preview;
+#[cfg(feature = "profile")]
+mod profile;
+mod qc;
 mod reader;
+mod record;
+mod replay;
+#[cfg(feature = "respiratory")]
+mod respiratory;
+mod session;
+mod signal;
+#[cfg(feature = "analysis")]
+mod sleep;
+mod sniff;
+mod timeline;
+mod unit;
+mod validation;
+mod wfdb;
@@ -0,0 +1,133 @@
+//! Loading the samples of a whole file within a memory budget.
+
+use std::borrow::Cow;
+
+use crate::error::Result;
+use crate::reader::Reader;
+use crate::unit::Unit;
+
+/// The number of data records read at a time while loading.
+const BATCH: usize = 64;
+
+/// The physical samples of a signal, either loaded or to be read on demand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+	/// The index of the signal.
+	pub signal: usize,
+	pub label: String,
+	/// The unit of the samples.
+	pub unit: Unit,
+	samples: Option<Vec<f64>>,
+}
+
+impl Channel {
+	/// Whether the samples were loaded within the budget.
+	pub fn is_loaded(&self) -> bool {
+		self.samples.is_some()
+	}
+
+	/// Returns the samples, reading them from the file if they were not
+	/// loaded.
+	///
+	/// Only the bytes of this signal are read, so reading a channel that was
+	/// over the budget takes the memory of that channel alone. The reader
+	/// must be the one the channel was loaded from.
+	pub fn samples(&self, reader: &Reader) -> Result<Cow<'_, [f64]>> {
+		match self.samples {
+			Some(ref samples) => Ok(Cow::Borrowed(samples)),
+			None => {
+				let records_len = reader.header().effective_records_len().unwrap_or(0);
+				Ok(Cow::Owned(
+					reader.read_signal_at(self.signal, 0..records_len)?,
+				))
+			}
+		}
+	}
+}
+
+/// Loads the physical samples of every signal except annotation signals,
+/// keeping their total size within a budget in bytes.
+///
+/// Signals are loaded in order until the next one would exceed the budget.
+/// The rest are returned as handles that read their samples on demand, so
+/// that loading a long ambulatory recording does not run out of memory.
+/// Records are read in batches, so the whole file is never held at once.
+pub fn load_all(reader: &Reader, budget: usize) -> Result<Vec<Channel>> {
+	let hdr = reader.header();
+	let records_len = hdr.effective_records_len().unwrap_or(0);
+	let mut remaining = budget;
+	let mut channels: Vec<Channel> = hdr
+		.signals
+		.iter()
+		.enumerate()
+		.filter(|(_, s)| !s.is_annotation())
+		.map(|(i, s)| {
+			let len = s.samples_per_record.saturating_mul(records_len);
+			let size = len.saturating_mul(std::mem::size_of::<f64>());
+			let samples = if size <= remaining {
+				remaining -= size;
+				Some(Vec::with_capacity(len))
+			} else {
+				None
+			};
+			Channel {
+				signal: i,
+				label: s.label.clone(),
+				unit: s.unit(),
+				samples,
+			}
+		})
+		.collect();
+	if !channels.iter().any(Channel::is_loaded) {
+		return Ok(channels);
+	}
+
+	for start in (0..records_len).step_by(BATCH) {
+		let records = reader.read_records_at(start..records_len.min(start + BATCH))?;
+		for channel in &mut channels {
+			if let Some(ref mut samples) = channel.samples {
+				for record in &records {
+					samples.extend(
+						record.signals()[channel.signal]
+							.iter()
+							.map(|&sample| reader.to_physical(channel.signal, sample)),
+					);
+				}
+			}
+		}
+	}
+	Ok(channels)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::load_all;
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::Reader;
+
+	#[test]
+	fn load_within_budget() {
+		let path = std::env::temp_dir().join(format!("edf-load-{}.edf", std::process::id()));
+		Generator::new()
+			.records(100)
+			.channel("EEG Fpz-Cz", 4, Pattern::Sine { frequency: 1.0 })
+			.channel("EEG Pz-Oz", 4, Pattern::Noise)
+			.annotation(1.0, "Lights off")
+			.to_path(&path)
+			.unwrap();
+		let mut reader = Reader::from_path(&path).unwrap();
+		let expected = reader.read_signal(1).unwrap();
+		// Room for the first signal only.
+		let channels = load_all(&reader, 400 * 8 + 100).unwrap();
+		let deferred = channels[1].samples(&reader).unwrap().into_owned();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(channels.len(), 2);
+		assert!(channels[0].is_loaded());
+		assert!(!channels[1].is_loaded());
+		assert_eq!(channels[0].samples(&reader).unwrap().len(), 400);
+		assert_eq!(deferred, expected);
+	}
+}
@@ -1,26 +1,1299 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
 use std::path::PathBuf;
+use std::process;
 
-use clap::Parser;
-use reader::Reader;
+use chrono::{Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use clap::{ArgEnum, Parser, Subcommand};
+use edf::{ManifestEntry, Reader, ReaderBuilder};
 
-mod error;
-mod reader;
-
-/// Simple program to greet a person
+/// Tools for inspecting EDF files
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-	/// The input file
-	#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
-	input: PathBuf,
+	#[clap(subcommand)]
+	command: Command,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Print the header of a file
+	Info {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// The output format of the header
+		#[clap(short, long, arg_enum, default_value = "text")]
+		format: Format,
+
+		/// The time zone the recording was made in, e.g. Europe/Amsterdam
+		///
+		/// EDF stores the start as local time. With a time zone, the start is
+		/// also printed as an absolute timestamp.
+		#[clap(long, value_name = "TZ")]
+		assume_tz: Option<Tz>,
+
+		/// Recover from non-conformant headers where possible, printing
+		/// warnings instead of failing
+		#[clap(long)]
+		lenient: bool,
+
+		/// How to decode the text fields of the header
+		#[clap(long, arg_enum, default_value = "utf8")]
+		encoding: TextEncoding,
+	},
+	/// List the EDF-family files under a directory
+	Ls {
+		/// The directory to search, including subdirectories
+		#[clap(parse(from_os_str), value_name = "DIR")]
+		dir: PathBuf,
+	},
+	/// Rewrite the start date and time of a file in place
+	SetStart {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// Shift the start by a duration, e.g. -1h or +1h30m
+		#[clap(
+			long,
+			allow_hyphen_values = true,
+			parse(try_from_str = parse_shift),
+			value_name = "DURATION",
+			conflicts_with_all = &["date", "time"],
+			required_unless_present_any = &["date", "time"]
+		)]
+		shift: Option<Duration>,
+
+		/// The new start date, e.g. 2024-03-01
+		#[clap(long)]
+		date: Option<NaiveDate>,
+
+		/// The new start time, e.g. 22:00:00
+		#[clap(long)]
+		time: Option<NaiveTime>,
+
+		/// Print the header fields that would change, with their old and new
+		/// bytes, without writing the file
+		#[clap(long)]
+		dry_run: bool,
+	},
+	/// Print the EDF+ annotations of a file
+	Annotations {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// Translate the annotation texts to standard terms with a mapping
+		/// file, listing texts it does not map on stderr. Each line of the
+		/// file holds a vendor text and its term, separated by a tab.
+		#[clap(long, parse(from_os_str), value_name = "MAPPING")]
+		map: Option<PathBuf>,
+
+		/// The output format, e.g. an Audacity label track or subtitles
+		#[clap(short, long, arg_enum, default_value = "text")]
+		format: AnnotationFormat,
+	},
+	/// Print the gain, offset, resolution and dynamic range of each signal
+	Calibration {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+	},
+	/// Print the number of samples, the number of missing samples and the
+	/// minimum, maximum and mean of each signal
+	Stats {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// A digital value that marks missing samples of a signal, as
+		/// LABEL:SENTINEL where the sentinel is digital-min, digital-max or a
+		/// number, e.g. "SpO2:digital-min"
+		#[clap(long = "missing", value_name = "MISSING", parse(try_from_str = parse_sentinel))]
+		sentinels: Vec<(String, edf::Sentinel)>,
+	},
+	/// Print the byte offset and length of every header field, data record
+	/// and signal block of a file
+	Offsets {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+	},
+	/// Print the gaps between the data records of a discontinuous file
+	///
+	/// Each gap is printed with the index of the record after it, and its
+	/// start, end and duration in seconds. The time covered by the records
+	/// and the time elapsed from the starttime to the end of the last record
+	/// follow.
+	Gaps {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+	},
+	/// Check a file for signs of recorder faults and mislabeled signals
+	///
+	/// Reports runs of identical consecutive records, and signals whose
+	/// physical range is implausible in their unit. The exit status is 1 if
+	/// any are found.
+	Qc {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+	},
+	/// Repair defects of a file in place
+	///
+	/// Each repair is opt-in. The changes are printed one per line.
+	Fix {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// Relabel signals whose physical range is implausible in their unit,
+		/// as suggested by qc
+		#[clap(long)]
+		units: bool,
+
+		/// Invert the polarity of a signal whose electrodes were wired
+		/// backwards, by its label
+		#[clap(long = "invert", value_name = "LABEL")]
+		inverts: Vec<String>,
+
+		/// Rescale a signal by a factor, as LABEL:FACTOR, e.g. "EEG Fpz-Cz:1000"
+		#[clap(long = "scale", value_name = "SCALE", parse(try_from_str = parse_scale))]
+		scales: Vec<(String, f64)>,
+
+		/// Blank the reserved field and the reserved field of each signal,
+		/// where some vendors store patient or study identifiers, keeping the
+		/// EDF+C or EDF+D of EDF+ files
+		#[clap(long)]
+		reserved: bool,
+
+		/// Print the header fields that would change, with their old and new
+		/// bytes, without writing the file
+		#[clap(long)]
+		dry_run: bool,
+	},
+	/// Print the data records of a file at the pace they were recorded
+	///
+	/// Each record is printed as a line with its offset in seconds followed
+	/// by the physical samples of each signal except annotation signals,
+	/// separated by tabs. Samples of the same signal are separated by commas.
+	Replay {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// Play back faster or slower, e.g. 2 for twice as fast
+		#[clap(long, default_value = "1", parse(try_from_str = parse_speed))]
+		speed: f64,
+	},
+	/// Write a preview of a file: the minimum and maximum of each signal in
+	/// each second
+	///
+	/// Viewers can draw an overview of a long recording from the preview
+	/// without decoding the whole file.
+	Preview {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// The output file [default: INPUT_FILE.preview]
+		#[clap(short, long, parse(from_os_str), value_name = "OUTPUT_FILE")]
+		output: Option<PathBuf>,
+	},
+	/// Draw an overview strip of each signal
+	///
+	/// The overview is drawn from INPUT_FILE.preview, as written by the
+	/// preview command, if it is up to date, and computed otherwise. Each
+	/// column shows the range of the signal in its stretch of the recording,
+	/// relative to the widest range of the signal.
+	Overview {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// The number of columns of each strip
+		#[clap(long, default_value = "60")]
+		width: usize,
+	},
+	/// Convert a file in one pass through the stages of a pipeline
+	///
+	/// The pipeline is a TOML file naming the input file, the stages, such
+	/// as a montage or decimation, and the outputs.
+	Pipeline {
+		/// The pipeline
+		#[clap(parse(from_os_str), value_name = "PIPELINE_FILE")]
+		config: PathBuf,
+	},
+	/// Convert the WFDB records of a PhysioNet dataset to EDF
+	///
+	/// The records are those listed in the RECORDS file of the dataset
+	/// directory. Each is written to OUTPUT_DIR as its name followed by
+	/// .edf, with the comments of its header in the recording
+	/// identification. The exit status is 1 if any record fails.
+	ImportWfdb {
+		/// The dataset directory
+		#[clap(parse(from_os_str), value_name = "INPUT_DIR")]
+		input: PathBuf,
+
+		/// The directory to write the EDF files to
+		#[clap(short, long, parse(from_os_str), value_name = "OUTPUT_DIR")]
+		output: PathBuf,
+	},
+	/// Check a file against the EDF and EDF+ specs and, optionally, that it
+	/// has the signals a montage profile requires
+	///
+	/// Issues and deviations are printed one per line, and the exit status is
+	/// 1 if there are any errors or deviations, or any probable PHI with
+	/// --phi.
+	Validate {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// Also scan for probable protected health information, such as names,
+		/// medical record numbers and birthdates, to check de-identification
+		#[clap(long)]
+		phi: bool,
+
+		/// The profile, a TOML file listing the required signals
+		#[clap(long, parse(from_os_str), value_name = "PROFILE")]
+		profile: Option<PathBuf>,
+	},
+	/// Print summary metrics of a scored sleep study as JSON
+	///
+	/// The metrics are computed from the hypnogram and the apneas and
+	/// hypopneas in the EDF+ annotations: total sleep time, wake after sleep
+	/// onset, sleep efficiency, stage percentages and, if there are any
+	/// respiratory events, the apnea-hypopnea index.
+	SleepReport {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+	},
+	/// Print candidate apneas, hypopneas and oxygen desaturations detected
+	/// from the airflow, effort and SpO2 signals, for review by a scorer
+	#[cfg(feature = "respiratory")]
+	Respiratory {
+		/// The input file
+		#[clap(short, long, parse(from_os_str), value_name = "INPUT_FILE")]
+		input: PathBuf,
+
+		/// The label of the airflow signal
+		#[clap(long, value_name = "LABEL")]
+		airflow: String,
+
+		/// The label of a respiratory effort signal, to tell obstructive from
+		/// central apneas
+		#[clap(long, value_name = "LABEL")]
+		effort: Option<String>,
+
+		/// The label of the SpO2 signal
+		#[clap(long, value_name = "LABEL")]
+		spo2: Option<String>,
+
+		/// The output format, e.g. an Audacity label track or subtitles
+		#[clap(short, long, arg_enum, default_value = "text")]
+		format: AnnotationFormat,
+
+		/// Also write the events to OUTPUT_FILE as EDF+ annotations, to review
+		/// next to the recording
+		#[clap(short, long, parse(from_os_str), value_name = "OUTPUT_FILE")]
+		output: Option<PathBuf>,
+	},
+	/// Write a synthetic recording for testing
+	Generate {
+		/// The output file
+		#[clap(short, long, parse(from_os_str), value_name = "OUTPUT_FILE")]
+		output: PathBuf,
+
+		/// The number of data records
+		#[clap(long, default_value = "10")]
+		records: usize,
+
+		/// The duration of a data record in seconds, e.g. 0.5
+		#[clap(long, default_value = "1", parse(try_from_str = parse_record_duration))]
+		duration: std::time::Duration,
+
+		/// A signal as LABEL:RATE:PATTERN, where the pattern is sine:HZ, noise
+		/// or spikes:SECONDS, e.g. "EEG Fpz-Cz:100:sine:10"
+		#[clap(long = "channel", value_name = "CHANNEL", parse(try_from_str = parse_channel))]
+		channels: Vec<(String, f64, edf::Pattern)>,
+
+		/// An annotation as ONSET:TEXT, with the onset in seconds, e.g.
+		/// "30:Lights off"
+		#[clap(long = "annotation", value_name = "ANNOTATION", parse(try_from_str = parse_annotation))]
+		annotations: Vec<(f64, String)>,
+
+		/// Introduce a defect into the file
+		#[clap(long, arg_enum, value_name = "DEFECT")]
+		corrupt: Vec<Corruption>,
+
+		/// The seed of the noise generator
+		#[clap(long, default_value = "1")]
+		seed: u64,
+
+		/// Also write the CRC-32 of each data record to OUTPUT_FILE.crc
+		#[clap(long)]
+		crc_sidecar: bool,
+	},
+	/// Print a manifest of the SHA-256 digests of the files' data records
+	Checksum {
+		/// The input files
+		#[clap(
+			parse(from_os_str),
+			value_name = "INPUT_FILE",
+			required_unless_present = "verify"
+		)]
+		inputs: Vec<PathBuf>,
+
+		/// Check the files listed in a manifest instead of printing one
+		#[clap(
+			long,
+			parse(from_os_str),
+			value_name = "MANIFEST",
+			conflicts_with = "inputs"
+		)]
+		verify: Option<PathBuf>,
+
+		/// Check the data records of the files against a sidecar of
+		/// per-record CRCs, printing the records that do not match
+		#[clap(
+			long,
+			parse(from_os_str),
+			value_name = "SIDECAR",
+			conflicts_with = "verify"
+		)]
+		records: Option<PathBuf>,
+
+		/// Write the CRC-32 of each data record of each file to
+		/// INPUT_FILE.crc instead of printing a manifest
+		#[clap(long, conflicts_with_all = &["verify", "records"])]
+		crc_sidecar: bool,
+
+		/// List the digest of each data record instead of one per file, so
+		/// that a verification tells which records changed
+		#[clap(long, conflicts_with_all = &["verify", "records", "crc-sidecar"])]
+		per_record: bool,
+
+		/// Print the digest of the samples of each signal instead of a
+		/// manifest, to check that a signal is bit-identical across copies
+		/// whose headers differ
+		#[clap(
+			long,
+			conflicts_with_all = &["verify", "records", "crc-sidecar", "per-record"]
+		)]
+		per_signal: bool,
+	},
+	/// Export the headers and annotations of files as XML, skipping the
+	/// files that have not changed since the last export
+	///
+	/// Each file is written to OUTPUT_DIR as its name followed by .xml.
+	/// Files under an input directory keep their path below it.
+	Export {
+		/// The input files, or directories to search for EDF files
+		#[clap(parse(from_os_str), value_name = "INPUT", required = true)]
+		inputs: Vec<PathBuf>,
+
+		/// The directory to write the exports to
+		#[clap(short, long, parse(from_os_str), value_name = "OUTPUT_DIR")]
+		output: PathBuf,
+
+		/// The state of the last export, which is updated, or created if it
+		/// does not exist. Without it, every file is exported
+		#[clap(long, parse(from_os_str), value_name = "STATE_FILE")]
+		state: Option<PathBuf>,
+	},
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum Format {
+	Text,
+	Xml,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum AnnotationFormat {
+	Text,
+	Audacity,
+	Srt,
+	Vtt,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum TextEncoding {
+	Utf8,
+	Ascii,
+	Latin1,
+	Lossy,
+}
+
+impl From<TextEncoding> for edf::TextEncoding {
+	fn from(encoding: TextEncoding) -> edf::TextEncoding {
+		match encoding {
+			TextEncoding::Utf8 => edf::TextEncoding::Utf8,
+			TextEncoding::Ascii => edf::TextEncoding::Ascii,
+			TextEncoding::Latin1 => edf::TextEncoding::Latin1,
+			TextEncoding::Lossy => edf::TextEncoding::Lossy,
+		}
+	}
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum Corruption {
+	TruncatedRecord,
+	InvalidStartDate,
+	RecordCountMismatch,
+}
+
+impl From<Corruption> for edf::Corruption {
+	fn from(corruption: Corruption) -> edf::Corruption {
+		match corruption {
+			Corruption::TruncatedRecord => edf::Corruption::TruncatedRecord,
+			Corruption::InvalidStartDate => edf::Corruption::InvalidStartDate,
+			Corruption::RecordCountMismatch => edf::Corruption::RecordCountMismatch,
+		}
+	}
+}
+
+fn main() {
+	if let Err(err) = run() {
+		eprintln!("error: {}", err);
+		process::exit(1);
+	}
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
 	let args = Args::parse();
 
-	let path = args.input;
-	let hdr = Reader::from_path(path)?;
-	println!("{}", hdr);
-	println!("Start datetime: {}", hdr.start_datetime);
+	match args.command {
+		Command::Info {
+			input,
+			format,
+			assume_tz,
+			lenient,
+			encoding,
+		} => {
+			let mut reader = ReaderBuilder::new()
+				.lenient(lenient)
+				.encoding(encoding.into())
+				.open_auto(input)?;
+			let hdr = reader.header().clone();
+			for warning in &hdr.warnings {
+				eprintln!("warning: {}", warning);
+			}
+			match format {
+				Format::Text => {
+					println!("{}", hdr);
+					println!("Start datetime: {}", hdr.start_datetime);
+					if let Some(tz) = assume_tz {
+						let start = match hdr.start_in(&tz) {
+							LocalResult::Single(start) => start,
+							LocalResult::Ambiguous(earliest, latest) => {
+								return Err(format!(
+									"start {} is ambiguous in {}: it may be {} or {}",
+									hdr.start_datetime,
+									tz,
+									earliest.to_rfc3339(),
+									latest.to_rfc3339()
+								)
+								.into())
+							}
+							LocalResult::None => {
+								return Err(format!(
+									"start {} does not exist in {}",
+									hdr.start_datetime, tz
+								)
+								.into())
+							}
+						};
+						println!("Start datetime ({}): {}", tz, start.to_rfc3339());
+						println!(
+							"Start datetime (UTC): {}",
+							start.with_timezone(&Utc).to_rfc3339()
+						);
+					}
+				}
+				Format::Xml => {
+					let annotations = reader.read_annotations()?;
+					edf::write_xml(io::stdout().lock(), &hdr, &annotations)?
+				}
+			}
+		}
+		Command::Ls { dir } => {
+			println!("path\tformat\tpatient\tstart\tduration\tchannels\tissues");
+			for entry in edf::catalog(dir)? {
+				println!(
+					"{}\t{}\t{}\t{}\t{}\t{}\t{}",
+					entry.path.display(),
+					entry.format.map_or("-".to_string(), |f| f.to_string()),
+					entry.patient_code.as_deref().unwrap_or("-"),
+					entry.start.map_or("-".to_string(), |s| s.to_string()),
+					entry
+						.duration
+						.map_or("-".to_string(), |d| format!("{}s", d.as_secs_f64())),
+					entry.channels.map_or("-".to_string(), |n| n.to_string()),
+					entry.issues.join("; ")
+				);
+			}
+		}
+		Command::SetStart {
+			input,
+			shift,
+			date,
+			time,
+			dry_run,
+		} => {
+			let mut editor = edf::HeaderEditor::open(&input)?;
+			let old = editor.header().start_datetime;
+			let new = match shift {
+				Some(shift) => old.checked_add_signed(shift).ok_or_else(|| {
+					format!("the start {} shifted by {} is out of range", old, shift)
+				})?,
+				None => NaiveDateTime::new(date.unwrap_or(old.date()), time.unwrap_or(old.time())),
+			};
+			editor.set_start(new)?;
+			if dry_run {
+				for change in editor.changes() {
+					println!("{}: {}", input.display(), change);
+				}
+			} else {
+				editor.save()?;
+				println!("{}: {} -> {}", input.display(), old, new);
+			}
+		}
+		Command::Annotations { input, map, format } => {
+			let mut annotations = Reader::from_path(input)?.read_annotations()?;
+			if let Some(map) = map {
+				let map = edf::AnnotationMap::read(BufReader::new(File::open(map)?))?;
+				for text in map.apply(&mut annotations) {
+					eprintln!("warning: unmapped annotation \"{}\"", text);
+				}
+			}
+			print_annotations(&annotations, format)?;
+		}
+		Command::Calibration { input } => {
+			let hdr = Reader::from_path(input)?.into_header();
+			println!("label\tunit\tgain\toffset\tresolution\tdynamic range (dB)");
+			for c in edf::calibration(&hdr) {
+				println!(
+					"{}\t{}\t{}\t{}\t{}\t{:.1}",
+					c.label, c.unit, c.gain, c.offset, c.resolution, c.dynamic_range
+				);
+			}
+		}
+		Command::Stats { input, sentinels } => {
+			let hdr = Reader::from_path(&input)?.into_header();
+			let mut builder = ReaderBuilder::new();
+			for (label, sentinel) in sentinels {
+				let signal = hdr
+					.signals
+					.iter()
+					.position(|s| s.label == label)
+					.ok_or_else(|| format!("no signal labeled \"{}\"", label))?;
+				builder.sentinel(signal, sentinel);
+			}
+			let stats = edf::signal_stats(&mut builder.from_path(&input)?)?;
+			let value = |v: Option<f64>| v.map_or("-".to_string(), |v| v.to_string());
+			println!("label\tunit\tcount\tmissing\tmin\tmax\tmean");
+			for (i, stats) in hdr.ordinary_signals().into_iter().zip(stats) {
+				let signal = &hdr.signals[i];
+				println!(
+					"{}\t{}\t{}\t{}\t{}\t{}\t{}",
+					signal.label,
+					signal.unit(),
+					stats.count,
+					stats.missing,
+					value(stats.min),
+					value(stats.max),
+					value(stats.mean)
+				);
+			}
+		}
+		Command::Offsets { input } => {
+			let hdr = Reader::from_path(input)?.into_header();
+			println!("offset\tlength\tregion");
+			for region in edf::offsets(&hdr) {
+				println!("{}\t{}\t{}", region.offset, region.len, region.name);
+			}
+		}
+		Command::Gaps { input } => {
+			let mut reader = Reader::from_path(input)?;
+			let timeline = reader.read_timeline()?;
+			println!("record\tstart\tend\tduration");
+			for gap in timeline.gaps() {
+				println!(
+					"{}\t{}\t{}\t{}",
+					gap.record,
+					gap.start,
+					gap.end(),
+					gap.duration
+				);
+			}
+			println!("recorded\t{}", timeline.recorded());
+			println!("elapsed\t{}", timeline.elapsed());
+		}
+		Command::Qc { input } => {
+			let mut reader = Reader::from_path(&input)?;
+			let runs = edf::repeated_records(&mut reader)?;
+			for run in &runs {
+				println!("{}: {}", input.display(), run);
+			}
+			let suggestions = edf::unit_suggestions(reader.header());
+			for suggestion in &suggestions {
+				println!("{}: {}", input.display(), suggestion);
+			}
+			if !runs.is_empty() || !suggestions.is_empty() {
+				process::exit(1);
+			}
+		}
+		Command::Fix {
+			input,
+			units,
+			inverts,
+			scales,
+			reserved,
+			dry_run,
+		} => {
+			if !units && inverts.is_empty() && scales.is_empty() && !reserved {
+				return Err("nothing to fix, pass --units, --invert, --scale or --reserved".into());
+			}
+			let mut editor = edf::HeaderEditor::open(&input)?;
+			let labels: Vec<String> = editor
+				.header()
+				.signals
+				.iter()
+				.map(|s| s.label.clone())
+				.collect();
+			let find = |label: &str| {
+				labels
+					.iter()
+					.position(|l| l == label)
+					.ok_or_else(|| format!("no signal labeled \"{}\"", label))
+			};
+			// Look every label up before changing anything.
+			let inverts = inverts
+				.iter()
+				.map(|label| find(label))
+				.collect::<Result<Vec<usize>, String>>()?;
+			let scales = scales
+				.iter()
+				.map(|(label, factor)| Ok((find(label)?, *factor)))
+				.collect::<Result<Vec<(usize, f64)>, String>>()?;
+			let mut messages = Vec::new();
+			for signal in inverts {
+				editor.invert_polarity(signal)?;
+				messages.push(format!(
+					"signal {} ({}): inverted polarity",
+					signal, labels[signal]
+				));
+			}
+			for (signal, factor) in scales {
+				editor.scale_signal(signal, factor)?;
+				messages.push(format!(
+					"signal {} ({}): scaled by {}",
+					signal, labels[signal], factor
+				));
+			}
+			// Units are suggested from the physical ranges, so after rescaling.
+			let suggestions = if units {
+				edf::unit_suggestions(editor.header())
+			} else {
+				Vec::new()
+			};
+			for suggestion in suggestions {
+				editor.set_physical_dimension(suggestion.signal, suggestion.suggested)?;
+				messages.push(format!(
+					"signal {} ({}): physical dimension {} -> {}",
+					suggestion.signal, suggestion.label, suggestion.unit, suggestion.suggested
+				));
+			}
+			if reserved {
+				editor.blank_reserved();
+				messages.push("blanked the reserved fields".to_string());
+			}
+			if dry_run {
+				for change in editor.changes() {
+					println!("{}: {}", input.display(), change);
+				}
+			} else {
+				editor.save()?;
+				for message in messages {
+					println!("{}: {}", input.display(), message);
+				}
+			}
+		}
+		Command::Replay { input, speed } => {
+			let mut reader = Reader::from_path(input)?;
+			let hdr = reader.header().clone();
+			let mut stdout = io::stdout().lock();
+			let duration = hdr.duration.as_secs_f64();
+			for (i, record) in edf::Replay::new(&mut reader, speed)?.enumerate() {
+				let record = record?;
+				// EDF+D records carry their onset, which may leave a gap.
+				let onset = record.onset(&hdr)?.unwrap_or(i as f64 * duration);
+				write!(stdout, "{}", onset)?;
+				for signal in hdr.ordinary_signals() {
+					let samples = record.physical_samples(&hdr, signal).unwrap_or_default();
+					let samples: Vec<String> = samples.iter().map(f64::to_string).collect();
+					write!(stdout, "\t{}", samples.join(","))?;
+				}
+				writeln!(stdout)?;
+			}
+		}
+		Command::Preview { input, output } => {
+			let preview = edf::Preview::compute(&mut Reader::from_path(&input)?)?;
+			let output = output.unwrap_or_else(|| {
+				let mut output = input.into_os_string();
+				output.push(".preview");
+				output.into()
+			});
+			let mut wtr = BufWriter::new(File::create(output)?);
+			preview.write(&mut wtr)?;
+			wtr.flush()?;
+		}
+		Command::Overview { input, width } => {
+			let preview = edf::Preview::load(input)?;
+			let label_width = preview
+				.channels
+				.iter()
+				.map(|c| c.label.chars().count())
+				.max()
+				.unwrap_or(0);
+			for channel in &preview.channels {
+				println!(
+					"{:<label_width$}  {}",
+					channel.label,
+					overview_strip(channel, width),
+					label_width = label_width
+				);
+			}
+		}
+		Command::Pipeline { config } => {
+			let config = edf::PipelineConfig::from_path(config)?;
+			let mut reader = Reader::from_path(&config.input)?;
+			let len = config.build()?.run(&mut reader)?;
+			println!("{}: {} records", config.input.display(), len);
+		}
+		Command::ImportWfdb { input, output } => {
+			let mut failed = false;
+			for (name, result) in edf::import_wfdb(input, output)? {
+				match result {
+					Ok(len) => println!("{}: {} records", name, len),
+					Err(err) => {
+						eprintln!("{}: {}", name, err);
+						failed = true;
+					}
+				}
+			}
+			if failed {
+				process::exit(1);
+			}
+		}
+		Command::Validate {
+			input,
+			profile,
+			phi,
+		} => {
+			let issues = edf::validate(&input)?;
+			for issue in &issues {
+				println!("{}: {}", input.display(), issue);
+			}
+			let phi = if phi {
+				edf::scan_phi(&input)?
+			} else {
+				Vec::new()
+			};
+			for issue in &phi {
+				println!("{}: {}", input.display(), issue);
+			}
+			let deviations = match profile {
+				Some(profile) => {
+					let profile = edf::Profile::from_path(profile)?;
+					let hdr = ReaderBuilder::new()
+						.lenient(true)
+						.from_path(&input)?
+						.into_header();
+					profile.validate(&hdr)
+				}
+				None => Vec::new(),
+			};
+			for deviation in &deviations {
+				println!("{}: {}", input.display(), deviation);
+			}
+			let errors = issues.iter().any(|i| i.severity == edf::Severity::Error);
+			if errors || !deviations.is_empty() || !phi.is_empty() {
+				process::exit(1);
+			}
+		}
+		Command::SleepReport { input } => {
+			let annotations = Reader::from_path(input)?.read_annotations()?;
+			let report = edf::sleep_report(&annotations);
+			let mut stdout = io::stdout().lock();
+			serde_json::to_writer_pretty(&mut stdout, &report)?;
+			writeln!(stdout)?;
+		}
+		#[cfg(feature = "respiratory")]
+		Command::Respiratory {
+			input,
+			airflow,
+			effort,
+			spo2,
+			format,
+			output,
+		} => {
+			let mut reader = Reader::from_path(input)?;
+			let find = |label: &str| {
+				reader
+					.header()
+					.signals
+					.iter()
+					.position(|s| s.label == label)
+					.ok_or_else(|| format!("no signal labeled \"{}\"", label))
+			};
+			let signals = edf::RespiratorySignals {
+				airflow: find(&airflow)?,
+				effort: effort.as_deref().map(find).transpose()?,
+				spo2: spo2.as_deref().map(find).transpose()?,
+			};
+			let events = edf::detect_respiratory_events(&mut reader, &signals)?;
+			print_annotations(&events, format)?;
+			if let Some(output) = output {
+				let wtr = BufWriter::new(File::create(output)?);
+				let mut list = edf::AnnotationList::new();
+				for event in events {
+					list.push(event)?;
+				}
+				edf::write_annotation_file(wtr, reader.header(), &list)?;
+			}
+		}
+		Command::Generate {
+			output,
+			records,
+			duration,
+			channels,
+			annotations,
+			corrupt,
+			seed,
+			crc_sidecar,
+		} => {
+			let mut generator = edf::Generator::new();
+			generator
+				.records(records)
+				.duration(duration)
+				.seed(seed)
+				.crc_sidecar(crc_sidecar);
+			for (label, rate, pattern) in &channels {
+				let samples = rate * duration.as_secs_f64();
+				if samples.fract() != 0.0 {
+					return Err(format!(
+						"{} Hz does not give a whole number of samples in {} s records",
+						rate,
+						duration.as_secs_f64()
+					)
+					.into());
+				}
+				generator.channel(label, samples as usize, *pattern);
+			}
+			for (onset, text) in &annotations {
+				generator.annotation(*onset, text);
+			}
+			for corruption in corrupt {
+				generator.corrupt(corruption.into());
+			}
+			generator.to_path(output)?;
+		}
+		Command::Checksum {
+			verify: Some(manifest),
+			..
+		} => {
+			let entries = edf::read_manifest(BufReader::new(File::open(manifest)?))?;
+			let (mut files, mut failed) = (0, 0);
+			for entries in entries.chunk_by(|a, b| a.path == b.path) {
+				let path = &entries[0].path;
+				files += 1;
+				match edf::verify_file(path, entries) {
+					Ok(mismatches) if mismatches.is_empty() => {
+						println!("{}: OK", path.display());
+						continue;
+					}
+					Ok(mismatches) => {
+						for entry in mismatches {
+							match entry.record {
+								Some(record) => {
+									println!("{}: record {} FAILED", path.display(), record)
+								}
+								None => println!("{}: FAILED", path.display()),
+							}
+						}
+					}
+					Err(err) => println!("{}: FAILED ({})", path.display(), err),
+				}
+				failed += 1;
+			}
+			if failed > 0 {
+				eprintln!("{} of {} files did not match", failed, files);
+				process::exit(1);
+			}
+		}
+		Command::Checksum {
+			inputs,
+			records: Some(sidecar),
+			..
+		} => {
+			let crcs = edf::read_record_crcs(BufReader::new(File::open(sidecar)?))?;
+			let mut failed = false;
+			for input in &inputs {
+				let mismatches = Reader::from_path(input)?.verify_records(&crcs)?;
+				for record in &mismatches {
+					println!("{}: record {} FAILED", input.display(), record);
+				}
+				if mismatches.is_empty() {
+					println!("{}: OK", input.display());
+				}
+				failed |= !mismatches.is_empty();
+			}
+			if failed {
+				process::exit(1);
+			}
+		}
+		Command::Checksum {
+			inputs,
+			crc_sidecar: true,
+			..
+		} => {
+			for input in &inputs {
+				let mut sidecar = input.clone().into_os_string();
+				sidecar.push(".crc");
+				let wtr = BufWriter::new(File::create(&sidecar)?);
+				let records_len = Reader::from_path(input)?.write_record_crcs(wtr)?;
+				println!(
+					"{}: {} records",
+					PathBuf::from(sidecar).display(),
+					records_len
+				);
+			}
+		}
+		Command::Checksum {
+			inputs,
+			per_signal: true,
+			..
+		} => {
+			for input in &inputs {
+				let hdr = Reader::from_path(input)?.into_header();
+				let digests = edf::signal_digests(input)?;
+				for (i, (signal, digest)) in hdr.signals.iter().zip(&digests).enumerate() {
+					println!(
+						"{}: signal {} ({}): {}",
+						input.display(),
+						i,
+						signal.label,
+						digest
+					);
+				}
+			}
+		}
+		Command::Checksum {
+			inputs,
+			verify: None,
+			records: None,
+			per_record,
+			..
+		} => {
+			let mut entries = Vec::new();
+			for input in &inputs {
+				if per_record {
+					entries.extend(ManifestEntry::compute_records(input)?);
+				} else {
+					entries.push(ManifestEntry::compute(input)?);
+				}
+			}
+			edf::write_manifest(io::stdout().lock(), &entries)?;
+		}
+		Command::Export {
+			inputs,
+			output,
+			state: state_path,
+		} => {
+			let mut state = match state_path {
+				Some(ref path) if path.exists() => {
+					edf::ExportState::read(BufReader::new(File::open(path)?))?
+				}
+				_ => edf::ExportState::new(),
+			};
+			let (mut files, mut skipped) = (Vec::new(), 0);
+			for input in &inputs {
+				if input.is_dir() {
+					for entry in edf::catalog(input)? {
+						match entry.format {
+							Some(edf::Format::Edf | edf::Format::Bdf) => {
+								let relative = entry.path.strip_prefix(input)?.to_path_buf();
+								files.push((entry.path, relative));
+								continue;
+							}
+							Some(format) => {
+								eprintln!("{}: skipped {} file", entry.path.display(), format)
+							}
+							None => eprintln!(
+								"{}: skipped, {}",
+								entry.path.display(),
+								entry.issues.join("; ")
+							),
+						}
+						skipped += 1;
+					}
+				} else {
+					let name = input.file_name().unwrap_or(input.as_os_str());
+					files.push((input.clone(), PathBuf::from(name)));
+				}
+			}
+			let (mut exported, mut failed) = (0, 0);
+			for (path, relative) in &files {
+				let mut target = output.join(relative).into_os_string();
+				target.push(".xml");
+				let target = PathBuf::from(target);
+				let result = (|| -> edf::Result<bool> {
+					let snapshot = match state.needs_export(path)? {
+						Some(snapshot) => snapshot,
+						None => return Ok(false),
+					};
+					let mut reader = Reader::from_path(path)?;
+					let annotations = reader.read_annotations()?;
+					if let Some(dir) = target.parent() {
+						std::fs::create_dir_all(dir)?;
+					}
+					let mut wtr = BufWriter::new(File::create(&target)?);
+					edf::write_xml(&mut wtr, reader.header(), &annotations)?;
+					wtr.flush()?;
+					state.mark_exported(path, snapshot);
+					Ok(true)
+				})();
+				match result {
+					Ok(true) => {
+						println!("{}", target.display());
+						exported += 1;
+					}
+					Ok(false) => {}
+					Err(err) => {
+						eprintln!("{}: {}", path.display(), err);
+						failed += 1;
+					}
+				}
+			}
+			if let Some(path) = state_path {
+				state.write(BufWriter::new(File::create(path)?))?;
+			}
+			eprintln!(
+				"{} exported, {} unchanged, {} skipped, {} failed",
+				exported,
+				files.len() - exported - failed,
+				skipped,
+				failed
+			);
+			if failed > 0 {
+				process::exit(1);
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Draws the range of a signal in each of `width` stretches of the
+/// recording as bars of eight heights. Stretches without samples are blank.
+fn overview_strip(channel: &edf::PreviewChannel, width: usize) -> String {
+	const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+	let seconds = channel.min.len();
+	let columns = width.min(seconds);
+	let ranges: Vec<f64> = (0..columns)
+		.map(|column| {
+			let stretch = column * seconds / columns..(column + 1) * seconds / columns;
+			let min = channel.min[stretch.clone()]
+				.iter()
+				.copied()
+				.fold(f64::NAN, f64::min);
+			let max = channel.max[stretch]
+				.iter()
+				.copied()
+				.fold(f64::NAN, f64::max);
+			max - min
+		})
+		.collect();
+	let widest = ranges.iter().copied().fold(f64::NAN, f64::max);
+	ranges
+		.iter()
+		.map(|&range| {
+			if range.is_nan() {
+				' '
+			} else if widest > 0.0 {
+				BARS[((range / widest * 7.0).round() as usize).min(7)]
+			} else {
+				BARS[0]
+			}
+		})
+		.collect()
+}
+
+fn print_annotations(
+	annotations: &[edf::Annotation],
+	format: AnnotationFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let stdout = io::stdout().lock();
+	match format {
+		AnnotationFormat::Text => {
+			println!("onset\tduration\ttext");
+			for a in annotations {
+				let duration = a.duration.map_or("-".to_string(), |d| d.to_string());
+				println!("{}\t{}\t{}", a.onset, duration, a.text);
+			}
+		}
+		AnnotationFormat::Audacity => edf::write_audacity_labels(stdout, annotations)?,
+		AnnotationFormat::Srt => edf::write_srt(stdout, annotations)?,
+		AnnotationFormat::Vtt => edf::write_vtt(stdout, annotations)?,
+	}
 	Ok(())
 }
+
+/// Parses a generated signal, e.g. `EEG Fpz-Cz:100:sine:10`.
+fn parse_channel(s: &str) -> Result<(String, f64, edf::Pattern), String> {
+	let err = || {
+		format!(
+			"invalid channel \"{}\", expected LABEL:RATE:PATTERN, e.g. EEG:100:sine:10",
+			s
+		)
+	};
+	let mut parts = s.splitn(4, ':');
+	let label = parts.next().filter(|l| !l.is_empty()).ok_or_else(err)?;
+	let rate = parts
+		.next()
+		.and_then(|r| r.parse::<f64>().ok())
+		.filter(|&r| r > 0.0)
+		.ok_or_else(err)?;
+	let pattern = match (parts.next(), parts.next().map(str::parse::<f64>)) {
+		(Some("sine"), Some(Ok(frequency))) => edf::Pattern::Sine { frequency },
+		(Some("noise"), None) => edf::Pattern::Noise,
+		(Some("spikes"), Some(Ok(interval))) if interval > 0.0 => edf::Pattern::Spikes { interval },
+		_ => return Err(err()),
+	};
+	Ok((label.to_string(), rate, pattern))
+}
+
+/// Parses an annotation, e.g. `30:Lights off`.
+fn parse_annotation(s: &str) -> Result<(f64, String), String> {
+	s.split_once(':')
+		.and_then(|(onset, text)| Some((onset.parse().ok()?, text.to_string())))
+		.ok_or_else(|| format!("invalid annotation \"{}\", expected ONSET:TEXT", s))
+}
+
+/// Parses a missing-data sentinel, e.g. `SpO2:digital-min`.
+fn parse_sentinel(s: &str) -> Result<(String, edf::Sentinel), String> {
+	let err = || {
+		format!(
+			"invalid sentinel \"{}\", expected LABEL:SENTINEL where the sentinel is digital-min, digital-max or a number",
+			s
+		)
+	};
+	let (label, sentinel) = s.rsplit_once(':').ok_or_else(err)?;
+	let sentinel = match sentinel {
+		"digital-min" => edf::Sentinel::DigitalMin,
+		"digital-max" => edf::Sentinel::DigitalMax,
+		value => edf::Sentinel::Value(value.parse().map_err(|_| err())?),
+	};
+	Ok((label.to_string(), sentinel))
+}
+
+/// Parses a rescaling of a signal, e.g. `EEG Fpz-Cz:1000`.
+fn parse_scale(s: &str) -> Result<(String, f64), String> {
+	s.rsplit_once(':')
+		.and_then(|(label, factor)| {
+			let factor = factor.parse::<f64>().ok()?;
+			(factor.is_finite() && factor != 0.0).then(|| (label.to_string(), factor))
+		})
+		.ok_or_else(|| {
+			format!(
+				"invalid scale \"{}\", expected LABEL:FACTOR with a non-zero factor",
+				s
+			)
+		})
+}
+
+/// Parses a positive playback speed.
+fn parse_speed(s: &str) -> Result<f64, String> {
+	match s.parse::<f64>() {
+		Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+		_ => Err(format!(
+			"invalid speed \"{}\", expected a positive number",
+			s
+		)),
+	}
+}
+
+/// Parses the duration of a data record in seconds, e.g. `0.5`.
+fn parse_record_duration(s: &str) -> Result<std::time::Duration, String> {
+	match s.parse().map(std::time::Duration::try_from_secs_f64) {
+		Ok(Ok(duration)) if !duration.is_zero() => Ok(duration),
+		_ => Err(format!(
+			"invalid duration \"{}\", expected a positive number of seconds",
+			s
+		)),
+	}
+}
+
+/// Parses a signed duration made of hours, minutes and seconds, e.g. `-1h30m`.
+fn parse_shift(s: &str) -> Result<Duration, String> {
+	let err = || format!("invalid duration \"{}\", expected e.g. -1h or +1h30m", s);
+	let (sign, rest) = match s.strip_prefix('-') {
+		Some(rest) => (-1, rest),
+		None => (1, s.strip_prefix('+').unwrap_or(s)),
+	};
+	if rest.is_empty() {
+		return Err(err());
+	}
+	let mut total = Duration::zero();
+	let mut digits = String::new();
+	for c in rest.chars() {
+		if c.is_ascii_digit() {
+			digits.push(c);
+			continue;
+		}
+		let n: i64 = digits.parse().map_err(|_| err())?;
+		let part = match c {
+			'h' => Duration::try_hours(n),
+			'm' => Duration::try_minutes(n),
+			's' => Duration::try_seconds(n),
+			_ => return Err(err()),
+		};
+		total = part
+			.and_then(|part| total.checked_add(&part))
+			.ok_or_else(err)?;
+		digits.clear();
+	}
+	if !digits.is_empty() {
+		return Err(err());
+	}
+	Ok(total * sign)
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::Duration;
+
+	use super::parse_shift;
+
+	#[test]
+	fn shifts() {
+		assert_eq!(parse_shift("-1h"), Ok(Duration::hours(-1)));
+		assert_eq!(parse_shift("+1h30m"), Ok(Duration::minutes(90)));
+		assert_eq!(parse_shift("90s"), Ok(Duration::seconds(90)));
+		assert!(parse_shift("").is_err());
+		assert!(parse_shift("-").is_err());
+		assert!(parse_shift("1d").is_err());
+		assert!(parse_shift("30").is_err());
+		assert!(parse_shift("h").is_err());
+		// Too long for a duration, but not for an i64.
+		assert!(parse_shift("9223372036854775807h").is_err());
+		assert!(parse_shift("2562047788015h2562047788015h").is_err());
+	}
+}
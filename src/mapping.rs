@@ -0,0 +1,108 @@
+//! Translation of vendor annotation texts to a standard vocabulary, so that
+//! studies recorded on different systems use the same event labels.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::BufRead;
+
+use crate::annotation::Annotation;
+use crate::error::{Error, ErrorKind, Result};
+
+/// A mapping from vendor annotation texts to standard terms, such as AASM
+/// event names or SNOMED codes.
+///
+/// Texts are matched ignoring case and surrounding whitespace, since
+/// vendors are inconsistent about both.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnnotationMap {
+	terms: HashMap<String, String>,
+}
+
+impl AnnotationMap {
+	/// Creates an empty mapping.
+	pub fn new() -> AnnotationMap {
+		AnnotationMap::default()
+	}
+
+	/// Reads a mapping, one term per line.
+	///
+	/// Each line holds a vendor text and the standard term it maps to,
+	/// separated by a tab, e.g. `Obs Apnea\tObstructive apnea`. Blank lines
+	/// and lines starting with `#` are skipped.
+	pub fn read<R: BufRead>(rdr: R) -> Result<AnnotationMap> {
+		let mut map = AnnotationMap::new();
+		for (i, line) in rdr.lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let (text, term) = line
+				.split_once('\t')
+				.filter(|(text, term)| !text.trim().is_empty() && !term.trim().is_empty())
+				.ok_or_else(|| Error::new(ErrorKind::Mapping { line: i + 1 }))?;
+			map.insert(text, term.trim());
+		}
+		Ok(map)
+	}
+
+	/// Maps a vendor text to a standard term, replacing any earlier mapping of
+	/// the text.
+	pub fn insert(&mut self, text: &str, term: &str) -> &mut AnnotationMap {
+		self.terms.insert(normalize(text), term.to_string());
+		self
+	}
+
+	/// Returns the standard term of a vendor text, if it is mapped.
+	pub fn get(&self, text: &str) -> Option<&str> {
+		self.terms.get(&normalize(text)).map(String::as_str)
+	}
+
+	/// Replaces the text of each annotation with its standard term.
+	///
+	/// Annotations whose text is not mapped are left as they are. Their
+	/// distinct texts are returned, sorted, so that gaps in the mapping can
+	/// be reported and filled.
+	pub fn apply(&self, annotations: &mut [Annotation]) -> Vec<String> {
+		let mut unmapped = BTreeSet::new();
+		for annotation in annotations {
+			match self.get(&annotation.text) {
+				Some(term) => annotation.text = term.to_string(),
+				None => {
+					unmapped.insert(annotation.text.clone());
+				}
+			}
+		}
+		unmapped.into_iter().collect()
+	}
+}
+
+fn normalize(text: &str) -> String {
+	text.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::AnnotationMap;
+	use crate::annotation::Annotation;
+
+	#[test]
+	fn map_vendor_terms() {
+		let map =
+			AnnotationMap::read(&b"# Vendor A\nObs Apnea\tObstructive apnea\n\nSTAGE 2\tN2\n"[..])
+				.unwrap();
+		let mut annotations: Vec<Annotation> = ["obs apnea ", "Stage 2", "Arousal", "Arousal"]
+			.iter()
+			.enumerate()
+			.map(|(i, text)| Annotation {
+				onset: i as f64,
+				duration: None,
+				text: text.to_string(),
+			})
+			.collect();
+		let unmapped = map.apply(&mut annotations);
+		let texts: Vec<&str> = annotations.iter().map(|a| a.text.as_str()).collect();
+
+		assert_eq!(texts, ["Obstructive apnea", "N2", "Arousal", "Arousal"]);
+		assert_eq!(unmapped, ["Arousal"]);
+		assert!(AnnotationMap::read(&b"Obs Apnea\n"[..]).is_err());
+	}
+}
@@ -0,0 +1,149 @@
+//! Handling of sentinel values that recorders write for missing samples.
+
+use std::io::{Read, Seek};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::reader::Reader;
+use crate::signal::SignalHeader;
+
+/// A digital value that marks a sample as missing rather than measured.
+///
+/// Some recorders fill gaps, such as a disconnected sensor, with the digital
+/// minimum or a vendor-specific value. Read as physical values, these show
+/// up as huge deflections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sentinel {
+	/// The digital minimum of the signal.
+	DigitalMin,
+	/// The digital maximum of the signal.
+	DigitalMax,
+	/// A fixed digital value.
+	Value(i32),
+}
+
+impl Sentinel {
+	/// Whether a digital sample of the signal is this sentinel.
+	pub fn matches(self, signal: &SignalHeader, sample: i32) -> bool {
+		match self {
+			Sentinel::DigitalMin => sample == signal.digital_min,
+			Sentinel::DigitalMax => sample == signal.digital_max,
+			Sentinel::Value(value) => sample == value,
+		}
+	}
+}
+
+/// Summary statistics of physical samples, excluding missing ones.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SampleStats {
+	/// The number of samples that are not missing.
+	pub count: usize,
+	/// The number of missing samples.
+	pub missing: usize,
+	/// The smallest sample, or `None` if every sample is missing.
+	pub min: Option<f64>,
+	pub max: Option<f64>,
+	pub mean: Option<f64>,
+}
+
+/// Computes summary statistics of physical samples, skipping the NaN samples
+/// that missing data is read as.
+pub fn sample_stats(samples: &[f64]) -> SampleStats {
+	let mut accumulator = Accumulator::default();
+	for &sample in samples {
+		accumulator.push(sample);
+	}
+	accumulator.finish()
+}
+
+/// Computes summary statistics of every signal except annotation signals,
+/// in one pass over the data records.
+///
+/// Samples are converted as by `Reader::read_signal`, so the sentinels
+/// configured on the reader count as missing. The statistics are in the
+/// order of `Header::ordinary_signals`. Only one data record is held in
+/// memory at a time.
+pub fn signal_stats<R: Read + Seek>(reader: &mut Reader<R>) -> Result<Vec<SampleStats>> {
+	let signals = reader.header().ordinary_signals();
+	let records_len = reader.header().effective_records_len().unwrap_or(0);
+	let mut accumulators = vec![Accumulator::default(); signals.len()];
+	for i in 0..records_len {
+		let record = reader.read_record(i)?;
+		for (accumulator, &signal) in accumulators.iter_mut().zip(&signals) {
+			for &sample in &record.signals()[signal] {
+				accumulator.push(reader.to_physical(signal, sample));
+			}
+		}
+	}
+	Ok(accumulators.into_iter().map(Accumulator::finish).collect())
+}
+
+/// Running statistics of physical samples.
+#[derive(Debug, Clone, Default)]
+struct Accumulator {
+	count: usize,
+	missing: usize,
+	min: Option<f64>,
+	max: Option<f64>,
+	sum: f64,
+}
+
+impl Accumulator {
+	fn push(&mut self, sample: f64) {
+		if sample.is_nan() {
+			self.missing += 1;
+			return;
+		}
+		self.count += 1;
+		self.sum += sample;
+		self.min = Some(self.min.map_or(sample, |min| min.min(sample)));
+		self.max = Some(self.max.map_or(sample, |max| max.max(sample)));
+	}
+
+	fn finish(self) -> SampleStats {
+		SampleStats {
+			count: self.count,
+			missing: self.missing,
+			min: self.min,
+			max: self.max,
+			mean: (self.count > 0).then(|| self.sum / self.count as f64),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::{sample_stats, signal_stats, Sentinel};
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::ReaderBuilder;
+
+	#[test]
+	fn sentinels_read_as_missing() {
+		let path = std::env::temp_dir().join(format!("edf-missing-{}.edf", std::process::id()));
+		Generator::new()
+			.records(4)
+			.channel("SpO2", 10, Pattern::Spikes { interval: 1.0 })
+			.to_path(&path)
+			.unwrap();
+		let mut reader = ReaderBuilder::new()
+			.sentinel(0, Sentinel::DigitalMax)
+			.from_path(&path)
+			.unwrap();
+		let samples = reader.read_signal(0).unwrap();
+		let at = reader.read_signal_at(0, 1..2).unwrap();
+		let streamed = signal_stats(&mut reader).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		// Every spike is at the digital maximum.
+		assert!(samples[0].is_nan());
+		assert!(at[0].is_nan());
+		let stats = sample_stats(&samples);
+		assert_eq!(stats.count, 36);
+		assert_eq!(stats.missing, 4);
+		assert_eq!(stats.max, Some(0.0));
+		assert_eq!(streamed, [stats]);
+	}
+}
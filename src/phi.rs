@@ -0,0 +1,241 @@
+//! A scan for protected health information left in a file, for checking
+//! de-identification before sharing.
+//!
+//! The scan is a heuristic. It points at fields that probably identify a
+//! patient, such as names, medical record numbers and birthdates, but a
+//! clean scan does not prove that a file is de-identified.
+
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use crate::annotation::Annotation;
+use crate::error::Result;
+use crate::identification::{PatientInfo, RecordingInfo, MONTHS};
+use crate::reader::{Header, Reader};
+use crate::validation::{Issue, Issues, Severity};
+
+/// The shortest run of digits taken for a medical record number.
+const MRN_DIGITS: usize = 6;
+/// The oldest plausible age of a patient, in years.
+const MAX_AGE: u32 = 120;
+
+impl Header {
+	/// Scans the header for probable protected health information, listing
+	/// each field that holds some as a warning.
+	///
+	/// The EDF+ patient subfields are flagged if they are filled in, and a
+	/// birthdate is also checked for plausibility against the start of the
+	/// recording. The recording identification is flagged if it names a
+	/// technician or holds something like a name, and any identification or
+	/// reserved field is flagged if it holds something like a medical record
+	/// number or a date.
+	pub fn scan_phi(&self) -> Vec<Issue> {
+		let mut issues = Issues::new(self.signals.len());
+		let patient = "local patient identification";
+		match PatientInfo::parse(&self.patient_info) {
+			PatientInfo::Structured(id) => {
+				if id.code.is_some() {
+					issues.push(patient, None, Severity::Warning, "holds a hospital code");
+				}
+				if id.name.is_some() {
+					issues.push(patient, None, Severity::Warning, "holds a name");
+				}
+				if let Some(birthdate) = id.birthdate {
+					issues.push(patient, None, Severity::Warning, "holds a birthdate");
+					let start = self.start_datetime.date();
+					match id.age_at(start) {
+						None => issues.push(
+							patient,
+							None,
+							Severity::Warning,
+							format!("the birthdate {} is after the startdate", birthdate),
+						),
+						Some(age) if age > MAX_AGE => issues.push(
+							patient,
+							None,
+							Severity::Warning,
+							format!("the birthdate implies an age of {} years", age),
+						),
+						Some(_) => {}
+					}
+				}
+				scan_text(&mut issues, patient, None, &id.additional.join(" "));
+			}
+			PatientInfo::Raw(text) => {
+				if !text.split_whitespace().all(|s| s == "X") {
+					issues.push(
+						patient,
+						None,
+						Severity::Warning,
+						"holds free text that may identify the patient",
+					);
+				}
+			}
+		}
+
+		let recording = "local recording identification";
+		let text = match RecordingInfo::parse(&self.recording_id) {
+			RecordingInfo::Structured(id) => {
+				if id.technician.is_some() {
+					issues.push(recording, None, Severity::Warning, "names a technician");
+				}
+				id.additional.join(" ")
+			}
+			RecordingInfo::Raw(text) => text,
+		};
+		if looks_like_name(&text) {
+			issues.push(recording, None, Severity::Warning, "holds a probable name");
+		}
+		scan_text(&mut issues, recording, None, &text);
+
+		scan_text(&mut issues, "reserved", None, &self.reserved);
+		for (i, signal) in self.signals.iter().enumerate() {
+			scan_text(&mut issues, "reserved", Some(i), &signal.reserved);
+		}
+		issues.issues
+	}
+}
+
+/// Scans the file at the given path for probable protected health
+/// information, listing each field that holds some as a warning.
+///
+/// The header is read in lenient mode and scanned with
+/// [`Header::scan_phi`]. The annotations of each data record are also
+/// flagged if they hold something like a date, such as a birthdate, or a
+/// medical record number. Records whose annotations cannot be parsed are
+/// skipped, as [`validate`](crate::validate) reports them.
+pub fn scan_phi<P: AsRef<Path>>(path: P) -> Result<Vec<Issue>> {
+	scan_reader(&mut Reader::options().lenient(true).from_path(path)?)
+}
+
+/// Scans the header and the annotations of a reader, as `scan_phi` does for
+/// a path.
+fn scan_reader<R: Read + Seek>(reader: &mut Reader<R>) -> Result<Vec<Issue>> {
+	let hdr = reader.header().clone();
+	let mut issues = hdr.scan_phi();
+	let record_size = hdr.record_size().unwrap_or(0);
+	for (i, record) in reader.records().enumerate() {
+		for Annotation { onset, text, .. } in record?.annotations(&hdr).unwrap_or_default() {
+			let found = if looks_like_date(&text) {
+				"a date"
+			} else if looks_like_mrn(&text) {
+				"a probable medical record number"
+			} else {
+				continue;
+			};
+			issues.push(Issue {
+				field: format!("record {}", i),
+				severity: Severity::Warning,
+				message: format!("the annotation at {} s holds {}", onset, found),
+				offset: hdr.size as u64 + (i * record_size) as u64,
+			});
+		}
+	}
+	Ok(issues)
+}
+
+/// Flags free text that holds a probable medical record number or a date.
+fn scan_text(issues: &mut Issues, name: &str, signal: Option<usize>, text: &str) {
+	if looks_like_mrn(text) {
+		issues.push(
+			name,
+			signal,
+			Severity::Warning,
+			"holds a probable medical record number",
+		);
+	}
+	if looks_like_date(text) {
+		issues.push(name, signal, Severity::Warning, "holds a date");
+	}
+}
+
+/// Whether the text holds a run of digits long enough to be a medical record
+/// number.
+fn looks_like_mrn(text: &str) -> bool {
+	text.split(|c: char| !c.is_ascii_digit())
+		.any(|digits| digits.len() >= MRN_DIGITS)
+}
+
+/// Whether the text holds a date such as `02.05.1951`, `1951-05-02` or
+/// `02-MAY-1951`.
+fn looks_like_date(text: &str) -> bool {
+	text.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+		.any(|word| {
+			['.', '-', '/'].iter().any(|&sep| {
+				let parts: Vec<&str> = word.split(sep).collect();
+				if parts.len() != 3 {
+					return false;
+				}
+				let year = |s: &str| s.len() == 4 && s.bytes().all(|b| b.is_ascii_digit());
+				let small =
+					|s: &str| (1..=2).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_digit());
+				let month = |s: &str| small(s) || MONTHS.iter().any(|m| m.eq_ignore_ascii_case(s));
+				(small(parts[0]) && month(parts[1]) && year(parts[2]))
+					|| (year(parts[0]) && small(parts[1]) && small(parts[2]))
+			})
+		})
+}
+
+/// Whether the text holds two capitalized words in a row, as in
+/// `John_Smith` or `Smith, John`.
+fn looks_like_name(text: &str) -> bool {
+	let capitalized = |word: &str| {
+		let mut chars = word.chars();
+		chars.next().is_some_and(|c| c.is_uppercase())
+			&& word.len() > 1
+			&& chars.all(|c| c.is_lowercase())
+	};
+	let words: Vec<&str> = text
+		.split(|c: char| c.is_whitespace() || c == '_' || c == ',')
+		.filter(|w| !w.is_empty())
+		.collect();
+	words
+		.windows(2)
+		.any(|pair| capitalized(pair[0]) && capitalized(pair[1]))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{looks_like_date, looks_like_name, scan_reader};
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::Reader;
+
+	#[test]
+	fn flag_probable_phi() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(2)
+			.channel("EEG Fpz-Cz", 4, Pattern::Noise)
+			.annotation(1.0, "Lights off")
+			.annotation(1.5, "DOB 02.05.1951")
+			.write(&mut bytes)
+			.unwrap();
+		bytes[8..88].copy_from_slice(format!("{:<80}", "X F 02-MAY-1851 X").as_bytes());
+		bytes[88..168]
+			.copy_from_slice(format!("{:<80}", "Startdate X X Jane_Doe edf-generator").as_bytes());
+		bytes[192..236].copy_from_slice(format!("{:<44}", "EDF+C MRN 00123456").as_bytes());
+		let mut reader = Reader::options().lenient(true).from_bytes(&bytes).unwrap();
+		let issues = scan_reader(&mut reader).unwrap();
+
+		let messages: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+		assert_eq!(
+			messages,
+			[
+				"warning at byte 8 (local patient identification): holds a birthdate",
+				"warning at byte 8 (local patient identification): the birthdate implies an age of 150 years",
+				"warning at byte 88 (local recording identification): names a technician",
+				"warning at byte 192 (reserved): holds a probable medical record number",
+				"warning at byte 818 (record 1): the annotation at 1.5 s holds a date",
+			]
+		);
+	}
+
+	#[test]
+	fn heuristics() {
+		assert!(looks_like_date("born 1951-05-02"));
+		assert!(looks_like_date("02-May-1951"));
+		assert!(!looks_like_date("Stage 2-3"));
+		assert!(looks_like_name("Smith, John"));
+		assert!(!looks_like_name("Nasal pressure"));
+	}
+}
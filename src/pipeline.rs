@@ -0,0 +1,1202 @@
+//! Conversions that stream the data records of a file through a chain of
+//! stages, so that a file of any length is converted in one pass with the
+//! memory of a few records.
+
+#[cfg(feature = "pipeline")]
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Datelike;
+#[cfg(feature = "parquet")]
+use parquet::basic::{Repetition, Type as PhysicalType};
+#[cfg(feature = "parquet")]
+use parquet::data_type::DoubleType;
+#[cfg(feature = "parquet")]
+use parquet::file::{properties::WriterProperties, writer::SerializedFileWriter};
+#[cfg(feature = "parquet")]
+use parquet::schema::types::Type;
+#[cfg(feature = "pipeline")]
+use serde::Deserialize;
+
+use crate::edit::{format_number, unrepresentable};
+use crate::error::{Error, ErrorKind, Result};
+use crate::reader::{Header, Reader};
+
+/// A signal of the blocks passed between stages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamSignal {
+	pub label: String,
+	/// The physical dimension, e.g. `uV`.
+	pub physical_dimension: String,
+	/// The lowest physical value the signal can take.
+	pub physical_min: f64,
+	/// The highest physical value the signal can take.
+	pub physical_max: f64,
+	/// The number of samples of the signal in each block.
+	pub samples_per_record: usize,
+}
+
+/// The physical samples of one data record, as passed between stages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+	/// The start of the block in seconds since the start of the recording.
+	pub onset: f64,
+	/// The duration of the block in seconds.
+	pub duration: f64,
+	/// The samples of each signal.
+	pub signals: Vec<Vec<f64>>,
+}
+
+/// A stage that changes the blocks passing through it, such as a montage or
+/// a resampling.
+pub trait Transform {
+	/// Returns the signals of the blocks the stage yields, given those of the
+	/// blocks it takes. This is called once, before any block, and should
+	/// fail if the stage cannot handle the signals.
+	fn signals(&mut self, input: &[StreamSignal]) -> Result<Vec<StreamSignal>>;
+
+	/// Transforms a block.
+	fn apply(&mut self, block: Block) -> Result<Block>;
+}
+
+/// A stage that writes the blocks that reach the end of a pipeline.
+pub trait Sink {
+	/// Starts the output, given the header of the file read and the signals
+	/// of the blocks to come.
+	fn begin(&mut self, hdr: &Header, signals: &[StreamSignal]) -> Result<()>;
+
+	/// Writes a block.
+	fn write(&mut self, block: &Block) -> Result<()>;
+
+	/// Finishes the output after the last block, e.g. by flushing it.
+	fn finish(&mut self) -> Result<()> {
+		Ok(())
+	}
+}
+
+/// A chain of transforms feeding one or more sinks.
+///
+/// The data records of a file are read one at a time, converted to
+/// physical values and passed through each transform in the order they were
+/// added. Every sink then gets the result. Annotation signals are left out.
+///
+/// ```no_run
+/// use edf::{CsvSink, Decimate, Pipeline, Reader, Select};
+///
+/// # fn main() -> edf::Result<()> {
+/// let mut reader = Reader::from_path("sleep.edf")?;
+/// Pipeline::new()
+///     .transform(Select::new(vec!["EEG Fpz-Cz".to_string()]))
+///     .transform(Decimate::new(2))
+///     .sink(CsvSink::new(std::io::stdout()))
+///     .run(&mut reader)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Pipeline {
+	transforms: Vec<Box<dyn Transform>>,
+	sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Pipeline {
+	/// Creates a pipeline without stages.
+	pub fn new() -> Pipeline {
+		Pipeline::default()
+	}
+
+	/// Adds a transform after those added before it.
+	pub fn transform<T: Transform + 'static>(&mut self, transform: T) -> &mut Pipeline {
+		self.transforms.push(Box::new(transform));
+		self
+	}
+
+	/// Adds a sink.
+	pub fn sink<S: Sink + 'static>(&mut self, sink: S) -> &mut Pipeline {
+		self.sinks.push(Box::new(sink));
+		self
+	}
+
+	/// Streams the data records of a file through the pipeline, returning
+	/// the number of records.
+	///
+	/// The onset of each block is that of its record for EDF+ files, and
+	/// follows from the record duration otherwise. Samples that match a
+	/// sentinel of the reader are NaN.
+	pub fn run<R: Read + Seek>(&mut self, reader: &mut Reader<R>) -> Result<usize> {
+		let hdr = reader.header().clone();
+		let indices = hdr.ordinary_signals();
+		let mut signals: Vec<StreamSignal> = indices
+			.iter()
+			.map(|&i| {
+				let signal = &hdr.signals[i];
+				StreamSignal {
+					label: signal.label.clone(),
+					physical_dimension: signal.physical_dimension.clone(),
+					physical_min: signal.physical_min.min(signal.physical_max),
+					physical_max: signal.physical_min.max(signal.physical_max),
+					samples_per_record: signal.samples_per_record,
+				}
+			})
+			.collect();
+		for transform in &mut self.transforms {
+			signals = transform.signals(&signals)?;
+		}
+		for sink in &mut self.sinks {
+			sink.begin(&hdr, &signals)?;
+		}
+
+		let duration = hdr.duration.as_secs_f64();
+		let len = hdr.effective_records_len().unwrap_or(0);
+		for i in 0..len {
+			let record = reader.read_record(i)?;
+			let mut block = Block {
+				onset: record.onset(&hdr)?.unwrap_or(i as f64 * duration),
+				duration,
+				signals: indices
+					.iter()
+					.map(|&s| {
+						let samples = record.samples(s).unwrap_or_default();
+						samples.iter().map(|&x| reader.to_physical(s, x)).collect()
+					})
+					.collect(),
+			};
+			for transform in &mut self.transforms {
+				block = transform.apply(block)?;
+			}
+			for sink in &mut self.sinks {
+				sink.write(&block)?;
+			}
+		}
+		for sink in &mut self.sinks {
+			sink.finish()?;
+		}
+		Ok(len)
+	}
+}
+
+/// Keeps the signals with the given labels, in the given order.
+///
+/// Labels are compared ignoring surrounding whitespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Select {
+	labels: Vec<String>,
+	indices: Vec<usize>,
+}
+
+impl Select {
+	pub fn new(labels: Vec<String>) -> Select {
+		Select {
+			labels,
+			indices: Vec::new(),
+		}
+	}
+}
+
+impl Transform for Select {
+	fn signals(&mut self, input: &[StreamSignal]) -> Result<Vec<StreamSignal>> {
+		self.indices = self
+			.labels
+			.iter()
+			.map(|label| find("select", input, label))
+			.collect::<Result<_>>()?;
+		Ok(self.indices.iter().map(|&i| input[i].clone()).collect())
+	}
+
+	fn apply(&mut self, mut block: Block) -> Result<Block> {
+		block.signals = self
+			.indices
+			.iter()
+			.map(|&i| block.signals[i].clone())
+			.collect();
+		Ok(block)
+	}
+}
+
+/// A signal derived as the difference of two others, e.g. `EEG Fp1-F3` from
+/// `EEG Fp1` and `EEG F3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "pipeline", derive(Deserialize), serde(deny_unknown_fields))]
+pub struct Derivation {
+	/// The label of the derived signal.
+	pub label: String,
+	pub positive: String,
+	pub negative: String,
+}
+
+/// Replaces the signals with bipolar derivations of them.
+///
+/// The two signals of a derivation must have the same number of samples per
+/// record and the same physical dimension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Montage {
+	derivations: Vec<Derivation>,
+	indices: Vec<(usize, usize)>,
+}
+
+impl Montage {
+	pub fn new(derivations: Vec<Derivation>) -> Montage {
+		Montage {
+			derivations,
+			indices: Vec::new(),
+		}
+	}
+}
+
+impl Transform for Montage {
+	fn signals(&mut self, input: &[StreamSignal]) -> Result<Vec<StreamSignal>> {
+		let mut output = Vec::new();
+		self.indices.clear();
+		for derivation in &self.derivations {
+			let positive = find("montage", input, &derivation.positive)?;
+			let negative = find("montage", input, &derivation.negative)?;
+			let (p, n) = (&input[positive], &input[negative]);
+			if p.samples_per_record != n.samples_per_record
+				|| p.physical_dimension.trim() != n.physical_dimension.trim()
+			{
+				return Err(stage_error(
+					"montage",
+					format!(
+						"\"{}\" and \"{}\" differ in rate or unit",
+						derivation.positive, derivation.negative
+					),
+				));
+			}
+			self.indices.push((positive, negative));
+			output.push(StreamSignal {
+				label: derivation.label.clone(),
+				physical_min: p.physical_min - n.physical_max,
+				physical_max: p.physical_max - n.physical_min,
+				..p.clone()
+			});
+		}
+		Ok(output)
+	}
+
+	fn apply(&mut self, block: Block) -> Result<Block> {
+		let signals = self
+			.indices
+			.iter()
+			.map(|&(p, n)| {
+				block.signals[p]
+					.iter()
+					.zip(&block.signals[n])
+					.map(|(p, n)| p - n)
+					.collect()
+			})
+			.collect();
+		Ok(Block { signals, ..block })
+	}
+}
+
+/// Lowers the sampling rate of every signal by an integer factor, averaging
+/// each run of that many samples.
+///
+/// The average is a crude low-pass filter, enough to keep slow signals from
+/// aliasing but not a substitute for proper filtering of EEG. The number of
+/// samples per record of each signal must be a multiple of the factor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decimate {
+	factor: usize,
+}
+
+impl Decimate {
+	/// # Panics
+	///
+	/// Panics if the factor is zero.
+	pub fn new(factor: usize) -> Decimate {
+		assert!(factor > 0, "invalid decimation factor 0");
+		Decimate { factor }
+	}
+}
+
+impl Transform for Decimate {
+	fn signals(&mut self, input: &[StreamSignal]) -> Result<Vec<StreamSignal>> {
+		input
+			.iter()
+			.map(|signal| {
+				if signal.samples_per_record % self.factor != 0 {
+					return Err(stage_error(
+						"decimate",
+						format!(
+							"{} samples of \"{}\" per record are not a multiple of {}",
+							signal.samples_per_record, signal.label, self.factor
+						),
+					));
+				}
+				Ok(StreamSignal {
+					samples_per_record: signal.samples_per_record / self.factor,
+					..signal.clone()
+				})
+			})
+			.collect()
+	}
+
+	fn apply(&mut self, mut block: Block) -> Result<Block> {
+		for samples in &mut block.signals {
+			*samples = samples
+				.chunks(self.factor)
+				.map(|chunk| chunk.iter().sum::<f64>() / chunk.len() as f64)
+				.collect();
+		}
+		Ok(block)
+	}
+}
+
+/// Writes the blocks as CSV, with a row per sample.
+///
+/// The first column is the time in seconds since the start of the
+/// recording, followed by a column per signal, headed by its label. All
+/// signals must have the same sampling rate.
+#[derive(Debug)]
+pub struct CsvSink<W> {
+	wtr: W,
+}
+
+impl<W: Write> CsvSink<W> {
+	pub fn new(wtr: W) -> CsvSink<W> {
+		CsvSink { wtr }
+	}
+}
+
+impl<W: Write> Sink for CsvSink<W> {
+	fn begin(&mut self, _: &Header, signals: &[StreamSignal]) -> Result<()> {
+		if signals
+			.windows(2)
+			.any(|pair| pair[0].samples_per_record != pair[1].samples_per_record)
+		{
+			return Err(stage_error(
+				"csv",
+				"the signals differ in sampling rate".to_string(),
+			));
+		}
+		write!(self.wtr, "time")?;
+		for signal in signals {
+			write!(self.wtr, ",{}", csv_field(signal.label.trim()))?;
+		}
+		writeln!(self.wtr)?;
+		Ok(())
+	}
+
+	fn write(&mut self, block: &Block) -> Result<()> {
+		let len = block.signals.first().map_or(0, Vec::len);
+		for i in 0..len {
+			write!(
+				self.wtr,
+				"{}",
+				block.onset + block.duration * i as f64 / len as f64
+			)?;
+			for samples in &block.signals {
+				write!(self.wtr, ",{}", samples[i])?;
+			}
+			writeln!(self.wtr)?;
+		}
+		Ok(())
+	}
+
+	fn finish(&mut self) -> Result<()> {
+		self.wtr.flush()?;
+		Ok(())
+	}
+}
+
+/// The digital range of the signals written by [`EdfSink`].
+const DIGITAL_MIN: i32 = -32768;
+const DIGITAL_MAX: i32 = 32767;
+/// The largest difference between the end of a block and the start of the
+/// next, in seconds, that [`EdfSink`] does not take as a gap.
+const TOLERANCE: f64 = 1e-6;
+
+/// Writes the blocks as a plain EDF file with 16-bit samples.
+///
+/// The identification fields and the start of the recording are copied from
+/// the file read, and each signal keeps the physical range given by the
+/// stages. Missing samples are written as the digital minimum. Plain EDF
+/// cannot hold gaps, so a block that does not start where the one before it
+/// ended is an error. The number of data records is written when the output
+/// is finished, which is why the output must be seekable.
+#[derive(Debug)]
+pub struct EdfSink<W> {
+	wtr: W,
+	/// The physical minimum and the units per digital step of each signal.
+	scales: Vec<(f64, f64)>,
+	next_onset: Option<f64>,
+	records_len: usize,
+}
+
+impl<W: Write + Seek> EdfSink<W> {
+	pub fn new(wtr: W) -> EdfSink<W> {
+		EdfSink {
+			wtr,
+			scales: Vec::new(),
+			next_onset: None,
+			records_len: 0,
+		}
+	}
+}
+
+impl<W: Write + Seek> Sink for EdfSink<W> {
+	fn begin(&mut self, hdr: &Header, signals: &[StreamSignal]) -> Result<()> {
+		let start = hdr.start_datetime;
+		if !(1985..=2084).contains(&start.year()) {
+			return Err(unrepresentable("startdate", start.date().to_string()));
+		}
+		let number = |field, value: f64| {
+			format_number(value).ok_or_else(|| unrepresentable(field, value.to_string()))
+		};
+		let mut header = Vec::with_capacity(256 * (signals.len() + 1));
+		header_field(&mut header, "version", "0", 8)?;
+		header_field(
+			&mut header,
+			"local patient identification",
+			&hdr.patient_info,
+			80,
+		)?;
+		header_field(
+			&mut header,
+			"local recording identification",
+			&hdr.recording_id,
+			80,
+		)?;
+		header_field(
+			&mut header,
+			"startdate",
+			&start.format("%d.%m.%y").to_string(),
+			8,
+		)?;
+		header_field(
+			&mut header,
+			"starttime",
+			&start.format("%H.%M.%S").to_string(),
+			8,
+		)?;
+		let size = (256 * (signals.len() + 1)).to_string();
+		header_field(&mut header, "number of bytes in header", &size, 8)?;
+		header_field(&mut header, "reserved", "", 44)?;
+		header_field(&mut header, "number of data records", "-1", 8)?;
+		let duration = number("duration of a data record", hdr.duration.as_secs_f64())?;
+		header_field(&mut header, "duration of a data record", &duration, 8)?;
+		header_field(
+			&mut header,
+			"number of signals",
+			&signals.len().to_string(),
+			4,
+		)?;
+		for signal in signals {
+			header_field(&mut header, "label", signal.label.trim(), 16)?;
+		}
+		for _ in signals {
+			header_field(&mut header, "transducer type", "", 80)?;
+		}
+		for signal in signals {
+			let unit = signal.physical_dimension.trim();
+			header_field(&mut header, "physical dimension", unit, 8)?;
+		}
+		// The written range is parsed back, so that samples are scaled by
+		// what a reader of the file will see.
+		let mut ranges = Vec::with_capacity(signals.len());
+		for signal in signals {
+			let min = number("physical minimum", signal.physical_min)?;
+			header_field(&mut header, "physical minimum", &min, 8)?;
+			ranges.push(min.parse::<f64>().unwrap_or(signal.physical_min));
+		}
+		for (signal, range) in signals.iter().zip(&mut ranges) {
+			let max = number("physical maximum", signal.physical_max)?;
+			header_field(&mut header, "physical maximum", &max, 8)?;
+			let max = max.parse::<f64>().unwrap_or(signal.physical_max);
+			let gain = (max - *range) / (DIGITAL_MAX - DIGITAL_MIN) as f64;
+			self.scales.push((*range, gain));
+		}
+		for _ in signals {
+			header_field(&mut header, "digital minimum", &DIGITAL_MIN.to_string(), 8)?;
+		}
+		for _ in signals {
+			header_field(&mut header, "digital maximum", &DIGITAL_MAX.to_string(), 8)?;
+		}
+		for _ in signals {
+			header_field(&mut header, "prefiltering", "", 80)?;
+		}
+		for signal in signals {
+			let samples = signal.samples_per_record.to_string();
+			header_field(
+				&mut header,
+				"number of samples in each data record",
+				&samples,
+				8,
+			)?;
+		}
+		for _ in signals {
+			header_field(&mut header, "reserved", "", 32)?;
+		}
+		self.wtr.write_all(&header)?;
+		Ok(())
+	}
+
+	fn write(&mut self, block: &Block) -> Result<()> {
+		if let Some(next) = self.next_onset {
+			if (block.onset - next).abs() > TOLERANCE {
+				return Err(stage_error(
+					"edf",
+					format!("the records have a gap at {} s", next),
+				));
+			}
+		}
+		self.next_onset = Some(block.onset + block.duration);
+		let mut record = Vec::new();
+		for (samples, &(min, gain)) in block.signals.iter().zip(&self.scales) {
+			for &sample in samples {
+				let digital = if sample.is_nan() || gain == 0.0 {
+					DIGITAL_MIN
+				} else {
+					let steps = ((sample - min) / gain).round() as i32;
+					steps
+						.saturating_add(DIGITAL_MIN)
+						.clamp(DIGITAL_MIN, DIGITAL_MAX)
+				};
+				record.extend_from_slice(&(digital as i16).to_le_bytes());
+			}
+		}
+		self.wtr.write_all(&record)?;
+		self.records_len += 1;
+		Ok(())
+	}
+
+	fn finish(&mut self) -> Result<()> {
+		let mut records_len = Vec::new();
+		let value = self.records_len.to_string();
+		header_field(&mut records_len, "number of data records", &value, 8)?;
+		self.wtr.seek(SeekFrom::Start(236))?;
+		self.wtr.write_all(&records_len)?;
+		self.wtr.seek(SeekFrom::End(0))?;
+		self.wtr.flush()?;
+		Ok(())
+	}
+}
+
+/// When [`SplitEdfSink`] starts a new file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitLimit {
+	/// The largest size of a file in bytes.
+	Size(u64),
+	/// The longest duration of a file.
+	Duration(Duration),
+}
+
+/// Writes the blocks as a session of plain EDF files, starting a new one
+/// whenever the current one reaches a size or duration limit.
+///
+/// The files are named after the given path with a number starting at 1,
+/// e.g. `night_001.edf` and `night_002.edf` for `night.edf`, and are
+/// written as by [`EdfSink`]. Each file holds as many whole data records as
+/// fit the limit, and starts when its first data record does, rounded down
+/// to the second. A gap between blocks also starts a new file, as plain EDF
+/// cannot hold one.
+#[derive(Debug)]
+pub struct SplitEdfSink {
+	path: PathBuf,
+	limit: SplitLimit,
+	hdr: Option<Header>,
+	signals: Vec<StreamSignal>,
+	records_per_file: usize,
+	current: Option<EdfSink<BufWriter<File>>>,
+	/// The number of files started.
+	files_len: usize,
+	/// The number of data records in the current file.
+	records_len: usize,
+	next_onset: Option<f64>,
+}
+
+impl SplitEdfSink {
+	pub fn new<P: Into<PathBuf>>(path: P, limit: SplitLimit) -> SplitEdfSink {
+		SplitEdfSink {
+			path: path.into(),
+			limit,
+			hdr: None,
+			signals: Vec::new(),
+			records_per_file: 0,
+			current: None,
+			files_len: 0,
+			records_len: 0,
+			next_onset: None,
+		}
+	}
+
+	/// Finishes the current file and starts the next, whose first data
+	/// record starts at the given onset.
+	fn start_file(&mut self, onset: f64) -> Result<()> {
+		if let Some(mut current) = self.current.take() {
+			current.finish()?;
+		}
+		let mut hdr = self.hdr.clone().expect("the output was begun");
+		hdr.start_datetime = chrono::Duration::from_std(Duration::from_secs_f64(onset))
+			.ok()
+			.and_then(|onset| hdr.start_datetime.checked_add_signed(onset))
+			.ok_or_else(|| unrepresentable("startdate", onset.to_string()))?;
+		self.files_len += 1;
+		let path = numbered_path(&self.path, self.files_len);
+		let mut sink = EdfSink::new(BufWriter::new(File::create(path)?));
+		sink.begin(&hdr, &self.signals)?;
+		self.current = Some(sink);
+		self.records_len = 0;
+		Ok(())
+	}
+}
+
+impl Sink for SplitEdfSink {
+	fn begin(&mut self, hdr: &Header, signals: &[StreamSignal]) -> Result<()> {
+		let bytes_per_record: usize = signals.iter().map(|s| 2 * s.samples_per_record).sum();
+		let duration = hdr.duration.as_secs_f64();
+		self.records_per_file = match self.limit {
+			_ if bytes_per_record == 0 || duration == 0.0 => usize::MAX,
+			SplitLimit::Size(size) => {
+				let header_size = 256 * (signals.len() as u64 + 1);
+				(size.saturating_sub(header_size) / bytes_per_record as u64) as usize
+			}
+			SplitLimit::Duration(limit) => (limit.as_secs_f64() / duration + TOLERANCE) as usize,
+		};
+		if self.records_per_file == 0 {
+			return Err(stage_error(
+				"edf",
+				"the limit does not fit a data record".to_string(),
+			));
+		}
+		self.hdr = Some(hdr.clone());
+		self.signals = signals.to_vec();
+		// The first file is written even if there are no blocks, as by
+		// `EdfSink`.
+		self.start_file(0.0)
+	}
+
+	fn write(&mut self, block: &Block) -> Result<()> {
+		let gap = self
+			.next_onset
+			.is_some_and(|next| (block.onset - next).abs() > TOLERANCE);
+		if gap || self.records_len == self.records_per_file {
+			self.start_file(block.onset)?;
+		}
+		self.next_onset = Some(block.onset + block.duration);
+		self.current
+			.as_mut()
+			.expect("the output was begun")
+			.write(block)?;
+		self.records_len += 1;
+		Ok(())
+	}
+
+	fn finish(&mut self) -> Result<()> {
+		match self.current.take() {
+			Some(mut current) => current.finish(),
+			None => Ok(()),
+		}
+	}
+}
+
+/// The path of a file of a split output, e.g. `night_002.edf` for the
+/// second file of `night.edf`.
+fn numbered_path(path: &Path, n: usize) -> PathBuf {
+	let mut name = path.file_stem().unwrap_or_default().to_os_string();
+	name.push(format!("_{:03}", n));
+	if let Some(extension) = path.extension() {
+		name.push(".");
+		name.push(extension);
+	}
+	path.with_file_name(name)
+}
+
+/// The number of rows [`ParquetSink`] buffers before writing a row group.
+#[cfg(feature = "parquet")]
+const ROW_GROUP_LEN: usize = 1 << 16;
+
+/// Writes the blocks as Parquet, with a row per sample.
+///
+/// The columns are those of [`CsvSink`]: the time in seconds since the
+/// start of the recording, followed by a column of doubles per signal, named
+/// by its label. All signals must have the same sampling rate. Rows are
+/// written in row groups of 65536.
+#[cfg(feature = "parquet")]
+pub struct ParquetSink<W: Write + Send> {
+	wtr: Option<W>,
+	file: Option<SerializedFileWriter<W>>,
+	columns: Vec<Vec<f64>>,
+}
+
+#[cfg(feature = "parquet")]
+impl<W: Write + Send> ParquetSink<W> {
+	pub fn new(wtr: W) -> ParquetSink<W> {
+		ParquetSink {
+			wtr: Some(wtr),
+			file: None,
+			columns: Vec::new(),
+		}
+	}
+
+	/// Writes the buffered rows as a row group.
+	fn flush_rows(&mut self) -> Result<()> {
+		let file = match self.file {
+			Some(ref mut file) => file,
+			None => return Ok(()),
+		};
+		if self.columns.iter().all(Vec::is_empty) {
+			return Ok(());
+		}
+		let mut group = file.next_row_group()?;
+		for values in &mut self.columns {
+			let mut column = group
+				.next_column()?
+				.expect("the schema has a column per signal");
+			column
+				.typed::<DoubleType>()
+				.write_batch(values, None, None)?;
+			column.close()?;
+			values.clear();
+		}
+		group.close()?;
+		Ok(())
+	}
+}
+
+#[cfg(feature = "parquet")]
+impl<W: Write + Send> Sink for ParquetSink<W> {
+	fn begin(&mut self, _: &Header, signals: &[StreamSignal]) -> Result<()> {
+		if signals
+			.windows(2)
+			.any(|pair| pair[0].samples_per_record != pair[1].samples_per_record)
+		{
+			return Err(stage_error(
+				"parquet",
+				"the signals differ in sampling rate".to_string(),
+			));
+		}
+		let wtr = match self.wtr.take() {
+			Some(wtr) => wtr,
+			None => return Err(stage_error("parquet", "the output was begun".to_string())),
+		};
+		let names = std::iter::once("time").chain(signals.iter().map(|s| s.label.trim()));
+		let fields = names
+			.map(|name| {
+				let column = Type::primitive_type_builder(name, PhysicalType::DOUBLE)
+					.with_repetition(Repetition::REQUIRED)
+					.build()?;
+				Ok(Arc::new(column))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		let schema = Type::group_type_builder("edf")
+			.with_fields(fields)
+			.build()?;
+		let properties = Arc::new(WriterProperties::builder().build());
+		self.file = Some(SerializedFileWriter::new(
+			wtr,
+			Arc::new(schema),
+			properties,
+		)?);
+		self.columns = vec![Vec::new(); signals.len() + 1];
+		Ok(())
+	}
+
+	fn write(&mut self, block: &Block) -> Result<()> {
+		let len = block.signals.first().map_or(0, Vec::len);
+		self.columns[0]
+			.extend((0..len).map(|i| block.onset + block.duration * i as f64 / len as f64));
+		for (column, samples) in self.columns[1..].iter_mut().zip(&block.signals) {
+			column.extend_from_slice(samples);
+		}
+		if self.columns[0].len() >= ROW_GROUP_LEN {
+			self.flush_rows()?;
+		}
+		Ok(())
+	}
+
+	fn finish(&mut self) -> Result<()> {
+		self.flush_rows()?;
+		if let Some(file) = self.file.take() {
+			file.close()?;
+		}
+		Ok(())
+	}
+}
+
+/// Quotes a CSV field if it holds a comma, a quote or a line break.
+fn csv_field(s: &str) -> String {
+	if s.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", s.replace('"', "\"\""))
+	} else {
+		s.to_string()
+	}
+}
+
+/// Writes a header field, padded with spaces, failing if the value is not
+/// ASCII or does not fit.
+pub(crate) fn header_field(
+	header: &mut Vec<u8>,
+	field: &'static str,
+	value: &str,
+	width: usize,
+) -> Result<()> {
+	if value.len() > width || !value.is_ascii() {
+		return Err(unrepresentable(field, format!("\"{}\"", value)));
+	}
+	header.extend_from_slice(value.as_bytes());
+	header.resize(header.len() + width - value.len(), b' ');
+	Ok(())
+}
+
+/// Finds the signal with the given label.
+fn find(stage: &'static str, signals: &[StreamSignal], label: &str) -> Result<usize> {
+	signals
+		.iter()
+		.position(|s| s.label.trim() == label.trim())
+		.ok_or_else(|| stage_error(stage, format!("no signal labeled \"{}\"", label)))
+}
+
+fn stage_error(stage: &'static str, message: String) -> Error {
+	Error::new(ErrorKind::Pipeline { stage, message })
+}
+
+/// A pipeline written in TOML, for the `pipeline` command.
+///
+/// The input file is followed by a table per stage, applied in order, and a
+/// table per output:
+///
+/// ```toml
+/// input = "sleep.edf"
+///
+/// [[stage]]
+/// type = "montage"
+/// derivations = [{ label = "EEG Fp1-F3", positive = "EEG Fp1", negative = "EEG F3" }]
+///
+/// [[stage]]
+/// type = "decimate"
+/// factor = 2
+///
+/// [[output]]
+/// type = "csv"
+/// path = "sleep.csv"
+/// ```
+///
+/// The stages are `select`, with a list of `labels`, `montage` and
+/// `decimate`. The outputs are `csv`, `edf` and, with the `parquet` feature,
+/// `parquet`. An `edf` output with a `max_size` in bytes or a
+/// `max_duration` in seconds is split into numbered files, as by
+/// [`SplitEdfSink`].
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+	pub input: PathBuf,
+	#[serde(rename = "stage", default)]
+	pub stages: Vec<StageConfig>,
+	#[serde(rename = "output", default)]
+	pub outputs: Vec<OutputConfig>,
+}
+
+/// A transform of a [`PipelineConfig`].
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum StageConfig {
+	Select { labels: Vec<String> },
+	Montage { derivations: Vec<Derivation> },
+	Decimate { factor: usize },
+}
+
+/// A sink of a [`PipelineConfig`].
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum OutputConfig {
+	Csv {
+		path: PathBuf,
+	},
+	Edf {
+		path: PathBuf,
+		max_size: Option<u64>,
+		max_duration: Option<u64>,
+	},
+	#[cfg(feature = "parquet")]
+	Parquet {
+		path: PathBuf,
+	},
+}
+
+#[cfg(feature = "pipeline")]
+impl PipelineConfig {
+	/// Parses a pipeline from TOML.
+	pub fn from_toml(s: &str) -> Result<PipelineConfig> {
+		toml::from_str(s).map_err(|err| Error::new(ErrorKind::PipelineConfig(err)))
+	}
+
+	/// Reads a pipeline from a TOML file.
+	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<PipelineConfig> {
+		PipelineConfig::from_toml(&fs::read_to_string(path)?)
+	}
+
+	/// Builds the pipeline, creating its output files.
+	pub fn build(&self) -> Result<Pipeline> {
+		let mut pipeline = Pipeline::new();
+		for stage in &self.stages {
+			match *stage {
+				StageConfig::Select { ref labels } => {
+					pipeline.transform(Select::new(labels.clone()))
+				}
+				StageConfig::Montage { ref derivations } => {
+					pipeline.transform(Montage::new(derivations.clone()))
+				}
+				StageConfig::Decimate { factor: 0 } => {
+					return Err(stage_error("decimate", "the factor is 0".to_string()))
+				}
+				StageConfig::Decimate { factor } => pipeline.transform(Decimate::new(factor)),
+			};
+		}
+		for output in &self.outputs {
+			match *output {
+				OutputConfig::Csv { ref path } => {
+					pipeline.sink(CsvSink::new(BufWriter::new(File::create(path)?)))
+				}
+				OutputConfig::Edf {
+					ref path,
+					max_size,
+					max_duration,
+				} => match (max_size, max_duration) {
+					(None, None) => {
+						pipeline.sink(EdfSink::new(BufWriter::new(File::create(path)?)))
+					}
+					(Some(size), None) => {
+						pipeline.sink(SplitEdfSink::new(path, SplitLimit::Size(size)))
+					}
+					(None, Some(secs)) => pipeline.sink(SplitEdfSink::new(
+						path,
+						SplitLimit::Duration(Duration::from_secs(secs)),
+					)),
+					(Some(_), Some(_)) => {
+						return Err(stage_error(
+							"edf",
+							"max_size and max_duration cannot both be set".to_string(),
+						))
+					}
+				},
+				#[cfg(feature = "parquet")]
+				OutputConfig::Parquet { ref path } => {
+					pipeline.sink(ParquetSink::new(BufWriter::new(File::create(path)?)))
+				}
+			};
+		}
+		Ok(pipeline)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+	use std::io::{self, Write};
+	use std::rc::Rc;
+
+	use super::{
+		CsvSink, Decimate, Derivation, Montage, Pipeline, Select, SplitEdfSink, SplitLimit,
+	};
+	use crate::generator::{Generator, Pattern};
+	use crate::missing::Sentinel;
+	use crate::reader::Reader;
+
+	/// A sink that shares what it is given, since the pipeline owns it.
+	#[derive(Clone, Default)]
+	struct Shared(Rc<RefCell<Vec<u8>>>);
+
+	impl Write for Shared {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.borrow_mut().write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn stream_through_stages() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.channel("EEG Fp1", 4, Pattern::Noise)
+			.channel("EEG F3", 4, Pattern::Sine { frequency: 1.0 })
+			.channel("ECG", 2, Pattern::Noise)
+			.annotation(0.5, "Lights off")
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let fp1 = reader.read_signal(0).unwrap();
+		let f3 = reader.read_signal(1).unwrap();
+		let csv = Shared::default();
+		let len = Pipeline::new()
+			.transform(Montage::new(vec![Derivation {
+				label: "EEG Fp1-F3".to_string(),
+				positive: "EEG Fp1".to_string(),
+				negative: "EEG F3".to_string(),
+			}]))
+			.transform(Decimate::new(2))
+			.sink(CsvSink::new(csv.clone()))
+			.run(&mut reader)
+			.unwrap();
+		let csv = String::from_utf8(csv.0.take()).unwrap();
+		let lines: Vec<&str> = csv.lines().collect();
+
+		assert_eq!(len, 3);
+		assert_eq!(lines.len(), 7);
+		assert_eq!(lines[0], "time,EEG Fp1-F3");
+		let expected = ((fp1[2] - f3[2]) + (fp1[3] - f3[3])) / 2.0;
+		assert_eq!(lines[2], format!("0.5,{}", expected));
+		assert!(Pipeline::new()
+			.transform(Select::new(vec!["EEG C3".to_string()]))
+			.run(&mut reader)
+			.is_err());
+		assert!(Pipeline::new()
+			.sink(CsvSink::new(Vec::new()))
+			.run(&mut reader)
+			.is_err());
+
+		// Samples that match a sentinel of the reader are missing.
+		let first = reader.read_record(0).unwrap().samples(0).unwrap()[0];
+		let mut reader = Reader::options()
+			.sentinel(0, Sentinel::Value(first))
+			.from_bytes(&bytes)
+			.unwrap();
+		let csv = Shared::default();
+		Pipeline::new()
+			.transform(Select::new(vec!["EEG Fp1".to_string()]))
+			.sink(CsvSink::new(csv.clone()))
+			.run(&mut reader)
+			.unwrap();
+		let csv = String::from_utf8(csv.0.take()).unwrap();
+		assert_eq!(csv.lines().nth(1), Some("0,NaN"));
+	}
+
+	#[test]
+	fn split_output() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(5)
+			.channel("EEG Fpz-Cz", 4, Pattern::Noise)
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let start = reader.header().start_datetime;
+		let dir = std::env::temp_dir().join(format!("edf-split-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let limit = SplitLimit::Duration(std::time::Duration::from_secs(2));
+		Pipeline::new()
+			.sink(SplitEdfSink::new(dir.join("night.edf"), limit))
+			.run(&mut reader)
+			.unwrap();
+		let headers: Vec<_> = (1..=3)
+			.map(|n| {
+				let path = dir.join(format!("night_{:03}.edf", n));
+				Reader::from_path(path).unwrap().into_header()
+			})
+			.collect();
+		// The header alone takes 512 bytes, and a data record another 8.
+		let err = Pipeline::new()
+			.sink(SplitEdfSink::new(
+				dir.join("small.edf"),
+				SplitLimit::Size(519),
+			))
+			.run(&mut reader)
+			.unwrap_err();
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		let records: Vec<_> = headers.iter().map(|h| h.records_len).collect();
+		assert_eq!(records, [Some(2), Some(2), Some(1)]);
+		let offset = headers[2].start_datetime - start;
+		assert_eq!(offset.num_seconds(), 4);
+		assert_eq!(err.to_string(), "edf: the limit does not fit a data record");
+	}
+
+	#[cfg(feature = "pipeline")]
+	#[test]
+	fn run_config() {
+		use super::PipelineConfig;
+
+		let dir = std::env::temp_dir().join(format!("edf-pipeline-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let (input, output) = (dir.join("in.edf"), dir.join("out.edf"));
+		Generator::new()
+			.records(3)
+			.channel("EEG Fp1", 4, Pattern::Noise)
+			.channel("EEG F3", 4, Pattern::Sine { frequency: 1.0 })
+			.annotation(0.5, "Lights off")
+			.to_path(&input)
+			.unwrap();
+		let config = PipelineConfig::from_toml(&format!(
+			"input = {:?}\n\
+			 [[stage]]\ntype = \"montage\"\n\
+			 derivations = [{{ label = \"Fp1-F3\", positive = \"EEG Fp1\", negative = \"EEG F3\" }}]\n\
+			 [[output]]\ntype = \"edf\"\npath = {:?}\n",
+			input, output
+		))
+		.unwrap();
+		let mut reader = Reader::from_path(&config.input).unwrap();
+		let fp1 = reader.read_signal(0).unwrap();
+		let f3 = reader.read_signal(1).unwrap();
+		let len = config.build().unwrap().run(&mut reader).unwrap();
+		let mut written = Reader::from_path(&output).unwrap();
+		let hdr = written.header().clone();
+		let derived = written.read_signal(0).unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(len, 3);
+		assert_eq!(hdr.records_len, Some(3));
+		assert_eq!(hdr.signals[0].label, "Fp1-F3");
+		assert_eq!(hdr.signals[0].physical_min, -2000.0);
+		assert_eq!(derived.len(), 12);
+		let step = 4000.0 / 65535.0;
+		for (i, sample) in derived.iter().enumerate() {
+			assert!((sample - (fp1[i] - f3[i])).abs() <= step);
+		}
+		assert!(
+			PipelineConfig::from_toml("input = \"in.edf\"\n[[output]]\ntype = \"xls\"\n").is_err()
+		);
+		let split = PipelineConfig::from_toml(
+			"input = \"in.edf\"\n[[output]]\ntype = \"edf\"\npath = \"out.edf\"\n\
+			 max_size = 1000000\nmax_duration = 3600\n",
+		)
+		.unwrap();
+		assert!(split.build().is_err());
+	}
+
+	#[cfg(feature = "parquet")]
+	#[test]
+	fn write_parquet() {
+		use parquet::file::reader::{FileReader, SerializedFileReader};
+
+		use super::ParquetSink;
+
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.channel("EEG Fp1", 4, Pattern::Noise)
+			.channel("EEG F3", 4, Pattern::Noise)
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		// The reader of the output needs a file, as the sink cannot share a
+		// buffer between threads.
+		let path = std::env::temp_dir().join(format!("edf-parquet-{}", std::process::id()));
+		Pipeline::new()
+			.sink(ParquetSink::new(std::fs::File::create(&path).unwrap()))
+			.run(&mut reader)
+			.unwrap();
+		let file = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+		let metadata = file.metadata().clone();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(metadata.file_metadata().num_rows(), 12);
+		assert_eq!(metadata.file_metadata().schema_descr().num_columns(), 3);
+		assert_eq!(
+			metadata.file_metadata().schema_descr().column(1).name(),
+			"EEG Fp1"
+		);
+	}
+}
@@ -0,0 +1,548 @@
+//! Downsampled previews of whole recordings, for drawing an overview
+//! without decoding every sample, and min/max pyramids for zooming in.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::reader::Reader;
+
+/// The range of the physical samples of every signal in each second of a
+/// recording.
+///
+/// A preview of a ten-hour recording holds 36000 pairs per signal however
+/// high its sampling rate, so a viewer can draw an overview strip from it
+/// at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preview {
+	pub channels: Vec<PreviewChannel>,
+}
+
+/// The preview of a single signal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewChannel {
+	pub label: String,
+	/// The smallest sample in each second. A second whose samples are all
+	/// missing is NaN.
+	pub min: Vec<f64>,
+	/// The largest sample in each second.
+	pub max: Vec<f64>,
+}
+
+impl Preview {
+	/// Computes the preview of every signal except annotation signals.
+	///
+	/// The data records are streamed, so memory use does not grow with the
+	/// length of the recording beyond the preview itself. Missing samples
+	/// are left out. Times assume the data records are contiguous. Signals
+	/// without a sampling rate, as in files whose data records have no
+	/// duration, have no seconds.
+	pub fn compute<R: Read + Seek>(reader: &mut Reader<R>) -> Result<Preview> {
+		let hdr = reader.header().clone();
+		let signals = hdr.ordinary_signals();
+		let rates: Vec<Option<f64>> = signals
+			.iter()
+			.map(|&i| hdr.sampling_rate(i).filter(|&rate| rate > 0.0))
+			.collect();
+		let mut channels: Vec<PreviewChannel> = signals
+			.iter()
+			.map(|&i| PreviewChannel {
+				label: hdr.signals[i].label.clone(),
+				min: Vec::new(),
+				max: Vec::new(),
+			})
+			.collect();
+		let mut counts = vec![0usize; signals.len()];
+
+		for i in 0..hdr.effective_records_len().unwrap_or(0) {
+			let record = reader.read_record(i)?;
+			for (c, &signal) in signals.iter().enumerate() {
+				let rate = match rates[c] {
+					Some(rate) => rate,
+					None => continue,
+				};
+				for &sample in &record.signals()[signal] {
+					let second = (counts[c] as f64 / rate) as usize;
+					channels[c].push(second, reader.to_physical(signal, sample));
+					counts[c] += 1;
+				}
+			}
+		}
+		Ok(Preview { channels })
+	}
+
+	/// Reads the preview of the file at the given path from its sidecar, the
+	/// path followed by `.preview`, or computes it if there is no sidecar or
+	/// the file was modified after it.
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Preview> {
+		let path = path.as_ref();
+		let sidecar = sidecar_path(path, "preview");
+		if is_fresh(&sidecar, path)? {
+			Preview::read(BufReader::new(File::open(sidecar)?))
+		} else {
+			Preview::compute(&mut Reader::from_path(path)?)
+		}
+	}
+
+	/// Writes the preview as text, one second per line.
+	///
+	/// The first line holds the labels of the signals, separated by tabs.
+	/// Each following line holds the minimum and maximum of each signal,
+	/// separated by a comma, with the signals separated by tabs.
+	pub fn write<W: Write>(&self, mut wtr: W) -> Result<()> {
+		let labels: Vec<&str> = self.channels.iter().map(|c| c.label.as_str()).collect();
+		writeln!(wtr, "{}", labels.join("\t"))?;
+		let seconds = self.channels.iter().map(|c| c.min.len()).max().unwrap_or(0);
+		for t in 0..seconds {
+			let ranges: Vec<String> = self
+				.channels
+				.iter()
+				.map(|c| match (c.min.get(t), c.max.get(t)) {
+					(Some(min), Some(max)) => format!("{},{}", min, max),
+					_ => String::new(),
+				})
+				.collect();
+			writeln!(wtr, "{}", ranges.join("\t"))?;
+		}
+		Ok(())
+	}
+
+	/// Reads a preview written by `write`.
+	pub fn read<R: BufRead>(rdr: R) -> Result<Preview> {
+		let mut lines = rdr.lines();
+		let labels = match lines.next() {
+			Some(line) => line?,
+			None => return Err(Error::new(ErrorKind::Preview { line: 1 })),
+		};
+		let mut channels: Vec<PreviewChannel> = labels
+			.split('\t')
+			.map(|label| PreviewChannel {
+				label: label.to_string(),
+				min: Vec::new(),
+				max: Vec::new(),
+			})
+			.collect();
+		for (i, line) in lines.enumerate() {
+			let line = line?;
+			let err = || Error::new(ErrorKind::Preview { line: i + 2 });
+			let ranges: Vec<&str> = line.split('\t').collect();
+			if ranges.len() != channels.len() {
+				return Err(err());
+			}
+			for (channel, range) in channels.iter_mut().zip(ranges) {
+				if range.is_empty() {
+					continue;
+				}
+				let (min, max) = range
+					.split_once(',')
+					.and_then(|(min, max)| Some((min.parse().ok()?, max.parse().ok()?)))
+					.ok_or_else(err)?;
+				channel.min.push(min);
+				channel.max.push(max);
+			}
+		}
+		Ok(Preview { channels })
+	}
+}
+
+/// The range of the physical samples of a signal in consecutive bins.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Envelope {
+	/// The smallest sample in each bin. A bin whose samples are all missing
+	/// is NaN.
+	pub min: Vec<f64>,
+	/// The largest sample in each bin.
+	pub max: Vec<f64>,
+}
+
+/// Envelopes of a signal at successively coarser resolutions, like the
+/// zoom levels of map tiles.
+///
+/// A viewer draws each screen from the coarsest level with at least one bin
+/// per pixel, so that zooming from a whole night down to single samples
+/// never reads more than a screenful of bins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pyramid {
+	pub label: String,
+	/// The number of samples in each bin of the finest level.
+	pub base: usize,
+	/// The levels, finest first. Each bin covers two bins of the level
+	/// before it, and the coarsest level has a single bin.
+	pub levels: Vec<Envelope>,
+}
+
+impl Pyramid {
+	/// Computes the pyramid of a signal, with `base` samples per bin at the
+	/// finest level.
+	///
+	/// Only the signal is read from each data record. Missing samples are
+	/// left out.
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at the given index or `base` is zero.
+	pub fn compute<R: Read + Seek>(
+		reader: &mut Reader<R>,
+		signal: usize,
+		base: usize,
+	) -> Result<Pyramid> {
+		assert!(base > 0, "bins must hold at least one sample");
+		let label = reader.header().signals[signal].label.clone();
+		let mut finest = PreviewChannel {
+			label,
+			min: Vec::new(),
+			max: Vec::new(),
+		};
+		let mut count = 0;
+		for i in 0..reader.header().effective_records_len().unwrap_or(0) {
+			let record = reader.read_record_channels(i, &[signal])?;
+			for &sample in &record.signals()[signal] {
+				finest.push(count / base, reader.to_physical(signal, sample));
+				count += 1;
+			}
+		}
+
+		let mut levels = vec![Envelope {
+			min: finest.min,
+			max: finest.max,
+		}];
+		while levels[levels.len() - 1].min.len() > 1 {
+			let level = &levels[levels.len() - 1];
+			// NaN is ignored by `min` and `max`, as when computing the bins.
+			let coarser = Envelope {
+				min: level
+					.min
+					.chunks(2)
+					.map(|c| c.iter().copied().fold(f64::NAN, f64::min))
+					.collect(),
+				max: level
+					.max
+					.chunks(2)
+					.map(|c| c.iter().copied().fold(f64::NAN, f64::max))
+					.collect(),
+			};
+			levels.push(coarser);
+		}
+		Ok(Pyramid {
+			label: finest.label,
+			base,
+			levels,
+		})
+	}
+
+	/// Reads the pyramid of a signal of the file at the given path from its
+	/// sidecar, or computes it if there is no sidecar for the signal and
+	/// `base`, or the file was modified after it.
+	///
+	/// The sidecar is the path followed by `.`, the index of the signal and
+	/// `.pyramid`, next to the `.preview` sidecar. A computed pyramid is
+	/// written to it for the next time, unless it cannot be, e.g. because
+	/// the directory is read-only.
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at the given index or `base` is zero.
+	pub fn load<P: AsRef<Path>>(path: P, signal: usize, base: usize) -> Result<Pyramid> {
+		let path = path.as_ref();
+		let sidecar = sidecar_path(path, &format!("{}.pyramid", signal));
+		if is_fresh(&sidecar, path)? {
+			let pyramid = Pyramid::read(BufReader::new(File::open(&sidecar)?))?;
+			if pyramid.base == base {
+				return Ok(pyramid);
+			}
+		}
+		let pyramid = Pyramid::compute(&mut Reader::from_path(path)?, signal, base)?;
+		let written = File::create(&sidecar).map_err(Error::from).and_then(|f| {
+			let mut wtr = BufWriter::new(f);
+			pyramid.write(&mut wtr)?;
+			Ok(wtr.flush()?)
+		});
+		if written.is_err() {
+			let _ = fs::remove_file(&sidecar);
+		}
+		Ok(pyramid)
+	}
+
+	/// Writes the pyramid as text.
+	///
+	/// The first line holds the label and the second the number of samples
+	/// in each bin of the finest level. Each following line holds a level,
+	/// finest first, as the minimum and maximum of each bin separated by a
+	/// comma, with the bins separated by tabs.
+	pub fn write<W: Write>(&self, mut wtr: W) -> Result<()> {
+		writeln!(wtr, "{}", self.label)?;
+		writeln!(wtr, "{}", self.base)?;
+		for level in &self.levels {
+			let bins: Vec<String> = level
+				.min
+				.iter()
+				.zip(&level.max)
+				.map(|(min, max)| format!("{},{}", min, max))
+				.collect();
+			writeln!(wtr, "{}", bins.join("\t"))?;
+		}
+		Ok(())
+	}
+
+	/// Reads a pyramid written by `write`.
+	///
+	/// Each level must have half as many bins as the one before it, rounded
+	/// up, down to a level of at most one bin.
+	pub fn read<R: BufRead>(rdr: R) -> Result<Pyramid> {
+		let mut lines = rdr.lines();
+		let mut next_line = |line| match lines.next() {
+			Some(text) => Ok(text?),
+			None => Err(Error::new(ErrorKind::Preview { line })),
+		};
+		let label = next_line(1)?;
+		let base = next_line(2)?
+			.parse()
+			.ok()
+			.filter(|&base| base > 0)
+			.ok_or_else(|| Error::new(ErrorKind::Preview { line: 2 }))?;
+		let mut levels: Vec<Envelope> = Vec::new();
+		while levels.last().is_none_or(|level| level.min.len() > 1) {
+			let line = levels.len() + 3;
+			let text = next_line(line)?;
+			let err = || Error::new(ErrorKind::Preview { line });
+			let mut level = Envelope::default();
+			for bin in text.split('\t').filter(|bin| !bin.is_empty()) {
+				let (min, max) = bin
+					.split_once(',')
+					.and_then(|(min, max)| Some((min.parse().ok()?, max.parse().ok()?)))
+					.ok_or_else(err)?;
+				level.min.push(min);
+				level.max.push(max);
+			}
+			if let Some(finer) = levels.last() {
+				if level.min.len() != finer.min.len().div_ceil(2) {
+					return Err(err());
+				}
+			}
+			levels.push(level);
+		}
+		if let Some(text) = lines.next() {
+			text?;
+			return Err(Error::new(ErrorKind::Preview {
+				line: levels.len() + 3,
+			}));
+		}
+		Ok(Pyramid {
+			label,
+			base,
+			levels,
+		})
+	}
+
+	/// The number of samples in each bin of a level.
+	pub fn samples_per_bin(&self, level: usize) -> usize {
+		self.base << level
+	}
+
+	/// The coarsest level whose bins hold at most the given number of
+	/// samples, e.g. the samples per pixel of a view.
+	pub fn level_for(&self, samples_per_bin: usize) -> usize {
+		(0..self.levels.len())
+			.rev()
+			.find(|&level| self.samples_per_bin(level) <= samples_per_bin)
+			.unwrap_or(0)
+	}
+
+	/// Returns a tile of a level: the `index`th run of `width` bins. Tiles at
+	/// the end of a level are cut short.
+	pub fn tile(&self, level: usize, index: usize, width: usize) -> Envelope {
+		let envelope = match self.levels.get(level) {
+			Some(envelope) => envelope,
+			None => return Envelope::default(),
+		};
+		let len = envelope.min.len();
+		let start = index.saturating_mul(width).min(len);
+		let end = start.saturating_add(width).min(len);
+		Envelope {
+			min: envelope.min[start..end].to_vec(),
+			max: envelope.max[start..end].to_vec(),
+		}
+	}
+}
+
+/// Returns the path of a sidecar of a file: its path followed by a dot and
+/// the extension.
+pub(crate) fn sidecar_path(path: &Path, extension: &str) -> PathBuf {
+	let mut sidecar = path.as_os_str().to_owned();
+	sidecar.push(".");
+	sidecar.push(extension);
+	sidecar.into()
+}
+
+/// Whether a sidecar exists and was modified no earlier than its file.
+fn is_fresh(sidecar: &Path, path: &Path) -> Result<bool> {
+	let modified = match fs::metadata(sidecar) {
+		Ok(metadata) => metadata.modified()?,
+		Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+		Err(err) => return Err(err.into()),
+	};
+	Ok(modified >= fs::metadata(path)?.modified()?)
+}
+
+impl PreviewChannel {
+	/// Widens the range of a second to include a sample, starting a new
+	/// second if needed.
+	fn push(&mut self, second: usize, value: f64) {
+		while self.min.len() <= second {
+			self.min.push(f64::NAN);
+			self.max.push(f64::NAN);
+		}
+		// NaN is ignored by `min` and `max`, so missing samples are left out.
+		self.min[second] = self.min[second].min(value);
+		self.max[second] = self.max[second].max(value);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::{sidecar_path, Envelope, Preview, Pyramid};
+	use crate::error::ErrorKind;
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::Reader;
+
+	#[test]
+	fn preview_round_trip() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.duration(Duration::from_secs(2))
+			.channel("Spikes", 4, Pattern::Spikes { interval: 4.0 })
+			.channel("Sine", 2, Pattern::Sine { frequency: 0.25 })
+			.annotation(1.0, "Lights off")
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let preview = Preview::compute(&mut reader).unwrap();
+		let mut text = Vec::new();
+		preview.write(&mut text).unwrap();
+
+		assert_eq!(preview.channels.len(), 2);
+		assert_eq!(
+			preview.channels[0].max,
+			[1000.0, 0.0, 0.0, 0.0, 1000.0, 0.0]
+		);
+		assert_eq!(
+			preview.channels[1].min,
+			[0.0, 1000.0, 0.0, -1000.0, 0.0, 1000.0]
+		);
+		assert_eq!(Preview::read(text.as_slice()).unwrap(), preview);
+		assert!(Preview::read(&b"A\tB\n1,2\n"[..]).is_err());
+
+		// Without a record duration, the signals have no sampling rate.
+		bytes[244..252].copy_from_slice(b"0       ");
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let preview = Preview::compute(&mut reader).unwrap();
+		assert_eq!(preview.channels.len(), 2);
+		assert!(preview.channels.iter().all(|c| c.min.is_empty()));
+	}
+
+	#[test]
+	fn load_sidecar() {
+		let path = std::env::temp_dir().join(format!("edf-preview-{}.edf", std::process::id()));
+		let sidecar = sidecar_path(&path, "preview");
+		Generator::new()
+			.records(2)
+			.channel("Sine", 2, Pattern::Sine { frequency: 0.25 })
+			.to_path(&path)
+			.unwrap();
+		let computed = Preview::load(&path).unwrap();
+		std::fs::write(&sidecar, "Sidecar\n-1,1\n").unwrap();
+		let loaded = Preview::load(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		std::fs::remove_file(&sidecar).unwrap();
+
+		assert_eq!(computed.channels[0].label, "Sine");
+		assert_eq!(computed.channels[0].min.len(), 2);
+		assert_eq!(loaded.channels[0].label, "Sidecar");
+		assert_eq!(loaded.channels[0].max, [1.0]);
+	}
+
+	#[test]
+	fn pyramid_levels_and_tiles() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.channel("Noise", 4, Pattern::Noise)
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let samples = reader.read_signal(0).unwrap();
+		let pyramid = Pyramid::compute(&mut reader, 0, 2).unwrap();
+		let lengths: Vec<usize> = pyramid.levels.iter().map(|l| l.min.len()).collect();
+		let fold =
+			|samples: &[f64], f: fn(f64, f64) -> f64| samples.iter().copied().fold(f64::NAN, f);
+
+		assert_eq!(lengths, [6, 3, 2, 1]);
+		assert_eq!(pyramid.levels[0].max[1], fold(&samples[2..4], f64::max));
+		assert_eq!(pyramid.levels[3].min, [fold(&samples, f64::min)]);
+		assert_eq!(pyramid.level_for(5), 1);
+		assert_eq!(pyramid.level_for(1), 0);
+		let tile = pyramid.tile(1, 1, 2);
+		assert_eq!(tile.max, [fold(&samples[8..], f64::max)]);
+	}
+
+	#[test]
+	fn pyramid_round_trip() {
+		let pyramid = Pyramid {
+			label: "EEG Fpz-Cz".to_string(),
+			base: 4,
+			levels: vec![
+				Envelope {
+					min: vec![-1.5, f64::NAN, 2.0],
+					max: vec![1.5, f64::NAN, 3.0],
+				},
+				Envelope {
+					min: vec![-1.5, 2.0],
+					max: vec![1.5, 3.0],
+				},
+				Envelope {
+					min: vec![-1.5],
+					max: vec![3.0],
+				},
+			],
+		};
+		let mut text = Vec::new();
+		pyramid.write(&mut text).unwrap();
+		let read = Pyramid::read(text.as_slice()).unwrap();
+
+		assert_eq!(
+			String::from_utf8(text).unwrap(),
+			"EEG Fpz-Cz\n4\n-1.5,1.5\tNaN,NaN\t2,3\n-1.5,1.5\t2,3\n-1.5,3\n"
+		);
+		assert_eq!(read.label, pyramid.label);
+		assert_eq!(read.levels[0].min[0], -1.5);
+		assert!(read.levels[0].max[1].is_nan());
+		assert_eq!(read.levels[1..], pyramid.levels[1..]);
+		assert!(Pyramid::read(&b"A\n4\n1,2\t3,4\n1,4\t5,6\n"[..]).is_err());
+		assert!(Pyramid::read(&b"A\n0\n1,2\n"[..]).is_err());
+		// A pyramid cut short, or followed by more levels, is not complete.
+		assert!(Pyramid::read(&b"A\n4\n1,2\t3,4\n"[..]).is_err());
+		assert!(Pyramid::read(&b"A\n4\n1,2\n1,2\n"[..]).is_err());
+		let err = Pyramid::read(&b"A\n4\n1,2\t3,4\n\xff\n"[..]).unwrap_err();
+		assert!(matches!(err.kind(), ErrorKind::Io(_)));
+
+		let path = std::env::temp_dir().join(format!("edf-pyramid-{}.edf", std::process::id()));
+		let sidecar = sidecar_path(&path, "0.pyramid");
+		Generator::new()
+			.records(3)
+			.channel("Noise", 4, Pattern::Noise)
+			.to_path(&path)
+			.unwrap();
+		let computed = Pyramid::load(&path, 0, 2).unwrap();
+		let cached = Pyramid::read(std::fs::read(&sidecar).unwrap().as_slice()).unwrap();
+		let loaded = Pyramid::load(&path, 0, 2).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		std::fs::remove_file(&sidecar).unwrap();
+
+		assert_eq!(cached, computed);
+		assert_eq!(loaded, computed);
+	}
+}
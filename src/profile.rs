@@ -0,0 +1,230 @@
+//! Validation of files against an expected montage.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::reader::Header;
+use crate::unit::Unit;
+
+/// The signals a study protocol requires, e.g. for a PSG montage.
+///
+/// Profiles are written in TOML, with a table per required signal:
+///
+/// ```toml
+/// [[signal]]
+/// label = "EEG Fpz-Cz"
+/// rate = 100.0
+/// unit = "uV"
+///
+/// [[signal]]
+/// label = "Resp oro-nasal"
+/// ```
+///
+/// The rate (in Hz) and unit are optional. Signals that are not listed are
+/// allowed.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+	#[serde(rename = "signal", default)]
+	pub signals: Vec<SignalProfile>,
+}
+
+/// A signal required by a profile.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignalProfile {
+	/// The label of the signal, compared ignoring surrounding whitespace.
+	pub label: String,
+	/// The sampling rate in Hz.
+	pub rate: Option<f64>,
+	/// The physical dimension, compared as a unit, so that `uV` matches
+	/// `µV`.
+	pub unit: Option<String>,
+}
+
+/// A way in which a file deviates from a profile.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Deviation {
+	/// A required signal is not in the file.
+	MissingSignal { label: String },
+	/// A signal has a different sampling rate.
+	Rate {
+		label: String,
+		expected: f64,
+		actual: f64,
+	},
+	/// A signal has a different physical dimension.
+	Unit {
+		label: String,
+		expected: String,
+		actual: String,
+	},
+}
+
+impl Profile {
+	/// Parses a profile from TOML.
+	pub fn from_toml(s: &str) -> Result<Profile> {
+		toml::from_str(s).map_err(|err| Error::new(ErrorKind::Profile(err)))
+	}
+
+	/// Reads a profile from a TOML file.
+	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Profile> {
+		Profile::from_toml(&fs::read_to_string(path)?)
+	}
+
+	/// Lists the ways in which a header deviates from the profile.
+	///
+	/// An empty list means the file conforms.
+	pub fn validate(&self, hdr: &Header) -> Vec<Deviation> {
+		let mut deviations = Vec::new();
+		for expected in &self.signals {
+			let label = expected.label.trim();
+			let signal = match hdr.signals.iter().find(|s| s.label.trim() == label) {
+				Some(signal) => signal,
+				None => {
+					deviations.push(Deviation::MissingSignal {
+						label: label.to_string(),
+					});
+					continue;
+				}
+			};
+			if let Some(rate) = expected.rate {
+				let actual = signal.sampling_rate(hdr.duration).unwrap_or(0.0);
+				if (actual - rate).abs() > 1e-9 * rate.abs().max(1.0) {
+					deviations.push(Deviation::Rate {
+						label: label.to_string(),
+						expected: rate,
+						actual,
+					});
+				}
+			}
+			if let Some(ref unit) = expected.unit {
+				if signal.unit() != Unit::parse(unit) {
+					deviations.push(Deviation::Unit {
+						label: label.to_string(),
+						expected: unit.clone(),
+						actual: signal.physical_dimension.clone(),
+					});
+				}
+			}
+		}
+		deviations
+	}
+}
+
+impl fmt::Display for Deviation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Deviation::MissingSignal { ref label } => write!(f, "missing signal {}", label),
+			Deviation::Rate {
+				ref label,
+				expected,
+				actual,
+			} => write!(
+				f,
+				"{}: sampling rate is {} Hz, expected {} Hz",
+				label, actual, expected
+			),
+			Deviation::Unit {
+				ref label,
+				ref expected,
+				ref actual,
+			} => write!(
+				f,
+				"{}: unit is \"{}\", expected \"{}\"",
+				label, actual, expected
+			),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use chrono::NaiveDate;
+
+	use super::{Deviation, Profile};
+	use crate::reader::{FileFormat, Header};
+	use crate::signal::SignalHeader;
+
+	fn signal(label: &str, unit: &str, samples_per_record: usize) -> SignalHeader {
+		SignalHeader {
+			label: label.to_string(),
+			transducer: String::new(),
+			physical_dimension: unit.to_string(),
+			physical_min: -500.0,
+			physical_max: 500.0,
+			digital_min: -2048,
+			digital_max: 2047,
+			prefiltering: String::new(),
+			samples_per_record,
+			reserved: String::new(),
+		}
+	}
+
+	#[test]
+	fn validate() {
+		let profile = Profile::from_toml(
+			r#"
+			[[signal]]
+			label = "EEG Fpz-Cz"
+			rate = 100.0
+			unit = "uV"
+
+			[[signal]]
+			label = "EOG horizontal"
+			rate = 100.0
+
+			[[signal]]
+			label = "EMG submental"
+			"#,
+		)
+		.unwrap();
+		let mut hdr = Header::new(
+			FileFormat::Edf,
+			String::new(),
+			String::new(),
+			NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(),
+			Default::default(),
+			768,
+			String::new(),
+			Some(1),
+			Duration::from_secs(30),
+			2,
+		);
+		hdr.signals = vec![
+			signal("EEG Fpz-Cz", "mV", 3000),
+			signal("EOG horizontal", "uV", 30),
+		];
+
+		let deviations = profile.validate(&hdr);
+		assert_eq!(
+			deviations,
+			[
+				Deviation::Unit {
+					label: "EEG Fpz-Cz".to_string(),
+					expected: "uV".to_string(),
+					actual: "mV".to_string(),
+				},
+				Deviation::Rate {
+					label: "EOG horizontal".to_string(),
+					expected: 100.0,
+					actual: 1.0,
+				},
+				Deviation::MissingSignal {
+					label: "EMG submental".to_string(),
+				},
+			]
+		);
+		assert_eq!(
+			deviations[1].to_string(),
+			"EOG horizontal: sampling rate is 1 Hz, expected 100 Hz"
+		);
+		assert!(Profile::from_toml("[[signal]]\nlabel = 1").is_err());
+	}
+}
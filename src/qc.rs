@@ -0,0 +1,237 @@
+//! Quality checks on the data records of a file.
+
+use std::fmt;
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::reader::{Header, Reader};
+use crate::record::Record;
+
+/// A run of consecutive data records with identical samples.
+///
+/// Some recorders keep writing the last record they received when the
+/// sensor feed drops, which shows up as such a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatedRun {
+	/// The indices of the records in the run.
+	pub records: Range<usize>,
+	/// The offset of the first record from the start, in seconds.
+	pub start: f64,
+	/// The offset of the end of the last record from the start, in seconds.
+	pub end: f64,
+}
+
+impl fmt::Display for RepeatedRun {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"records {} to {} are identical ({} s to {} s)",
+			self.records.start,
+			self.records.end - 1,
+			self.start,
+			self.end
+		)
+	}
+}
+
+/// Finds runs of two or more identical consecutive data records.
+///
+/// Annotation signals are ignored, since their time-keeping annotations
+/// differ between records even when the samples repeat. Times assume the
+/// records are contiguous. The records are streamed, keeping only the one
+/// before, so memory use does not grow with the length of the file.
+pub fn repeated_records<R: Read + Seek>(reader: &mut Reader<R>) -> Result<Vec<RepeatedRun>> {
+	let compared = reader.header().ordinary_signals();
+	let duration = reader.header().duration.as_secs_f64();
+	let mut runs = Vec::new();
+	let mut previous: Option<Record> = None;
+	let (mut first, mut len) = (0, 0);
+	for (i, record) in reader.records().enumerate() {
+		let record = record?;
+		let repeated = previous
+			.as_ref()
+			.is_some_and(|p| compared.iter().all(|&s| p.samples(s) == record.samples(s)));
+		if !repeated {
+			push_run(&mut runs, first..i, duration);
+			first = i;
+		}
+		previous = Some(record);
+		len = i + 1;
+	}
+	push_run(&mut runs, first..len, duration);
+	Ok(runs)
+}
+
+/// A signal whose physical range is implausible in its declared unit, along
+/// with the unit that would make it plausible.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitSuggestion {
+	/// The index of the signal.
+	pub signal: usize,
+	pub label: String,
+	/// The declared physical dimension.
+	pub unit: String,
+	/// The physical dimension the range was likely meant in.
+	pub suggested: &'static str,
+}
+
+impl fmt::Display for UnitSuggestion {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"signal {} ({}): range is implausible in {}, likely {} mislabeled as {}",
+			self.signal, self.label, self.unit, self.suggested, self.unit
+		)
+	}
+}
+
+/// Voltage units and their size in microvolts, in increasing order.
+const VOLTAGE_UNITS: [(&str, f64); 4] = [("nV", 1e-3), ("uV", 1.0), ("mV", 1e3), ("V", 1e6)];
+
+/// Finds signals whose physical range is implausible for their type, e.g. an
+/// EEG spanning ±3000 mV, and suggests the unit that was likely meant.
+///
+/// The type is inferred from the first word of the label, e.g. `EEG Fpz-Cz`.
+/// Only EEG, EOG, EMG and ECG signals with a voltage unit are checked.
+pub fn unit_suggestions(hdr: &Header) -> Vec<UnitSuggestion> {
+	let mut suggestions = Vec::new();
+	for (i, signal) in hdr.signals.iter().enumerate() {
+		let kind = signal.label.split_whitespace().next().unwrap_or("");
+		// The plausible largest absolute value of the range, in microvolts.
+		let plausible = match kind.to_ascii_uppercase().as_str() {
+			"EEG" | "EOG" | "EMG" => 10.0..=100_000.0,
+			"ECG" | "EKG" => 100.0..=1_000_000.0,
+			_ => continue,
+		};
+		let unit = signal.physical_dimension.trim().replace(['µ', 'μ'], "u");
+		let declared = match VOLTAGE_UNITS.iter().position(|&(u, _)| u == unit) {
+			Some(declared) => declared,
+			None => continue,
+		};
+		let range = signal.physical_min.abs().max(signal.physical_max.abs());
+		if plausible.contains(&(range * VOLTAGE_UNITS[declared].1)) {
+			continue;
+		}
+		// Prefer the unit closest to the declared one.
+		let mut candidates: Vec<usize> = (0..VOLTAGE_UNITS.len())
+			.filter(|&u| u != declared)
+			.collect();
+		candidates.sort_by_key(|&u| u.abs_diff(declared));
+		if let Some(u) = candidates
+			.into_iter()
+			.find(|&u| plausible.contains(&(range * VOLTAGE_UNITS[u].1)))
+		{
+			suggestions.push(UnitSuggestion {
+				signal: i,
+				label: signal.label.clone(),
+				unit: signal.physical_dimension.clone(),
+				suggested: VOLTAGE_UNITS[u].0,
+			});
+		}
+	}
+	suggestions
+}
+
+/// Adds a run of records to the runs if it has at least two, the duration
+/// of a record given in seconds.
+fn push_run(runs: &mut Vec<RepeatedRun>, records: Range<usize>, duration: f64) {
+	if records.len() >= 2 {
+		runs.push(RepeatedRun {
+			start: records.start as f64 * duration,
+			end: records.end as f64 * duration,
+			records,
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use chrono::NaiveDate;
+
+	use super::{repeated_records, unit_suggestions, RepeatedRun};
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::{FileFormat, Header, Reader};
+	use crate::signal::SignalHeader;
+
+	#[test]
+	fn find_repeated_records() {
+		let (mut sine, mut noise) = (Vec::new(), Vec::new());
+		// A 1 Hz sine sampled at 4 Hz repeats every record, while noise
+		// does not.
+		Generator::new()
+			.records(4)
+			.duration(Duration::from_secs(2))
+			.channel("Sine", 8, Pattern::Sine { frequency: 1.0 })
+			.annotation(1.0, "Lights off")
+			.write(&mut sine)
+			.unwrap();
+		Generator::new()
+			.channel("Noise", 8, Pattern::Noise)
+			.write(&mut noise)
+			.unwrap();
+		let sine = repeated_records(&mut Reader::from_bytes(&sine).unwrap()).unwrap();
+		let noise = repeated_records(&mut Reader::from_bytes(&noise).unwrap()).unwrap();
+
+		assert_eq!(
+			sine,
+			[RepeatedRun {
+				records: 0..4,
+				start: 0.0,
+				end: 8.0,
+			}]
+		);
+		assert_eq!(
+			sine[0].to_string(),
+			"records 0 to 3 are identical (0 s to 8 s)"
+		);
+		assert!(noise.is_empty());
+	}
+
+	#[test]
+	fn suggest_units() {
+		let mut hdr = Header::new(
+			FileFormat::Edf,
+			String::new(),
+			String::new(),
+			NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(),
+			Default::default(),
+			1024,
+			String::new(),
+			Some(1),
+			Duration::from_secs(1),
+			3,
+		);
+		for (label, unit, range) in [
+			("EEG Fpz-Cz", "mV", 3000.0),
+			("ECG", "uV", 5.0),
+			("EEG Pz-Oz", "uV", 200.0),
+		] {
+			hdr.signals.push(SignalHeader {
+				label: label.to_string(),
+				transducer: String::new(),
+				physical_dimension: unit.to_string(),
+				physical_min: -range,
+				physical_max: range,
+				digital_min: -32768,
+				digital_max: 32767,
+				prefiltering: String::new(),
+				samples_per_record: 100,
+				reserved: String::new(),
+			});
+		}
+
+		let suggestions = unit_suggestions(&hdr);
+		let suggested: Vec<_> = suggestions
+			.iter()
+			.map(|s| (s.signal, s.suggested))
+			.collect();
+		assert_eq!(suggested, [(0, "uV"), (1, "mV")]);
+		assert_eq!(
+			suggestions[0].to_string(),
+			"signal 0 (EEG Fpz-Cz): range is implausible in mV, likely uV mislabeled as mV"
+		);
+	}
+}
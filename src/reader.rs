@@ -1,33 +1,655 @@
+use crate::annotation::Annotation;
+use crate::crc::crc32;
 use crate::error::{Error, ErrorKind, HeaderError, Result};
-use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+use crate::identification::{PatientId, PatientInfo, RecordingId, RecordingInfo};
+use crate::missing::Sentinel;
+use crate::record::{decode_sample, Record};
+use crate::signal::SignalHeader;
+use crate::sniff::Format;
+use chrono::{
+	DateTime, Datelike, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike,
+};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::path::Path;
 use std::result;
+use std::str;
+use std::time::Duration;
 
-pub struct Reader;
+/// The largest data record the reader accepts, in bytes.
+///
+/// The spec recommends records of at most 61440 bytes, but high-rate BDF
+/// recordings routinely exceed that. The limit only guards against corrupt
+/// headers that would otherwise allocate gigabytes for a single record.
+pub const MAX_RECORD_SIZE: usize = 64 * 1024 * 1024;
+
+/// Builds a reader with various configuration knobs.
+#[derive(Debug, Clone, Default)]
+pub struct ReaderBuilder {
+	lenient: bool,
+	encoding: TextEncoding,
+	precedence: StartdatePrecedence,
+	sentinels: Vec<(usize, Sentinel)>,
+}
+
+impl ReaderBuilder {
+	/// Create a new builder for configuring how EDF files are read.
+	pub fn new() -> ReaderBuilder {
+		ReaderBuilder::default()
+	}
+
+	/// Whether to recover from non-conformant headers where possible.
+	///
+	/// In lenient mode, problems that have an unambiguous fix are recorded in
+	/// `Header::warnings` instead of failing:
+	///
+	/// - numbers padded with NUL bytes, or written with a plus sign or a
+	///   decimal comma;
+	/// - a startdate or starttime with other separators, e.g. `22:00:00`;
+	/// - text that cannot be decoded, which is then decoded as Latin-1;
+	/// - a header size that does not match the number of signals;
+	/// - a startdate that disagrees with the EDF+ startdate subfield, which
+	///   is resolved as set by `startdate_precedence`.
+	///
+	/// Strict mode, the default, fails on all of these.
+	pub fn lenient(&mut self, yes: bool) -> &mut ReaderBuilder {
+		self.lenient = yes;
+		self
+	}
+
+	/// How to decode the text fields of the header, such as the patient
+	/// identification and the signal labels.
+	///
+	/// The spec only allows printable ASCII, but many recorders write Latin-1
+	/// or stray bytes. This is `TextEncoding::Utf8` by default.
+	pub fn encoding(&mut self, encoding: TextEncoding) -> &mut ReaderBuilder {
+		self.encoding = encoding;
+		self
+	}
+
+	/// Which date to use when the startdate field disagrees with the EDF+
+	/// startdate subfield of the recording identification, in lenient mode.
+	///
+	/// This is `StartdatePrecedence::Subfield` by default, since the subfield
+	/// has a four-digit year.
+	pub fn startdate_precedence(&mut self, precedence: StartdatePrecedence) -> &mut ReaderBuilder {
+		self.precedence = precedence;
+		self
+	}
+
+	/// Treats a digital value of the signal at the given index as a missing
+	/// sample.
+	///
+	/// Missing samples are converted to NaN physical values. This can be
+	/// called several times to give a signal more than one sentinel.
+	pub fn sentinel(&mut self, signal: usize, sentinel: Sentinel) -> &mut ReaderBuilder {
+		self.sentinels.push((signal, sentinel));
+		self
+	}
+
+	/// Opens the file at the given path and reads its header.
+	pub fn from_path<P: AsRef<Path>>(&self, path: P) -> Result<Reader> {
+		self.from_reader(File::open(path)?)
+	}
+
+	/// Reads the header of a file held in memory.
+	pub fn from_bytes<'a>(&self, bytes: &'a [u8]) -> Result<Reader<Cursor<&'a [u8]>>> {
+		self.from_reader(Cursor::new(bytes))
+	}
+
+	/// Reads the header from any source that can seek, such as a
+	/// `Cursor<Vec<u8>>`.
+	///
+	/// The file must start at the start of the source, as offsets of data
+	/// records are taken from there.
+	pub fn from_reader<R: Read + Seek>(&self, mut rdr: R) -> Result<Reader<R>> {
+		let mut header = Reader::read_header(&mut rdr, self)?;
+		let record_size = header
+			.record_size()
+			.filter(|&size| size <= MAX_RECORD_SIZE)
+			.ok_or_else(|| {
+				Error::new(ErrorKind::Header(HeaderError::RecordTooLarge {
+					limit: MAX_RECORD_SIZE,
+				}))
+			})?;
+		header.inferred_records_len = infer_records_len(&mut rdr, &header, record_size)?;
+		let mut reader = Reader {
+			rdr,
+			header,
+			record_size,
+			sentinels: self.sentinels.clone(),
+		};
+		reader.read_start_offset();
+		Ok(reader)
+	}
+}
+
+/// Which of two disagreeing start dates a lenient reader uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartdatePrecedence {
+	/// The EDF+ startdate subfield of the recording identification.
+	#[default]
+	Subfield,
+	/// The startdate field.
+	Startdate,
+}
+
+/// How the text fields of the header are decoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextEncoding {
+	/// Fail on bytes that are not valid UTF-8. As ASCII is a subset of
+	/// UTF-8, this accepts every conformant file.
+	#[default]
+	Utf8,
+	/// Fail on any byte outside of ASCII, as the spec requires.
+	Ascii,
+	/// Decode each byte as a Latin-1 (ISO 8859-1) character. This never
+	/// fails.
+	Latin1,
+	/// Decode as UTF-8, replacing invalid bytes with U+FFFD. This never
+	/// fails.
+	Lossy,
+}
+
+impl TextEncoding {
+	/// Decodes a text field. The field name is used in errors.
+	fn decode(self, buffer: &[u8], field: &'static str) -> Result<String> {
+		match self {
+			TextEncoding::Utf8 => Ok(str::from_utf8(buffer)?.to_string()),
+			TextEncoding::Ascii if !buffer.is_ascii() => {
+				Err(Error::new(ErrorKind::Header(HeaderError::InvalidText {
+					field,
+					raw: buffer.to_vec(),
+				})))
+			}
+			TextEncoding::Ascii => Ok(str::from_utf8(buffer)?.to_string()),
+			TextEncoding::Latin1 => Ok(buffer.iter().map(|&b| char::from(b)).collect()),
+			TextEncoding::Lossy => Ok(String::from_utf8_lossy(buffer).into_owned()),
+		}
+	}
+}
+
+/// Reads the header and data records of an EDF or BDF file.
+///
+/// The source is a `File` by default, but it can be anything that
+/// implements `Read` and `Seek`.
+///
+/// A reader of a `File` is `Sync`. Its `_at` methods take `&self` and use
+/// positioned reads, so a shared reader can serve concurrent requests
+/// without locking.
+#[derive(Debug)]
+pub struct Reader<R = File> {
+	rdr: R,
+	header: Header,
+	/// The validated size of a data record in bytes.
+	record_size: usize,
+	/// The digital values that mark missing samples, by signal index.
+	sentinels: Vec<(usize, Sentinel)>,
+}
+
+impl<R> Reader<R> {
+	/// Returns the position in the file of the byte `offset` bytes into the
+	/// data record at the given index.
+	///
+	/// A position past what a file can hold is an `UnexpectedEof` error, as
+	/// there can be no such record.
+	fn record_offset(&self, index: usize, offset: usize) -> Result<u64> {
+		(index as u64)
+			.checked_mul(self.record_size as u64)
+			.and_then(|position| position.checked_add(self.header.size as u64))
+			.and_then(|position| position.checked_add(offset as u64))
+			.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into())
+	}
+
+	/// Converts a digital sample of a signal to its physical value, or NaN
+	/// if it is one of the signal's sentinels.
+	pub(crate) fn to_physical(&self, signal: usize, sample: i32) -> f64 {
+		let header = &self.header.signals[signal];
+		let missing = self
+			.sentinels
+			.iter()
+			.any(|&(i, sentinel)| i == signal && sentinel.matches(header, sample));
+		if missing {
+			f64::NAN
+		} else {
+			header.to_physical(sample)
+		}
+	}
+}
 
 impl Reader {
-	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Header> {
-		let f = File::open(path)?;
-		let hdr = Reader::read_header(&f)?;
-		Ok(hdr)
+	/// Returns a builder for configuring how a file is read, e.g.
+	/// `Reader::options().lenient(true).from_path(path)`.
+	pub fn options() -> ReaderBuilder {
+		ReaderBuilder::new()
+	}
+
+	/// Opens the file at the given path and reads its header, using the
+	/// default configuration.
+	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Reader> {
+		ReaderBuilder::new().from_path(path)
+	}
+
+	/// Reads a range of data records, e.g. `10..20` for the eleventh to the
+	/// twentieth.
+	///
+	/// This does not move the position of the file, so it can be called from
+	/// several threads at once. A range past the end of the file is an
+	/// `UnexpectedEof` error.
+	pub fn read_records_at(&self, records: Range<usize>) -> Result<Vec<Record>> {
+		let mut buffer = vec![0; self.record_size];
+		let records_len = self.header.effective_records_len().unwrap_or(0);
+		let mut decoded = Vec::with_capacity(records.len().min(records_len));
+		for i in records {
+			read_exact_at(&self.rdr, &mut buffer, self.record_offset(i, 0)?)?;
+			decoded.push(Record::decode(&self.header, &buffer));
+		}
+		Ok(decoded)
+	}
+
+	/// Reads the samples of a signal in a range of data records, converted
+	/// to physical values. Missing samples are NaN.
+	///
+	/// Only the bytes of the signal are read from each record, so memory
+	/// use is that of the returned samples. Like `read_records_at`, this can
+	/// be called from several threads at once.
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at the given index.
+	pub fn read_signal_at(&self, signal: usize, records: Range<usize>) -> Result<Vec<f64>> {
+		assert!(signal < self.header.signals.len(), "no signal {}", signal);
+		let width = self.header.format.bytes_per_sample();
+		let before: usize = self.header.signals[..signal]
+			.iter()
+			.map(|s| s.samples_per_record)
+			.sum();
+		let samples_per_record = self.header.signals[signal].samples_per_record;
+		let mut buffer = vec![0; samples_per_record * width];
+		let records_len = self.header.effective_records_len().unwrap_or(0);
+		let mut samples = Vec::with_capacity(
+			records
+				.len()
+				.min(records_len)
+				.saturating_mul(samples_per_record),
+		);
+		for i in records {
+			let offset = self.record_offset(i, before * width)?;
+			read_exact_at(&self.rdr, &mut buffer, offset)?;
+			samples.extend(
+				buffer
+					.chunks_exact(width)
+					.map(|bytes| self.to_physical(signal, decode_sample(bytes))),
+			);
+		}
+		Ok(samples)
+	}
+}
+
+impl<'a> Reader<Cursor<&'a [u8]>> {
+	/// Reads the header of a file held in memory, using the default
+	/// configuration.
+	pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+		ReaderBuilder::new().from_bytes(bytes)
+	}
+}
+
+impl<R: Read + Seek> Reader<R> {
+	/// Reads the header from any source that can seek, using the default
+	/// configuration.
+	pub fn from_reader(rdr: R) -> Result<Reader<R>> {
+		ReaderBuilder::new().from_reader(rdr)
+	}
+
+	/// Returns the header of the file.
+	pub fn header(&self) -> &Header {
+		&self.header
+	}
+
+	/// Consumes the reader, returning the header.
+	pub fn into_header(self) -> Header {
+		self.header
+	}
+
+	/// Reads all data records, starting from the first.
+	///
+	/// If the number of records is unknown, the complete records in the file
+	/// are read, and a record cut short by an interrupted recording is
+	/// dropped.
+	pub fn read_records(&mut self) -> Result<Vec<Record>> {
+		self.records().collect()
+	}
+
+	/// Returns an iterator that reads the data records one at a time,
+	/// starting from the first.
+	///
+	/// Only one record is held in memory at a time, so this suits long
+	/// recordings that `read_records` would load whole. The records are the
+	/// same as those of `read_records`. The iterator ends after the first
+	/// error.
+	pub fn records(&mut self) -> Records<'_, R> {
+		let len = self.header.effective_records_len().unwrap_or(0);
+		Records {
+			buffer: vec![0; self.record_size],
+			reader: self,
+			index: 0,
+			len,
+		}
+	}
+
+	/// Checks the size of the file again, updating the number of data records
+	/// inferred from it, and returns the number of records now available.
+	///
+	/// A file whose header is provisional is still being written, so new
+	/// records may have been added since it was opened. Only complete
+	/// records are counted.
+	pub fn refresh_records_len(&mut self) -> Result<usize> {
+		self.header.inferred_records_len =
+			infer_records_len(&mut self.rdr, &self.header, self.record_size)?;
+		Ok(self.header.effective_records_len().unwrap_or(0))
+	}
+
+	/// Reads the data record at the given index, seeking straight to it.
+	///
+	/// This lets a viewer jump to any point of a long recording without
+	/// reading the records before it. An index past the last record is an
+	/// `UnexpectedEof` error.
+	pub fn read_record(&mut self, index: usize) -> Result<Record> {
+		if index >= self.header.effective_records_len().unwrap_or(0) {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+		}
+		let offset = self.record_offset(index, 0)?;
+		self.rdr.seek(SeekFrom::Start(offset))?;
+		let mut buffer = vec![0; self.record_size];
+		match self.read_next_record(&mut buffer)? {
+			Some(record) => Ok(record),
+			None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+		}
+	}
+
+	/// Reads some signals of the data record at the given index, seeking over
+	/// the bytes of the others.
+	///
+	/// The other signals of the returned record are empty. When only a few of
+	/// many signals are needed, this reads a fraction of each record. An
+	/// index past the last record is an `UnexpectedEof` error.
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at one of the given indices.
+	pub fn read_record_channels(&mut self, index: usize, channels: &[usize]) -> Result<Record> {
+		for &signal in channels {
+			assert!(signal < self.header.signals.len(), "no signal {}", signal);
+		}
+		if index >= self.header.effective_records_len().unwrap_or(0) {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+		}
+		let width = self.header.format.bytes_per_sample();
+		let mut buffers = Vec::with_capacity(channels.len());
+		for &signal in channels {
+			let before: usize = self.header.signals[..signal]
+				.iter()
+				.map(|s| s.samples_per_record)
+				.sum();
+			let mut buffer = vec![0; self.header.signals[signal].samples_per_record * width];
+			let offset = self.record_offset(index, before * width)?;
+			self.rdr.seek(SeekFrom::Start(offset))?;
+			self.rdr.read_exact(&mut buffer)?;
+			buffers.push((signal, buffer));
+		}
+		Ok(Record::decode_signals(&self.header, buffers))
+	}
+
+	/// Reads every sample of a signal, converted to physical values. Missing
+	/// samples are NaN.
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at the given index.
+	pub fn read_signal(&mut self, signal: usize) -> Result<Vec<f64>> {
+		assert!(signal < self.header.signals.len(), "no signal {}", signal);
+		let mut samples = Vec::new();
+		for record in self.records() {
+			samples.extend_from_slice(&record?.signals()[signal]);
+		}
+		Ok(samples
+			.into_iter()
+			.map(|sample| self.to_physical(signal, sample))
+			.collect())
+	}
+
+	/// Reads the samples of some signals in a window of time, converted to
+	/// physical values. Missing samples are NaN.
+	///
+	/// A sample is in the window if its time from the start of the recording
+	/// is at least `start` and less than `start + len`. Only the data records
+	/// that overlap the window are read, and the window may begin and end
+	/// part way through a record. The samples are returned in the order of
+	/// `channels`, and the bytes of other signals are skipped as by
+	/// `read_record_channels`. A window that runs past the end of the
+	/// recording is cut short. Times assume the data records are contiguous.
+	///
+	/// If the header is provisional, the size of the file is checked first,
+	/// so that a window of a recording in progress covers the records
+	/// written so far.
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at one of the given indices.
+	pub fn read_range(
+		&mut self,
+		start: Duration,
+		len: Duration,
+		channels: &[usize],
+	) -> Result<Vec<Vec<f64>>> {
+		for &signal in channels {
+			assert!(signal < self.header.signals.len(), "no signal {}", signal);
+		}
+		let mut samples = vec![Vec::new(); channels.len()];
+		let duration = self.header.duration.as_nanos();
+		if duration == 0 {
+			return Ok(samples);
+		}
+		// A window running past the largest duration ends there, as it runs
+		// past the end of the recording anyway.
+		let end = start.saturating_add(len).as_nanos();
+		let start = start.as_nanos();
+		let index = |i: u128| usize::try_from(i).unwrap_or(usize::MAX);
+		// The indices of the samples of each signal in the window, counted
+		// from the start of the recording.
+		let windows: Vec<Range<usize>> = channels
+			.iter()
+			.map(|&signal| {
+				let n = self.header.signals[signal].samples_per_record as u128;
+				let first = index((start * n).div_ceil(duration));
+				let last = index((end * n).div_ceil(duration));
+				first..last
+			})
+			.collect();
+
+		let records_len = if self.header.is_provisional() {
+			self.refresh_records_len()?
+		} else {
+			self.header.effective_records_len().unwrap_or(0)
+		};
+		let first = index(start / duration);
+		let last = index(end.div_ceil(duration)).min(records_len);
+		for i in first..last {
+			let record = self.read_record_channels(i, channels)?;
+			for (c, &signal) in channels.iter().enumerate() {
+				let record_samples = &record.signals()[signal];
+				let offset = i * record_samples.len();
+				let from = windows[c]
+					.start
+					.saturating_sub(offset)
+					.min(record_samples.len());
+				let to = windows[c]
+					.end
+					.saturating_sub(offset)
+					.min(record_samples.len());
+				samples[c].extend(
+					record_samples[from..to.max(from)]
+						.iter()
+						.map(|&sample| self.to_physical(signal, sample)),
+				);
+			}
+		}
+		Ok(samples)
+	}
+
+	/// Reads the annotations of every data record, from all annotation
+	/// signals, ordered by onset.
+	///
+	/// Annotations with the same onset keep their order in the file. Only the
+	/// annotation signals are read, one record at a time, so this works for
+	/// files of any size.
+	pub fn read_annotations(&mut self) -> Result<Vec<Annotation>> {
+		let signals = self.header.annotation_signals();
+		let mut annotations = Vec::new();
+		if !signals.is_empty() {
+			for i in 0..self.header.effective_records_len().unwrap_or(0) {
+				let record = self.read_record_channels(i, &signals)?;
+				annotations.extend(record.annotations(&self.header)?);
+			}
+		}
+		annotations.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+		Ok(annotations)
+	}
+
+	/// Checks each data record against its CRC-32 from a sidecar, as read by
+	/// `read_record_crcs`.
+	///
+	/// Returns the indices of the records that do not match, including
+	/// records that are missing from the file or from the sidecar. This is
+	/// meant for validating long transfers of large recordings record by
+	/// record rather than as a whole.
+	pub fn verify_records(&mut self, crcs: &[u32]) -> Result<Vec<usize>> {
+		self.rdr.seek(SeekFrom::Start(self.header.size as u64))?;
+		let records_len = self.header.effective_records_len().unwrap_or(0);
+		let mut buffer = vec![0; self.record_size];
+		let mut mismatches = Vec::new();
+		let mut i = 0;
+		while i < records_len && self.read_record_bytes(&mut buffer)? {
+			if crcs.get(i) != Some(&crc32(&buffer)) {
+				mismatches.push(i);
+			}
+			i += 1;
+		}
+		mismatches.extend(i..crcs.len());
+		Ok(mismatches)
+	}
+
+	/// Writes the CRC-32 of each data record as a sidecar, in the format of
+	/// `write_record_crcs`, returning the number of records.
+	///
+	/// The records are read one at a time, so this works for files of any
+	/// size, e.g. to make a sidecar for a recording before transferring it.
+	pub fn write_record_crcs<W: Write>(&mut self, mut wtr: W) -> Result<usize> {
+		self.rdr.seek(SeekFrom::Start(self.header.size as u64))?;
+		let records_len = self.header.effective_records_len().unwrap_or(0);
+		let mut buffer = vec![0; self.record_size];
+		let mut i = 0;
+		while i < records_len && self.read_record_bytes(&mut buffer)? {
+			writeln!(wtr, "{}\t{:08x}", i, crc32(&buffer))?;
+			i += 1;
+		}
+		wtr.flush()?;
+		Ok(i)
+	}
+
+	/// Adds the sub-second part of the start time of an EDF+ file to
+	/// `Header::start_datetime`.
+	///
+	/// The starttime field only has whole seconds, so EDF+ gives the fraction
+	/// as the onset of the first data record. A first record that cannot be
+	/// read is left to fail when it is read.
+	fn read_start_offset(&mut self) {
+		let signal = match self.header.annotation_signals().first() {
+			Some(&signal) if self.header.file_type() != FileType::Edf => signal,
+			_ => return,
+		};
+		let onset = self
+			.read_record_channels(0, &[signal])
+			.and_then(|record| record.onset(&self.header));
+		if let Ok(Some(onset)) = onset {
+			if onset > 0.0 && onset < 1.0 {
+				self.header.start_datetime +=
+					chrono::Duration::nanoseconds((onset * 1e9).round() as i64);
+			}
+		}
+	}
+
+	/// Reads the data record at the current position.
+	///
+	/// Returns `None` at the end of the file. A record cut short by the end
+	/// of the file is an error.
+	fn read_next_record(&mut self, buffer: &mut [u8]) -> Result<Option<Record>> {
+		if self.read_record_bytes(buffer)? {
+			Ok(Some(Record::decode(&self.header, buffer)))
+		} else {
+			Ok(None)
+		}
+	}
+
+	/// Fills the buffer with the data record at the current position.
+	///
+	/// Returns `false` at the end of the file. A record cut short by the end
+	/// of the file is an error.
+	fn read_record_bytes(&mut self, buffer: &mut [u8]) -> Result<bool> {
+		let mut filled = 0;
+		while filled < buffer.len() {
+			match self.rdr.read(&mut buffer[filled..]) {
+				Ok(0) => break,
+				Ok(n) => filled += n,
+				Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+				Err(err) => return Err(err.into()),
+			}
+		}
+		if filled == 0 && !buffer.is_empty() {
+			return Ok(false);
+		}
+		if filled < buffer.len() {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+		}
+		Ok(true)
 	}
 
 	/// Reads and validates the header.
-	fn read_header(f: &File) -> Result<Header> {
-		Reader::read_version(&f)?;
-		let patient_info = Reader::read_patient_info(f)?;
-		let recording_id = Reader::read_recording_id(f)?;
-		let start_date = Reader::read_start_date(f)?;
-		let start_time = Reader::read_start_time(f)?;
-		let size = Reader::read_header_size(f)?;
-		let reserved = Reader::read_reserved(f)?;
-		let records_len = Reader::read_records_len(f)?;
-		let duration = Reader::read_duration(f)?;
-		let signals_len = Reader::read_signals_len(f)?;
-		Ok(Header::new(
+	fn read_header(f: &mut R, options: &ReaderBuilder) -> Result<Header> {
+		let mut warnings = Vec::new();
+		let format = Self::read_version(f)?;
+		let raw_patient_info = Self::read_patient_info(f)?;
+		let patient_info = decode_text(
+			&raw_patient_info,
+			"local patient identification",
+			options,
+			&mut warnings,
+		)?;
+		let raw_recording_id = Self::read_recording_id(f)?;
+		let recording_id = decode_text(
+			&raw_recording_id,
+			"local recording identification",
+			options,
+			&mut warnings,
+		)?;
+		let start_date = Self::read_start_date(f, &recording_id, options, &mut warnings)?;
+		let start_date = Self::reconcile_start_date(start_date, &recording_id, options.precedence)
+			.or_else(|err| warn(options, &mut warnings, err))?;
+		let start_time = Self::read_start_time(f, options, &mut warnings)?;
+		let size = Self::read_header_size(f, options, &mut warnings)?;
+		let raw_reserved = Self::read_reserved(f)?;
+		let reserved = decode_text(&raw_reserved, "reserved", options, &mut warnings)?;
+		let records_len = Self::read_records_len(f, options, &mut warnings)?;
+		let duration = Self::read_duration(f, options, &mut warnings)?;
+		let signals_len = Self::read_signals_len(f, options, &mut warnings)?;
+		let size = Self::check_header_size(size, signals_len)
+			.or_else(|err| warn(options, &mut warnings, err))?;
+		let signals = Self::read_signal_headers(f, signals_len as usize, options, &mut warnings)?;
+		let mut hdr = Header::new(
+			format,
 			patient_info,
 			recording_id,
 			start_date,
@@ -37,165 +659,765 @@ impl Reader {
 			records_len,
 			duration,
 			signals_len,
-		))
+		);
+		hdr.raw_patient_info = raw_patient_info;
+		hdr.raw_recording_id = raw_recording_id;
+		hdr.raw_reserved = raw_reserved;
+		hdr.signals = signals;
+		hdr.warnings = warnings;
+		Ok(hdr)
+	}
+
+	/// Checks the start date against the EDF+ startdate subfield.
+	///
+	/// When the two disagree, the one given by the precedence is recovered.
+	fn reconcile_start_date(
+		start_date: NaiveDate,
+		recording_id: &str,
+		precedence: StartdatePrecedence,
+	) -> result::Result<NaiveDate, Recovered<NaiveDate>> {
+		match startdate_subfield(recording_id) {
+			Some(subfield) if subfield != start_date => Err(Recovered {
+				err: HeaderError::StartdateMismatch {
+					date: start_date,
+					subfield,
+				},
+				value: match precedence {
+					StartdatePrecedence::Subfield => subfield,
+					StartdatePrecedence::Startdate => start_date,
+				},
+			}),
+			_ => Ok(start_date),
+		}
+	}
+
+	/// Checks the number of bytes in the header against the number of
+	/// signals.
+	///
+	/// The size computed from the signals is preferred when the two disagree,
+	/// since the per-signal section is read by the number of signals.
+	fn check_header_size(size: usize, signals_len: u32) -> result::Result<usize, Recovered<usize>> {
+		let expected = (signals_len as usize + 1) * 256;
+		if size == expected {
+			Ok(size)
+		} else {
+			Err(Recovered {
+				err: HeaderError::SizeMismatch {
+					declared: size,
+					expected,
+				},
+				value: expected,
+			})
+		}
 	}
 
-	/// Reads and validate the version.
+	/// Reads and validate the version, which determines the format.
 	///
-	/// Bytes from 0–80 are the version. The version is always 0.
-	fn read_version(mut f: &File) -> Result<()> {
+	/// Bytes from 0–8 are the version. It is `0` followed by spaces for EDF,
+	/// and `0xFF` followed by `BIOSEMI` for BDF.
+	fn read_version(f: &mut R) -> Result<FileFormat> {
 		let mut buffer = [0; 8];
 		f.read_exact(&mut buffer)?;
-		if buffer[0] != 48 {
-			return Err(Error::new(ErrorKind::Header(HeaderError::Version)));
-		}
-		for i in buffer.into_iter().skip(1) {
-			if i != 32 {
-				return Err(Error::new(ErrorKind::Header(HeaderError::Version)));
-			}
+		match &buffer {
+			b"0       " => Ok(FileFormat::Edf),
+			b"\xffBIOSEMI" => Ok(FileFormat::Bdf),
+			// Name the format if it is recognizable, rather than just
+			// reporting an invalid version.
+			_ => match Format::sniff(&buffer) {
+				Some(format) => Err(Error::new(ErrorKind::UnsupportedFormat(format))),
+				None => Err(Error::new(ErrorKind::Header(HeaderError::Version))),
+			},
 		}
-		Ok(())
 	}
 
 	/// Reads patient information.
-	fn read_patient_info(mut f: &File) -> Result<String> {
-		let mut buffer = [0; 80];
+	fn read_patient_info(f: &mut R) -> Result<Vec<u8>> {
+		let mut buffer = vec![0; 80];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		Ok(s)
+		Ok(buffer)
 	}
 
 	/// Reads recording information.
-	fn read_recording_id(mut f: &File) -> Result<String> {
-		let mut buffer = [0; 80];
+	fn read_recording_id(f: &mut R) -> Result<Vec<u8>> {
+		let mut buffer = vec![0; 80];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		Ok(s)
+		Ok(buffer)
 	}
 
 	/// Reads the start date of the recording.
-	fn read_start_date(mut f: &File) -> Result<NaiveDate> {
+	///
+	/// EDF+ writes the year as `yy` after 2084, which the two digits cannot
+	/// represent. The year is then taken from the startdate subfield of the
+	/// recording identification, and it is an error if there is none. The
+	/// day and month are still those of the field, to be checked against the
+	/// subfield like any other start date.
+	fn read_start_date(
+		f: &mut R,
+		recording_id: &str,
+		options: &ReaderBuilder,
+		warnings: &mut Vec<HeaderError>,
+	) -> Result<NaiveDate> {
 		let mut buffer = [0; 8];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		let date = Reader::parse_start_date(s).expect("Invalid start time");
-		Ok(date)
+		if &buffer[6..] == b"yy" {
+			// Check the day and month in a leap year, so that 29.02 passes.
+			let day_month = str::from_utf8(&buffer[..6])
+				.ok()
+				.and_then(|s| NaiveDate::parse_from_str(&format!("{}2000", s), "%d.%m.%Y").ok());
+			let subfield = startdate_subfield(recording_id);
+			return day_month
+				.zip(subfield)
+				.and_then(|(date, subfield)| date.with_year(subfield.year()))
+				.ok_or_else(|| {
+					Error::new(ErrorKind::Header(HeaderError::InvalidDate {
+						raw: buffer.to_vec(),
+					}))
+				});
+		}
+		parse_field(
+			&buffer,
+			|raw| HeaderError::InvalidDate { raw },
+			parse_start_date,
+		)
+		.or_else(|err| recover_separators(&buffer, err, parse_start_date, options, warnings))
 	}
 
 	/// Reads the start time of the recording.
-	fn read_start_time(mut f: &File) -> Result<NaiveTime> {
+	fn read_start_time(
+		f: &mut R,
+		options: &ReaderBuilder,
+		warnings: &mut Vec<HeaderError>,
+	) -> Result<NaiveTime> {
 		let mut buffer = [0; 8];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		let time = NaiveTime::parse_from_str(&s, "%H.%M.%S").expect("Invalid start time");
-		Ok(time)
+		let parse = |s: &str| NaiveTime::parse_from_str(s, "%H.%M.%S").ok();
+		parse_field(&buffer, |raw| HeaderError::InvalidTime { raw }, parse)
+			.or_else(|err| recover_separators(&buffer, err, parse, options, warnings))
 	}
 
 	/// Reads the number of bytes.
-	fn read_header_size(mut f: &File) -> Result<usize> {
+	fn read_header_size(
+		f: &mut R,
+		options: &ReaderBuilder,
+		warnings: &mut Vec<HeaderError>,
+	) -> Result<usize> {
 		let mut buffer = [0; 8];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		let n = s.trim_end().parse().expect("Could not parse header size");
-		Ok(n)
-	}
-
-	// Parse the start date from a string.
-	fn parse_start_date(s: String) -> result::Result<NaiveDate, chrono::ParseError> {
-		let date = NaiveDate::parse_from_str(&s, "%d.%m.%y")?;
-		// The spec specifies a clipping date of 1985.
-		let date = if date.year() < 1985 {
-			date.with_year(date.year() + 100)
-		} else {
-			Some(date)
-		}
-		.unwrap();
-		Ok(date)
+		parse_numeric_field(
+			&buffer,
+			"number of bytes in header",
+			"an integer",
+			options,
+			warnings,
+			|s| s.trim_end().parse().ok(),
+		)
 	}
 
 	/// Reads the reserved block.
-	fn read_reserved(mut f: &File) -> Result<String> {
-		let mut buffer = [0; 44];
+	fn read_reserved(f: &mut R) -> Result<Vec<u8>> {
+		let mut buffer = vec![0; 44];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		Ok(s)
+		Ok(buffer)
 	}
 
 	/// Reads the number of records.
-	fn read_records_len(mut f: &File) -> Result<Option<usize>> {
+	fn read_records_len(
+		f: &mut R,
+		options: &ReaderBuilder,
+		warnings: &mut Vec<HeaderError>,
+	) -> Result<Option<usize>> {
 		let mut buffer = [0; 8];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		let n = s
-			.trim_end()
-			.parse::<isize>()
-			.expect("Could not parse number of records");
-		if n == -1 {
-			Ok(None)
-		} else if n > 0 {
-			Ok(Some(n as usize))
-		} else {
-			panic!("Record length cannot be negative");
+		let value: i64 = parse_numeric_field(
+			&buffer,
+			"number of data records",
+			"a non-negative integer or -1",
+			options,
+			warnings,
+			|s| s.trim_end().parse().ok(),
+		)?;
+		match value {
+			-1 => Ok(None),
+			value => usize::try_from(value).map(Some).map_err(|_| {
+				Error::new(ErrorKind::Header(HeaderError::NegativeRecordCount {
+					value,
+				}))
+			}),
 		}
 	}
 
 	/// Reads the duration of a data record.
 	///
-	/// The spec recommends that it is a whole number of seconds.
-	fn read_duration(mut f: &File) -> Result<usize> {
+	/// The spec recommends a whole number of seconds, but fractions such as
+	/// `0.002` are allowed and common for high-rate recorders.
+	fn read_duration(
+		f: &mut R,
+		options: &ReaderBuilder,
+		warnings: &mut Vec<HeaderError>,
+	) -> Result<Duration> {
 		let mut buffer = [0; 8];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		let s = s.trim_end();
-		// Check to see if there is a trailing decimal.
-		let split = s.split_once(".");
-		let n = match split {
-			None => s,
-			Some((characteristic, mantissa)) => match mantissa.parse::<u8>() {
-				Ok(v) => match v {
-					// The trailing decimals were just zeroes. Continue.
-					0 => characteristic,
-					_ => panic!("Unimplemented parsing of float durations"),
-				},
-				Err(_) => panic!("Could not parse mantissa of duration"),
-			},
-		}
-		.parse()
-		.expect("Could not parse duration");
-		Ok(n)
+		parse_numeric_field(
+			&buffer,
+			"duration of a data record",
+			"a non-negative number of seconds",
+			options,
+			warnings,
+			|s| parse_seconds(s.trim_end()),
+		)
 	}
 
 	/// Reads the number of signals in the data record.
-	fn read_signals_len(mut f: &File) -> Result<u32> {
+	///
+	/// The field is four characters wide, which bounds the per-signal section
+	/// of the header to 9999 * 256 bytes.
+	fn read_signals_len(
+		f: &mut R,
+		options: &ReaderBuilder,
+		warnings: &mut Vec<HeaderError>,
+	) -> Result<u32> {
 		let mut buffer = [0; 4];
 		f.read_exact(&mut buffer)?;
-		let s = String::from_utf8(buffer.to_vec())?;
-		let n = s
-			.trim_end()
-			.parse()
-			.expect("Could not parse number of signals");
-		Ok(n)
+		parse_numeric_field(
+			&buffer,
+			"number of signals",
+			"an integer",
+			options,
+			warnings,
+			|s| s.trim_end().parse().ok(),
+		)
+	}
+
+	/// Reads the per-signal section of the header.
+	///
+	/// Each field is stored for all signals before the next field starts, so
+	/// the whole section is read at once and split into columns.
+	fn read_signal_headers(
+		f: &mut R,
+		signals_len: usize,
+		options: &ReaderBuilder,
+		warnings: &mut Vec<HeaderError>,
+	) -> Result<Vec<SignalHeader>> {
+		let mut buffer = vec![0; signals_len * 256];
+		f.read_exact(&mut buffer)?;
+		let mut columns = SignalColumns {
+			buffer: &buffer,
+			signals_len,
+		};
+		let labels = columns.next(16);
+		let transducers = columns.next(80);
+		let physical_dimensions = columns.next(8);
+		let physical_mins = columns.next(8);
+		let physical_maxs = columns.next(8);
+		let digital_mins = columns.next(8);
+		let digital_maxs = columns.next(8);
+		let prefilterings = columns.next(80);
+		let samples_per_records = columns.next(8);
+		let reserveds = columns.next(32);
+
+		(0..signals_len)
+			.map(|i| {
+				let in_signal = |err: HeaderError| HeaderError::Signal {
+					index: i,
+					err: Box::new(err),
+				};
+				let signal_field = |err: Error| match err.into_kind() {
+					ErrorKind::Header(err) => Error::new(ErrorKind::Header(in_signal(err))),
+					kind => Error::new(kind),
+				};
+				let mut signal_warnings = Vec::new();
+				let mut text = |buffer, field| {
+					parse_text(buffer, field, options, &mut signal_warnings).map_err(signal_field)
+				};
+				let label = text(labels[i], "label")?;
+				let transducer = text(transducers[i], "transducer type")?;
+				let physical_dimension = text(physical_dimensions[i], "physical dimension")?;
+				let prefiltering = text(prefilterings[i], "prefiltering")?;
+				let reserved = text(reserveds[i], "reserved")?;
+				let signal = SignalHeader {
+					label,
+					transducer,
+					physical_dimension,
+					physical_min: parse_number(
+						physical_mins[i],
+						"physical minimum",
+						options,
+						&mut signal_warnings,
+					)
+					.map_err(signal_field)?,
+					physical_max: parse_number(
+						physical_maxs[i],
+						"physical maximum",
+						options,
+						&mut signal_warnings,
+					)
+					.map_err(signal_field)?,
+					digital_min: parse_number(
+						digital_mins[i],
+						"digital minimum",
+						options,
+						&mut signal_warnings,
+					)
+					.map_err(signal_field)?,
+					digital_max: parse_number(
+						digital_maxs[i],
+						"digital maximum",
+						options,
+						&mut signal_warnings,
+					)
+					.map_err(signal_field)?,
+					prefiltering,
+					samples_per_record: parse_number(
+						samples_per_records[i],
+						"number of samples in each data record",
+						options,
+						&mut signal_warnings,
+					)
+					.map_err(signal_field)?,
+					reserved,
+				};
+				warnings.extend(signal_warnings.into_iter().map(in_signal));
+				Ok(signal)
+			})
+			.collect()
+	}
+}
+
+/// An iterator over the data records of a file, created by
+/// [`Reader::records`].
+#[derive(Debug)]
+pub struct Records<'r, R> {
+	reader: &'r mut Reader<R>,
+	/// The bytes of the record being read, reused between records.
+	buffer: Vec<u8>,
+	/// The index of the next record.
+	index: usize,
+	len: usize,
+}
+
+impl<R: Read + Seek> Iterator for Records<'_, R> {
+	type Item = Result<Record>;
+
+	fn next(&mut self) -> Option<Result<Record>> {
+		if self.index >= self.len {
+			return None;
+		}
+		if self.index == 0 {
+			let start = SeekFrom::Start(self.reader.header.size as u64);
+			if let Err(err) = self.reader.rdr.seek(start) {
+				self.index = self.len;
+				return Some(Err(err.into()));
+			}
+		}
+		match self.reader.read_next_record(&mut self.buffer) {
+			Ok(Some(record)) => {
+				self.index += 1;
+				Some(Ok(record))
+			}
+			Ok(None) => {
+				self.index = self.len;
+				None
+			}
+			Err(err) => {
+				self.index = self.len;
+				Some(Err(err))
+			}
+		}
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		(0, Some(self.len - self.index))
+	}
+}
+
+/// Infers the number of complete data records from the size of the file.
+fn infer_records_len<R: Seek>(
+	rdr: &mut R,
+	header: &Header,
+	record_size: usize,
+) -> Result<Option<usize>> {
+	if record_size == 0 {
+		return Ok(None);
+	}
+	let len = rdr.seek(SeekFrom::End(0))?;
+	let data_size = len.saturating_sub(header.size as u64);
+	Ok(Some((data_size / record_size as u64) as usize))
+}
+
+/// Returns the EDF+ startdate subfield of the recording identification, if
+/// it follows EDF+ and the date is known.
+fn startdate_subfield(recording_id: &str) -> Option<NaiveDate> {
+	RecordingId::parse(recording_id).ok()?.startdate
+}
+
+/// Parses the start date, e.g. `02.03.02`.
+fn parse_start_date(s: &str) -> Option<NaiveDate> {
+	let date = NaiveDate::parse_from_str(s, "%d.%m.%y").ok()?;
+	// The spec specifies a clipping date of 1985.
+	if date.year() < 1985 {
+		date.with_year(date.year() + 100)
+	} else {
+		Some(date)
+	}
+}
+
+/// Fills the buffer from the given offset without moving the position of the
+/// file.
+#[cfg(unix)]
+fn read_exact_at(f: &File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
+	use std::os::unix::fs::FileExt;
+	f.read_exact_at(buffer, offset)
+}
+
+/// Fills the buffer from the given offset.
+///
+/// On Windows a positioned read moves the position of the file, but
+/// `read_records` seeks before reading, so it is not affected.
+#[cfg(windows)]
+fn read_exact_at(f: &File, mut buffer: &mut [u8], mut offset: u64) -> io::Result<()> {
+	use std::os::windows::fs::FileExt;
+	while !buffer.is_empty() {
+		match f.seek_read(buffer, offset) {
+			Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+			Ok(n) => {
+				buffer = &mut buffer[n..];
+				offset += n as u64;
+			}
+			Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+			Err(err) => return Err(err),
+		}
+	}
+	Ok(())
+}
+
+/// Splits the per-signal section of the header into its fields.
+struct SignalColumns<'a> {
+	buffer: &'a [u8],
+	signals_len: usize,
+}
+
+impl<'a> SignalColumns<'a> {
+	/// Returns the next field of every signal, each `width` bytes wide.
+	fn next(&mut self, width: usize) -> Vec<&'a [u8]> {
+		let (column, rest) = self.buffer.split_at(width * self.signals_len);
+		self.buffer = rest;
+		column.chunks(width).collect()
+	}
+}
+
+/// Drops the padding of a text field: trailing spaces, and the NUL bytes some
+/// recorders pad with instead.
+fn trim_padding(s: &str) -> String {
+	s.trim_end_matches([' ', '\0']).to_string()
+}
+
+/// Pads a text field with spaces to its width in bytes.
+fn pad(s: &str, width: usize) -> Vec<u8> {
+	let mut bytes = s.as_bytes().to_vec();
+	if bytes.len() < width {
+		bytes.resize(width, b' ');
+	}
+	bytes
+}
+
+/// Parses a text field, dropping the padding.
+fn parse_text(
+	buffer: &[u8],
+	field: &'static str,
+	options: &ReaderBuilder,
+	warnings: &mut Vec<HeaderError>,
+) -> Result<String> {
+	Ok(trim_padding(&decode_text(
+		buffer, field, options, warnings,
+	)?))
+}
+
+/// Decodes a text field in the configured encoding.
+///
+/// In lenient mode, a field that cannot be decoded is decoded as Latin-1
+/// with a warning, as that never fails.
+fn decode_text(
+	buffer: &[u8],
+	field: &'static str,
+	options: &ReaderBuilder,
+	warnings: &mut Vec<HeaderError>,
+) -> Result<String> {
+	match options.encoding.decode(buffer, field) {
+		Err(_) if options.lenient => {
+			warnings.push(HeaderError::InvalidText {
+				field,
+				raw: buffer.to_vec(),
+			});
+			TextEncoding::Latin1.decode(buffer, field)
+		}
+		decoded => decoded,
+	}
+}
+
+/// Recovers a startdate or starttime written with separators other than
+/// points, e.g. `22:00:00` or `01/03/21`.
+fn recover_separators<T, F>(
+	buffer: &[u8],
+	err: Error,
+	parse: F,
+	options: &ReaderBuilder,
+	warnings: &mut Vec<HeaderError>,
+) -> Result<T>
+where
+	F: FnOnce(&str) -> Option<T>,
+{
+	let value = str::from_utf8(buffer)
+		.ok()
+		.map(|s| {
+			s.trim_matches([' ', '\0'])
+				.replace([':', '/', '-', ' '], ".")
+		})
+		.and_then(|s| parse(&s));
+	match (value, err.into_kind()) {
+		(Some(value), ErrorKind::Header(err)) => warn(options, warnings, Recovered { err, value }),
+		(_, kind) => Err(Error::new(kind)),
+	}
+}
+
+/// Parses a numeric field of the per-signal section.
+fn parse_number<T: str::FromStr>(
+	buffer: &[u8],
+	field: &'static str,
+	options: &ReaderBuilder,
+	warnings: &mut Vec<HeaderError>,
+) -> Result<T> {
+	parse_numeric_field(buffer, field, "a number", options, warnings, |s| {
+		s.trim().parse().ok()
+	})
+}
+
+/// Parses a numeric header field.
+///
+/// In lenient mode, a value that only parses after normalizing it is
+/// accepted with a warning. Normalizing trims spaces, drops a leading plus
+/// sign and turns a decimal comma into a point, as some exporters write
+/// `+1,5` for `1.5`.
+fn parse_numeric_field<T, F>(
+	buffer: &[u8],
+	field: &'static str,
+	expected: &'static str,
+	options: &ReaderBuilder,
+	warnings: &mut Vec<HeaderError>,
+	parse: F,
+) -> Result<T>
+where
+	F: Fn(&str) -> Option<T>,
+{
+	let invalid = |raw| HeaderError::InvalidNumber {
+		field,
+		raw,
+		expected,
+	};
+	let err = match parse_field(buffer, invalid, &parse) {
+		Ok(value) => return Ok(value),
+		Err(err) => err,
+	};
+	let value = str::from_utf8(buffer)
+		.ok()
+		.map(normalize_number)
+		.and_then(|s| parse(&s));
+	match (value, err.into_kind()) {
+		(Some(value), ErrorKind::Header(err)) => warn(options, warnings, Recovered { err, value }),
+		(_, kind) => Err(Error::new(kind)),
+	}
+}
+
+/// Parses a decimal number of seconds exactly, e.g. `0.002`.
+///
+/// The field is eight characters wide, so the fraction always fits in
+/// nanoseconds.
+fn parse_seconds(s: &str) -> Option<Duration> {
+	let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+	let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+	if (whole.is_empty() && fraction.is_empty())
+		|| !digits(whole)
+		|| !digits(fraction)
+		|| fraction.len() > 9
+	{
+		return None;
+	}
+	let secs = if whole.is_empty() {
+		0
+	} else {
+		whole.parse().ok()?
+	};
+	let nanos = if fraction.is_empty() {
+		0
+	} else {
+		format!("{:0<9}", fraction).parse().ok()?
+	};
+	Some(Duration::new(secs, nanos))
+}
+
+/// Formats a duration as a decimal number of seconds, e.g. `0.002`.
+pub(crate) fn format_seconds(d: Duration) -> String {
+	if d.subsec_nanos() == 0 {
+		return d.as_secs().to_string();
+	}
+	let s = format!("{}.{:09}", d.as_secs(), d.subsec_nanos());
+	s.trim_end_matches('0').to_string()
+}
+
+/// Normalizes the spelling of a number, e.g. ` +1,5 ` to `1.5`.
+fn normalize_number(s: &str) -> String {
+	let s = s.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+	let s = s.strip_prefix('+').unwrap_or(s).trim_start();
+	s.replace(',', ".")
+}
+
+/// A problem in the header along with the value to use in lenient mode.
+struct Recovered<T> {
+	err: HeaderError,
+	value: T,
+}
+
+/// Fails in strict mode; records a warning and recovers in lenient mode.
+fn warn<T>(
+	options: &ReaderBuilder,
+	warnings: &mut Vec<HeaderError>,
+	recovered: Recovered<T>,
+) -> Result<T> {
+	if options.lenient {
+		warnings.push(recovered.err);
+		Ok(recovered.value)
+	} else {
+		Err(Error::new(ErrorKind::Header(recovered.err)))
 	}
 }
 
+/// Parses an ASCII header field.
+///
+/// On failure, the error carries the raw bytes, so that the offending value
+/// can be found without a hex editor.
+fn parse_field<T, E, F>(buffer: &[u8], invalid: E, parse: F) -> Result<T>
+where
+	E: FnOnce(Vec<u8>) -> HeaderError,
+	F: FnOnce(&str) -> Option<T>,
+{
+	str::from_utf8(buffer)
+		.ok()
+		.and_then(parse)
+		.ok_or_else(|| Error::new(ErrorKind::Header(invalid(buffer.to_vec()))))
+}
+
+/// The format of a file, as given by its version field.
+///
+/// EDF and BDF share the header layout, but BDF stores samples as 24-bit
+/// rather than 16-bit integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileFormat {
+	Edf,
+	Bdf,
+}
+
+impl FileFormat {
+	/// The number of bytes of each sample in a data record.
+	pub fn bytes_per_sample(self) -> usize {
+		match self {
+			FileFormat::Edf => 2,
+			FileFormat::Bdf => 3,
+		}
+	}
+
+	/// The smallest digital value a sample can hold.
+	pub fn digital_min(self) -> i32 {
+		match self {
+			FileFormat::Edf => i16::MIN.into(),
+			FileFormat::Bdf => -(1 << 23),
+		}
+	}
+
+	/// The largest digital value a sample can hold.
+	pub fn digital_max(self) -> i32 {
+		match self {
+			FileFormat::Edf => i16::MAX.into(),
+			FileFormat::Bdf => (1 << 23) - 1,
+		}
+	}
+}
+
+impl fmt::Display for FileFormat {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			FileFormat::Edf => write!(f, "EDF"),
+			FileFormat::Bdf => write!(f, "BDF"),
+		}
+	}
+}
+
+/// The subtype of a file, as given by its reserved field.
+///
+/// BDF+ files, marked `BDF+C` or `BDF+D`, map to the EDF+ variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileType {
+	/// Plain EDF, without annotations.
+	Edf,
+	/// EDF+ with contiguous data records.
+	EdfPlusContinuous,
+	/// EDF+ whose data records may have gaps between them, as given by the
+	/// time-keeping annotations.
+	EdfPlusDiscontinuous,
+}
+
+impl fmt::Display for FileType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			FileType::Edf => write!(f, "EDF"),
+			FileType::EdfPlusContinuous => write!(f, "EDF+C"),
+			FileType::EdfPlusDiscontinuous => write!(f, "EDF+D"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Header {
+	pub format: FileFormat,
+	/// The local patient identification, without the padding.
 	pub patient_info: String,
+	/// The local recording identification, without the padding.
 	pub recording_id: String,
-	/// The start date and time of the recording/
+	/// The start date and time of the recording. For EDF+ files, this
+	/// includes the fraction of a second given by the onset of the first
+	/// data record.
 	pub start_datetime: NaiveDateTime,
 	// The number of bytes in the header.
 	pub size: usize,
+	/// The reserved field, without the padding.
 	pub reserved: String,
 	// The number of records. If unknown (value is -1), then it is `None`.
 	pub records_len: Option<usize>,
-	// The duration of a a record in seconds.
-	pub duration: usize,
+	/// The number of complete data records in the file, from its size.
+	///
+	/// This is set by the reader, and is `None` for headers that were not
+	/// read from a file or whose records are empty.
+	#[serde(skip)]
+	pub inferred_records_len: Option<usize>,
+	/// The duration of a data record.
+	pub duration: Duration,
 	// The number of signals in the record
 	pub signals_len: u32,
+	/// The headers of the signals, in the order they appear in each record.
+	pub signals: Vec<SignalHeader>,
+	/// Problems that were recovered from when reading in lenient mode.
+	#[serde(skip)]
+	pub warnings: Vec<HeaderError>,
+	#[serde(default)]
+	raw_patient_info: Vec<u8>,
+	#[serde(default)]
+	raw_recording_id: Vec<u8>,
+	#[serde(default)]
+	raw_reserved: Vec<u8>,
 }
 
 impl Header {
+	#[allow(clippy::too_many_arguments)]
 	pub fn new(
+		format: FileFormat,
 		patient_info: String,
 		recording_id: String,
 		start_date: NaiveDate,
@@ -203,58 +1425,227 @@ impl Header {
 		size: usize,
 		reserved: String,
 		records_len: Option<usize>,
-		duration: usize,
+		duration: Duration,
 		signals_len: u32,
 	) -> Self {
 		let start_datetime = NaiveDateTime::new(start_date, start_time);
 		Self {
-			patient_info,
-			recording_id,
+			format,
+			raw_patient_info: pad(&patient_info, 80),
+			raw_recording_id: pad(&recording_id, 80),
+			raw_reserved: pad(&reserved, 44),
+			patient_info: trim_padding(&patient_info),
+			recording_id: trim_padding(&recording_id),
 			start_datetime,
 			size,
-			reserved,
+			reserved: trim_padding(&reserved),
 			records_len,
+			inferred_records_len: None,
 			duration,
 			signals_len,
+			signals: Vec::new(),
+			warnings: Vec::new(),
+		}
+	}
+
+	/// The local patient identification exactly as stored, including the
+	/// padding.
+	pub fn raw_patient_info(&self) -> &[u8] {
+		&self.raw_patient_info
+	}
+
+	/// The local recording identification exactly as stored, including the
+	/// padding.
+	pub fn raw_recording_id(&self) -> &[u8] {
+		&self.raw_recording_id
+	}
+
+	/// The reserved field exactly as stored, including the padding.
+	pub fn raw_reserved(&self) -> &[u8] {
+		&self.raw_reserved
+	}
+
+	/// The number of bytes in each data record.
+	///
+	/// Returns `None` if the size does not fit in a `usize`.
+	pub fn record_size(&self) -> Option<usize> {
+		self.signals
+			.iter()
+			.try_fold(0usize, |sum, s| sum.checked_add(s.samples_per_record))?
+			.checked_mul(self.format.bytes_per_sample())
+	}
+
+	/// The number of data records: the declared number, or if that is unknown,
+	/// the number inferred from the size of the file.
+	pub fn effective_records_len(&self) -> Option<usize> {
+		self.records_len.or(self.inferred_records_len)
+	}
+
+	/// The date and time of an onset in seconds from the starttime field, as
+	/// of an annotation or `Record::onset`.
+	///
+	/// The onsets of EDF+ files count from the whole second of the
+	/// starttime field, not from the sub-second `start_datetime`.
+	pub fn datetime_at(&self, onset: f64) -> NaiveDateTime {
+		let start = self
+			.start_datetime
+			.with_nanosecond(0)
+			.unwrap_or(self.start_datetime);
+		start + chrono::Duration::nanoseconds((onset * 1e9).round() as i64)
+	}
+
+	/// Whether the number of data records is -1, as in the header of a file
+	/// that is still being written.
+	pub fn is_provisional(&self) -> bool {
+		self.records_len.is_none()
+	}
+
+	/// The subtype of the file, from the start of the reserved field.
+	pub fn file_type(&self) -> FileType {
+		let subtype = self
+			.reserved
+			.strip_prefix("EDF+")
+			.or_else(|| self.reserved.strip_prefix("BDF+"));
+		match subtype.and_then(|s| s.chars().next()) {
+			Some('C') => FileType::EdfPlusContinuous,
+			Some('D') => FileType::EdfPlusDiscontinuous,
+			_ => FileType::Edf,
 		}
 	}
+
+	/// The indices of the ordinary signals, which hold samples rather than
+	/// annotations.
+	pub fn ordinary_signals(&self) -> Vec<usize> {
+		(0..self.signals.len())
+			.filter(|&i| !self.signals[i].is_annotation())
+			.collect()
+	}
+
+	/// The indices of the annotation signals.
+	///
+	/// EDF+ allows more than one, for annotations that do not fit in a
+	/// single signal. The first also holds the time-keeping TAL of each data
+	/// record.
+	pub fn annotation_signals(&self) -> Vec<usize> {
+		(0..self.signals.len())
+			.filter(|&i| self.signals[i].is_annotation())
+			.collect()
+	}
+
+	/// The sampling rate of the signal at the given index in Hz.
+	pub fn sampling_rate(&self, signal: usize) -> Option<f64> {
+		self.signals.get(signal)?.sampling_rate(self.duration)
+	}
+
+	/// Interprets the start date and time as local time in the given time zone.
+	///
+	/// EDF stores the start as local time without an offset. Around a
+	/// daylight-saving transition the local time may be ambiguous or may not
+	/// exist at all, which is reflected in the returned `LocalResult`.
+	pub fn start_in<Tz: TimeZone>(&self, tz: &Tz) -> LocalResult<DateTime<Tz>> {
+		tz.from_local_datetime(&self.start_datetime)
+	}
+
+	/// Parses the EDF+ subfields of the local patient identification.
+	///
+	/// Fails for plain EDF files, whose patient field is free text.
+	pub fn patient(&self) -> Result<PatientId> {
+		PatientId::parse(&self.patient_info)
+	}
+
+	/// Parses the local patient identification, keeping the raw text if it
+	/// does not follow EDF+.
+	pub fn patient_identification(&self) -> PatientInfo {
+		PatientInfo::parse(&self.patient_info)
+	}
+
+	/// Parses the EDF+ subfields of the local recording identification.
+	///
+	/// Fails for plain EDF files, whose recording field is free text.
+	pub fn recording(&self) -> Result<RecordingId> {
+		RecordingId::parse(&self.recording_id)
+	}
+
+	/// Parses the local recording identification, keeping the raw text if it
+	/// does not follow EDF+.
+	pub fn recording_identification(&self) -> RecordingInfo {
+		RecordingInfo::parse(&self.recording_id)
+	}
 }
 
 impl fmt::Display for Header {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		let records_len = match self.records_len {
-			None => "-1".to_string(),
-			Some(v) => v.to_string(),
+		let records_len = match (self.records_len, self.inferred_records_len) {
+			(None, None) => "-1".to_string(),
+			(None, Some(n)) => format!("-1 ({} inferred from the file size)", n),
+			(Some(v), _) => v.to_string(),
 		};
 
 		write!(
 			f,
-			"\n## Header\n{}\nRecording ID: {}\nStart Time: {}\nSize of header: {} B\nReserved: {}\n{} data records\n{} seconds\n{} signals",
+			"\n## {} Header\n{}\nRecording ID: {}\nStart Time: {}\nSize of header: {} B\nReserved: {}\n{} data records\n{} seconds\n{} signals",
+			self.format,
 			self.patient_info,
 			self.recording_id,
 			self.start_datetime,
 			self.size,
 			self.reserved,
 			records_len,
-			self.duration,
+			format_seconds(self.duration),
 			self.signals_len
-		)
+		)?;
+		for (i, signal) in self.signals.iter().enumerate() {
+			write!(f, "\n\n### Signal {}\n{}", i, signal)?;
+			if let Some(rate) = signal.sampling_rate(self.duration) {
+				write!(f, "\nSampling rate: {} Hz", rate)?;
+			}
+		}
+		Ok(())
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use chrono::NaiveDate;
+	use std::cell::Cell;
+	use std::io;
+	use std::rc::Rc;
+	use std::time::Duration;
+
+	use chrono::{LocalResult, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+	use chrono_tz::Europe::Amsterdam;
 
-	use super::Reader;
+	use super::{
+		format_seconds, parse_field, parse_seconds, parse_start_date, FileFormat, FileType, Header,
+		Reader, ReaderBuilder, StartdatePrecedence, TextEncoding,
+	};
+	use crate::crc::read_record_crcs;
+	use crate::error::{ErrorKind, HeaderError};
+	use crate::fixture::{TestHeader, TestSignal};
+	use crate::generator::{Generator, Pattern};
+	use crate::record::Record;
+
+	fn header_starting_at(date: NaiveDate, time: NaiveTime) -> Header {
+		Header::new(
+			FileFormat::Edf,
+			String::new(),
+			String::new(),
+			date,
+			time,
+			256,
+			String::new(),
+			None,
+			Duration::from_secs(1),
+			0,
+		)
+	}
 
 	// Check that month and date are in the right order.
 	#[test]
 	fn parse_start_date_simple() {
 		let s = String::from("31.01.01");
 		assert_eq!(
-			Reader::parse_start_date(s),
-			Ok(NaiveDate::from_ymd(2001, 1, 31))
+			parse_start_date(&s),
+			Some(NaiveDate::from_ymd_opt(2001, 1, 31).unwrap())
 		);
 	}
 
@@ -262,8 +1653,8 @@ mod tests {
 	fn parse_start_date_y2k() {
 		let s = String::from("01.01.00");
 		assert_eq!(
-			Reader::parse_start_date(s),
-			Ok(NaiveDate::from_ymd(2000, 1, 1))
+			parse_start_date(&s),
+			Some(NaiveDate::from_ymd_opt(2000, 1, 1).unwrap())
 		);
 	}
 
@@ -271,8 +1662,8 @@ mod tests {
 	fn parse_start_date_before_clip() {
 		let s = String::from("01.01.85");
 		assert_eq!(
-			Reader::parse_start_date(s),
-			Ok(NaiveDate::from_ymd(1985, 1, 1))
+			parse_start_date(&s),
+			Some(NaiveDate::from_ymd_opt(1985, 1, 1).unwrap())
 		);
 	}
 
@@ -280,8 +1671,790 @@ mod tests {
 	fn parse_start_date_after_clip() {
 		let s = String::from("31.12.84");
 		assert_eq!(
-			Reader::parse_start_date(s),
-			Ok(NaiveDate::from_ymd(2084, 12, 31))
+			parse_start_date(&s),
+			Some(NaiveDate::from_ymd_opt(2084, 12, 31).unwrap())
+		);
+	}
+
+	#[test]
+	fn parse_field_error_shows_raw_bytes() {
+		let err = parse_field(
+			b"3l.0\x001.01",
+			|raw| HeaderError::InvalidDate { raw },
+			parse_start_date,
+		)
+		.unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			r#"invalid startdate: "3l.0\x001.01" (expected dd.mm.yy)"#
+		);
+	}
+
+	#[test]
+	fn start_in_time_zone() {
+		let date = |m, d| NaiveDate::from_ymd_opt(2021, m, d).unwrap();
+		let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+		let hdr = header_starting_at(date(1, 15), time(22, 0));
+		let start = hdr.start_in(&Amsterdam).unwrap();
+		assert_eq!(
+			start.with_timezone(&Utc),
+			Utc.with_ymd_and_hms(2021, 1, 15, 21, 0, 0).unwrap()
+		);
+
+		// Clocks went back from 03:00 to 02:00 on 31 October 2021.
+		let hdr = header_starting_at(date(10, 31), time(2, 30));
+		assert!(matches!(
+			hdr.start_in(&Amsterdam),
+			LocalResult::Ambiguous(..)
+		));
+
+		// Clocks went forward from 02:00 to 03:00 on 28 March 2021.
+		let hdr = header_starting_at(date(3, 28), time(2, 30));
+		assert_eq!(hdr.start_in(&Amsterdam), LocalResult::None);
+	}
+
+	#[test]
+	fn read_version_detects_format() {
+		let mut bdf = TestHeader {
+			reserved: "24BIT",
+			..TestHeader::default()
+		}
+		.to_bytes();
+		bdf[..8].copy_from_slice(b"\xffBIOSEMI");
+		let hdr = Reader::from_bytes(&bdf).map(Reader::into_header);
+		bdf[0] = b'1';
+		let err = Reader::from_bytes(&bdf).unwrap_err();
+
+		assert_eq!(hdr.unwrap().format, FileFormat::Bdf);
+		assert_eq!(err.to_string(), "invalid version");
+	}
+
+	#[test]
+	fn read_records() {
+		let mut bytes = TestHeader {
+			records_len: "-1",
+			signals: vec![
+				TestSignal {
+					label: "EEG Fpz-Cz",
+					physical_min: "-192",
+					physical_max: "192",
+					samples_per_record: "2",
+					..TestSignal::default()
+				},
+				TestSignal {
+					label: "Resp oro-nasal",
+					physical_dimension: "",
+					physical_min: "-2048",
+					physical_max: "2047",
+					digital_min: "-2048",
+					digital_max: "2047",
+					..TestSignal::default()
+				},
+			],
+			..TestHeader::default()
+		}
+		.to_bytes();
+		for sample in [1i16, -2, 3, -32768, 32767, 0] {
+			bytes.extend(sample.to_le_bytes());
+		}
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let records = reader.read_records().unwrap();
+		let signal = reader.read_signal(0).unwrap();
+		let hdr = reader.into_header();
+		bytes.pop();
+		let inferred = Reader::from_bytes(&bytes).unwrap().read_records().unwrap();
+		// With a declared number of records, a record cut short is an error.
+		bytes[236..244].copy_from_slice(b"2       ");
+		let err = Reader::from_bytes(&bytes)
+			.unwrap()
+			.read_records()
+			.unwrap_err();
+
+		assert_eq!(records.len(), 2);
+		assert_eq!(records[0].samples(0), Some(&[1, -2][..]));
+		assert_eq!(records[0].samples(1), Some(&[3][..]));
+		assert_eq!(records[1].signals(), [vec![-32768, 32767], vec![0]]);
+		assert_eq!(records[1].samples(2), None);
+		assert_eq!(records[1].physical_samples(&hdr, 1), Some(vec![0.0]));
+		assert_eq!(signal.len(), 4);
+		assert_eq!(signal[2], -192.0);
+		assert_eq!(hdr.records_len, None);
+		assert_eq!(hdr.inferred_records_len, Some(2));
+		assert_eq!(inferred, records[..1]);
+		assert!(matches!(err.kind(), ErrorKind::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+	}
+
+	#[test]
+	fn stream_records() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.channel("Sine", 4, Pattern::Sine { frequency: 1.0 })
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let expected = reader.read_records().unwrap();
+		// The iterator starts from the first record wherever the reader is.
+		let streamed: Vec<Record> = reader.records().map(Result::unwrap).collect();
+		let last = reader.read_record(2).unwrap();
+		let first = reader.read_record(0).unwrap();
+		let past_end = reader.read_record(3);
+		bytes.pop();
+		let mut truncated = Reader::from_bytes(&bytes).unwrap();
+		let mut records = truncated.records();
+
+		assert_eq!(streamed, expected);
+		assert_eq!(last, expected[2]);
+		assert_eq!(first, expected[0]);
+		assert!(past_end.is_err());
+		assert!(records.next().unwrap().is_ok());
+		assert!(records.next().unwrap().is_ok());
+		assert!(records.next().unwrap().is_err());
+		assert!(records.next().is_none());
+	}
+
+	#[test]
+	fn read_time_range() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.duration(Duration::from_secs(2))
+			.channel("Fast", 8, Pattern::Noise)
+			.channel("Slow", 2, Pattern::Sine { frequency: 0.25 })
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let fast = reader.read_signal(0).unwrap();
+		let slow = reader.read_signal(1).unwrap();
+		// From the middle of the first record to the middle of the second.
+		let range = reader
+			.read_range(Duration::from_millis(1500), Duration::from_secs(2), &[1, 0])
+			.unwrap();
+		let past_end = reader
+			.read_range(Duration::from_secs(5), Duration::from_secs(10), &[1])
+			.unwrap();
+		let unbounded = reader
+			.read_range(Duration::from_secs(5), Duration::MAX, &[1])
+			.unwrap();
+		let beyond = reader
+			.read_range(Duration::MAX, Duration::MAX, &[1])
+			.unwrap();
+
+		assert_eq!(range, [slow[2..4].to_vec(), fast[6..14].to_vec()]);
+		assert_eq!(past_end, [slow[5..].to_vec()]);
+		assert_eq!(unbounded, past_end);
+		assert_eq!(beyond, [Vec::<f64>::new()]);
+	}
+
+	#[test]
+	fn subsecond_start() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.channel("Sine", 4, Pattern::Sine { frequency: 1.0 })
+			.annotation(1.5, "Lights off")
+			.write(&mut bytes)
+			.unwrap();
+		let tal = bytes.windows(5).position(|w| w == b"+0\x14\x14\0").unwrap();
+		bytes[tal..tal + 7].copy_from_slice(b"+0.5\x14\x14\0");
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let hdr = reader.header().clone();
+		let onsets: Vec<Option<f64>> = reader
+			.records()
+			.map(|record| record.unwrap().onset(&hdr).unwrap())
+			.collect();
+
+		assert_eq!(hdr.start_datetime.nanosecond(), 500_000_000);
+		assert_eq!(onsets, [Some(0.5), Some(1.0), Some(2.0)]);
+		assert_eq!(
+			hdr.datetime_at(1.5) - hdr.start_datetime,
+			chrono::Duration::seconds(1)
+		);
+	}
+
+	/// A reader that counts the bytes read through it.
+	struct Counting<R> {
+		inner: R,
+		read: Rc<Cell<usize>>,
+	}
+
+	impl<R: io::Read> io::Read for Counting<R> {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			let n = self.inner.read(buf)?;
+			self.read.set(self.read.get() + n);
+			Ok(n)
+		}
+	}
+
+	impl<R: io::Seek> io::Seek for Counting<R> {
+		fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+			self.inner.seek(pos)
+		}
+	}
+
+	#[test]
+	fn read_only_annotation_signals() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(10)
+			.channel("Noise", 1000, Pattern::Noise)
+			.annotation(4.5, "Lights off")
+			.write(&mut bytes)
+			.unwrap();
+		let read = Rc::new(Cell::new(0));
+		let mut reader = Reader::from_reader(Counting {
+			inner: io::Cursor::new(&bytes),
+			read: Rc::clone(&read),
+		})
+		.unwrap();
+		read.set(0);
+		let annotations = reader.read_annotations().unwrap();
+
+		assert_eq!(annotations[0].text, "Lights off");
+		let annotation_bytes = reader.header().signals[1].samples_per_record * 2;
+		assert_eq!(read.get(), 10 * annotation_bytes);
+	}
+
+	#[test]
+	fn merge_annotation_signals() {
+		let mut bytes = Vec::new();
+		// The annotations alternate between the signals, so the later one of
+		// the first record is in the first signal.
+		Generator::new()
+			.records(2)
+			.channel("Sine", 4, Pattern::Sine { frequency: 1.0 })
+			.annotation(0.7, "Lights off")
+			.annotation(0.2, "Calibration")
+			.annotation(1.5, "Arousal")
+			.annotation_signals(2)
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let hdr = reader.header().clone();
+		let annotations = reader.read_annotations().unwrap();
+		let texts: Vec<&str> = annotations.iter().map(|a| a.text.as_str()).collect();
+
+		assert_eq!(hdr.ordinary_signals(), [0]);
+		assert_eq!(hdr.annotation_signals(), [1, 2]);
+		assert_eq!(texts, ["Calibration", "Lights off", "Arousal"]);
+		assert_eq!(
+			reader.read_record(1).unwrap().onset(&hdr).unwrap(),
+			Some(1.0)
+		);
+	}
+
+	#[test]
+	fn read_growing_file() {
+		let path = std::env::temp_dir().join(format!("edf-growing-{}.edf", std::process::id()));
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.channel("Sine", 4, Pattern::Sine { frequency: 1.0 })
+			.write(&mut bytes)
+			.unwrap();
+		bytes[236..244].copy_from_slice(b"-1      ");
+		// The header and the first record, with the second partly written.
+		std::fs::write(&path, &bytes[..512 + 8 + 3]).unwrap();
+		let mut reader = Reader::from_path(&path).unwrap();
+		let window = Duration::from_secs(10);
+		let written = reader.read_range(Duration::ZERO, window, &[0]).unwrap();
+		std::fs::write(&path, &bytes).unwrap();
+		let grown = reader.read_range(Duration::ZERO, window, &[0]).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert!(reader.header().is_provisional());
+		assert_eq!(written[0].len(), 4);
+		assert_eq!(grown[0].len(), 12);
+	}
+
+	#[test]
+	fn read_selected_channels() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(2)
+			.channel("A", 4, Pattern::Noise)
+			.channel("B", 2, Pattern::Noise)
+			.channel("C", 3, Pattern::Noise)
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let record = reader.read_record(1).unwrap();
+		let selected = reader.read_record_channels(1, &[2, 0]).unwrap();
+
+		assert_eq!(selected.samples(0), record.samples(0));
+		assert_eq!(selected.samples(1), Some(&[][..]));
+		assert_eq!(selected.samples(2), record.samples(2));
+		assert!(reader.read_record_channels(2, &[0]).is_err());
+	}
+
+	#[test]
+	fn file_type() {
+		let mut hdr = header_starting_at(
+			NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(),
+			NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+		);
+		for (reserved, file_type) in [
+			("", FileType::Edf),
+			("EDF+C", FileType::EdfPlusContinuous),
+			("EDF+D   ", FileType::EdfPlusDiscontinuous),
+			("BDF+D", FileType::EdfPlusDiscontinuous),
+			("EDF+", FileType::Edf),
+			("24BIT", FileType::Edf),
+		] {
+			hdr.reserved = reserved.to_string();
+			assert_eq!(hdr.file_type(), file_type, "{:?}", reserved);
+		}
+	}
+
+	#[test]
+	fn parse_record_duration() {
+		for (s, duration, formatted) in [
+			("1", Duration::from_secs(1), "1"),
+			("30.000", Duration::from_secs(30), "30"),
+			("0.002", Duration::from_millis(2), "0.002"),
+			(".1", Duration::from_millis(100), "0.1"),
+			("0.000001", Duration::from_micros(1), "0.000001"),
+		] {
+			assert_eq!(parse_seconds(s), Some(duration), "{}", s);
+			assert_eq!(format_seconds(duration), formatted);
+		}
+		for s in ["", ".", "-1", "1e-3", "0.0000000001"] {
+			assert_eq!(parse_seconds(s), None, "{}", s);
+		}
+	}
+
+	#[test]
+	fn lenient_numbers() {
+		let bytes = TestHeader {
+			duration: "1,000",
+			signals: vec![TestSignal {
+				physical_min: "-1,5",
+				physical_max: "+ 1,5",
+				samples_per_record: "100",
+				..TestSignal::default()
+			}],
+			..TestHeader::default()
+		}
+		.to_bytes();
+		let strict = Reader::from_bytes(&bytes).map(Reader::into_header);
+		let lenient = ReaderBuilder::new()
+			.lenient(true)
+			.from_bytes(&bytes)
+			.map(Reader::into_header);
+
+		assert_eq!(
+			strict.unwrap_err().to_string(),
+			r#"invalid duration of a data record: "1,000   " (expected a non-negative number of seconds)"#
+		);
+		let hdr = lenient.unwrap();
+		assert_eq!(hdr.duration, Duration::from_secs(1));
+		assert_eq!(hdr.signals[0].physical_min, -1.5);
+		assert_eq!(hdr.signals[0].physical_max, 1.5);
+		let warnings: Vec<String> = hdr.warnings.iter().map(|w| w.to_string()).collect();
+		assert_eq!(
+			warnings,
+			[
+				r#"invalid duration of a data record: "1,000   " (expected a non-negative number of seconds)"#,
+				r#"signal 0: invalid physical minimum: "-1,5    " (expected a number)"#,
+				r#"signal 0: invalid physical maximum: "+ 1,5   " (expected a number)"#,
+			]
+		);
+	}
+
+	#[test]
+	fn lenient_dates_padding_and_text() {
+		let mut bytes = TestHeader {
+			patient_info: "X F X Jos",
+			startdate: "01/03/21",
+			starttime: "22:00:00",
+			..TestHeader::default()
+		}
+		.to_bytes();
+		bytes[8 + 9] = 0xe9;
+		// The number of data records padded with NUL bytes.
+		bytes[236..244].copy_from_slice(b"0\0\0\0\0\0\0\0");
+		let strict = Reader::options()
+			.from_bytes(&bytes)
+			.map(Reader::into_header);
+		let lenient = Reader::options()
+			.lenient(true)
+			.from_bytes(&bytes)
+			.map(Reader::into_header);
+
+		assert!(matches!(strict.unwrap_err().kind(), ErrorKind::Utf8(_)));
+		let hdr = lenient.unwrap();
+		assert_eq!(hdr.patient_info, "X F X José");
+		assert_eq!(
+			hdr.start_datetime,
+			NaiveDate::from_ymd_opt(2021, 3, 1)
+				.unwrap()
+				.and_hms_opt(22, 0, 0)
+				.unwrap()
+		);
+		assert_eq!(hdr.records_len, Some(0));
+		assert_eq!(hdr.warnings.len(), 4);
+		assert_eq!(
+			hdr.warnings[1].to_string(),
+			r#"invalid startdate: "01/03/21" (expected dd.mm.yy)"#
+		);
+	}
+
+	#[test]
+	fn read_from_bytes() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(2)
+			.channel("Sine", 4, Pattern::Sine { frequency: 1.0 })
+			.write(&mut bytes)
+			.unwrap();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		assert_eq!(reader.header().signals[0].label, "Sine");
+		assert_eq!(reader.read_signal(0).unwrap()[1], 1000.0);
+		assert!(Reader::from_bytes(&bytes[..100]).is_err());
+	}
+
+	#[test]
+	fn read_from_memory() {
+		let path = std::env::temp_dir().join(format!("edf-memory-{}.edf", std::process::id()));
+		Generator::new()
+			.records(3)
+			.channel("Noise", 4, Pattern::Noise)
+			.annotation(1.5, "Lights off")
+			.to_path(&path)
+			.unwrap();
+		let bytes = std::fs::read(&path).unwrap();
+		let mut reader = Reader::from_path(&path).unwrap();
+		let records = reader.read_records().unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		let mut cursor = Reader::from_reader(io::Cursor::new(bytes)).unwrap();
+		assert_eq!(cursor.header(), reader.header());
+		assert_eq!(cursor.read_records().unwrap(), records);
+		assert_eq!(cursor.read_annotations().unwrap()[0].text, "Lights off");
+	}
+
+	#[test]
+	fn verify_records() {
+		let path = std::env::temp_dir().join(format!("edf-verify-{}.edf", std::process::id()));
+		let mut sidecar = path.clone().into_os_string();
+		sidecar.push(".crc");
+		Generator::new()
+			.records(4)
+			.channel("Noise", 4, Pattern::Noise)
+			.crc_sidecar(true)
+			.to_path(&path)
+			.unwrap();
+		let crcs = read_record_crcs(std::fs::read(&sidecar).unwrap().as_slice()).unwrap();
+		let intact = Reader::from_path(&path).unwrap().verify_records(&crcs);
+		let mut written = Vec::new();
+		let written_len = Reader::from_path(&path)
+			.unwrap()
+			.write_record_crcs(&mut written);
+		assert_eq!(written_len.unwrap(), 4);
+		assert_eq!(written, std::fs::read(&sidecar).unwrap());
+		// Flip a bit in the third record and drop the last one.
+		let mut bytes = std::fs::read(&path).unwrap();
+		bytes[512 + 2 * 8] ^= 1;
+		bytes.truncate(512 + 3 * 8);
+		bytes[236..244].copy_from_slice(b"-1      ");
+		std::fs::write(&path, &bytes).unwrap();
+		let damaged = Reader::from_path(&path).unwrap().verify_records(&crcs);
+		std::fs::remove_file(&path).unwrap();
+		std::fs::remove_file(&sidecar).unwrap();
+
+		assert_eq!(crcs.len(), 4);
+		assert_eq!(intact.unwrap(), []);
+		assert_eq!(damaged.unwrap(), [2, 3]);
+	}
+
+	#[test]
+	fn concurrent_reads() {
+		let path = std::env::temp_dir().join(format!("edf-concurrent-{}.edf", std::process::id()));
+		Generator::new()
+			.records(8)
+			.channel("Noise", 4, Pattern::Noise)
+			.to_path(&path)
+			.unwrap();
+		let mut reader = Reader::from_path(&path).unwrap();
+		let records = reader.read_records().unwrap();
+		let signal = reader.read_signal(0).unwrap();
+		let shared = &reader;
+		let windows: Vec<_> = std::thread::scope(|scope| {
+			let threads: Vec<_> = (0..4)
+				.map(|i| scope.spawn(move || shared.read_records_at(2 * i..2 * i + 2)))
+				.collect();
+			threads.into_iter().map(|t| t.join().unwrap()).collect()
+		});
+		let window = reader.read_signal_at(0, 3..5).unwrap();
+		let err = reader.read_records_at(7..9).unwrap_err();
+		// Offsets past what a file can hold are errors rather than overflows.
+		let far = reader.read_records_at(usize::MAX - 1..usize::MAX);
+		let far_signal = reader.read_signal_at(0, usize::MAX / 2..usize::MAX);
+		std::fs::remove_file(&path).unwrap();
+
+		let windows: Vec<Record> = windows.into_iter().flat_map(Result::unwrap).collect();
+		assert_eq!(windows, records);
+		assert_eq!(window, signal[12..20]);
+		assert!(matches!(err.kind(), ErrorKind::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+		assert!(far.is_err());
+		assert!(far_signal.is_err());
+	}
+
+	#[test]
+	fn malformed_fixed_fields() {
+		let mut errors = Vec::new();
+		for (startdate, starttime, records_len) in [
+			("31.02.21", "22.00.00", "0"),
+			("01.03.21", "25.00.00", "0"),
+			("01.03.21", "22.00.00", "-2"),
+		] {
+			let bytes = TestHeader {
+				startdate,
+				starttime,
+				records_len,
+				..TestHeader::default()
+			}
+			.to_bytes();
+			errors.push(Reader::from_bytes(&bytes).unwrap_err());
+		}
+
+		let kinds: Vec<&ErrorKind> = errors.iter().map(|e| e.kind()).collect();
+		assert!(matches!(
+			kinds[..],
+			[
+				ErrorKind::Header(HeaderError::InvalidDate { .. }),
+				ErrorKind::Header(HeaderError::InvalidTime { .. }),
+				ErrorKind::Header(HeaderError::NegativeRecordCount { value: -2 }),
+			]
+		));
+		assert_eq!(
+			errors[1].to_string(),
+			r#"invalid starttime: "25.00.00" (expected hh.mm.ss)"#
+		);
+	}
+
+	#[test]
+	fn text_encodings() {
+		let mut bytes = TestHeader {
+			patient_info: "X F X Jos",
+			..TestHeader::default()
+		}
+		.to_bytes();
+		// A Latin-1 é right after the name, in the padding of the field.
+		bytes[8 + 9] = 0xe9;
+		let read = |encoding| {
+			ReaderBuilder::new()
+				.encoding(encoding)
+				.from_bytes(&bytes)
+				.map(Reader::into_header)
+		};
+		let utf8 = read(TextEncoding::Utf8);
+		let ascii = read(TextEncoding::Ascii);
+		let latin1 = read(TextEncoding::Latin1);
+		let lossy = read(TextEncoding::Lossy);
+
+		assert!(matches!(utf8.unwrap_err().kind(), ErrorKind::Utf8(_)));
+		assert!(matches!(
+			ascii.unwrap_err().kind(),
+			ErrorKind::Header(HeaderError::InvalidText {
+				field: "local patient identification",
+				..
+			})
+		));
+		let latin1 = latin1.unwrap();
+		assert_eq!(latin1.patient_info, "X F X José");
+		assert_eq!(latin1.raw_patient_info().len(), 80);
+		assert_eq!(&latin1.raw_patient_info()[6..11], b"Jos\xe9 ");
+		assert_eq!(lossy.unwrap().patient_info, "X F X Jos\u{fffd}");
+	}
+
+	#[test]
+	fn record_size_limit() {
+		let bytes = TestHeader {
+			records_len: "1",
+			signals: vec![TestSignal {
+				samples_per_record: "99999999",
+				..TestSignal::default()
+			}],
+			..TestHeader::default()
+		}
+		.to_bytes();
+		let err = Reader::from_bytes(&bytes).unwrap_err();
+
+		assert_eq!(
+			err.to_string(),
+			"data records exceed the limit of 67108864 bytes"
+		);
+	}
+
+	#[test]
+	fn read_signal_headers() {
+		let header = |physical_max| {
+			TestHeader {
+				signals: vec![
+					TestSignal {
+						label: "EEG Fpz-Cz",
+						transducer: "AgAgCl electrode",
+						physical_min: "-192",
+						physical_max: "192",
+						digital_min: "-2048",
+						digital_max: "2047",
+						prefiltering: "HP:0.1Hz LP:75Hz",
+						samples_per_record: "100",
+						..TestSignal::default()
+					},
+					TestSignal {
+						label: "Resp oro-nasal",
+						transducer: "Thermistor",
+						physical_dimension: "",
+						physical_min: "-2048",
+						physical_max,
+						digital_min: "-2048",
+						digital_max: "2047",
+						prefiltering: "HP:0.03Hz",
+						samples_per_record: "1",
+					},
+				],
+				..TestHeader::default()
+			}
+			.to_bytes()
+		};
+		let hdr = Reader::from_bytes(&header("2047")).unwrap().into_header();
+		let err = Reader::from_bytes(&header("20x7")).unwrap_err();
+
+		assert_eq!(hdr.signals.len(), 2);
+		assert_eq!(hdr.signals[0].label, "EEG Fpz-Cz");
+		assert_eq!(hdr.signals[0].physical_min, -192.0);
+		assert_eq!(hdr.signals[1].transducer, "Thermistor");
+		assert_eq!(hdr.signals[1].physical_dimension, "");
+		assert_eq!(hdr.signals[1].samples_per_record, 1);
+		assert_eq!(
+			err.to_string(),
+			r#"signal 1: invalid physical maximum: "20x7    " (expected a number)"#
+		);
+	}
+
+	#[test]
+	fn header_size_mismatch() {
+		let bytes = TestHeader {
+			size: Some("512"),
+			..TestHeader::default()
+		}
+		.to_bytes();
+		let strict = Reader::from_bytes(&bytes).map(Reader::into_header);
+		let lenient = ReaderBuilder::new()
+			.lenient(true)
+			.from_bytes(&bytes)
+			.map(Reader::into_header);
+
+		assert_eq!(
+			strict.unwrap_err().to_string(),
+			"header size of 512 bytes does not match the 256 bytes expected for 0 signals"
+		);
+		let hdr = lenient.unwrap();
+		assert_eq!(hdr.size, 256);
+		assert_eq!(
+			hdr.warnings,
+			[HeaderError::SizeMismatch {
+				declared: 512,
+				expected: 256,
+			}]
+		);
+	}
+
+	#[test]
+	fn reconcile_start_date() {
+		let bytes = TestHeader {
+			recording_id: "Startdate 02-MAR-2012 X X X",
+			startdate: "02.03.02",
+			starttime: "13.00.00",
+			..TestHeader::default()
+		}
+		.to_bytes();
+		let strict = Reader::from_bytes(&bytes).map(Reader::into_header);
+		let lenient = ReaderBuilder::new()
+			.lenient(true)
+			.from_bytes(&bytes)
+			.map(Reader::into_header);
+
+		let mismatch = HeaderError::StartdateMismatch {
+			date: NaiveDate::from_ymd_opt(2002, 3, 2).unwrap(),
+			subfield: NaiveDate::from_ymd_opt(2012, 3, 2).unwrap(),
+		};
+		assert_eq!(
+			strict.unwrap_err().to_string(),
+			"startdate 02.03.02 does not match the startdate subfield 02-MAR-2012"
+		);
+		let hdr = lenient.unwrap();
+		assert_eq!(
+			hdr.start_datetime.date(),
+			NaiveDate::from_ymd_opt(2012, 3, 2).unwrap()
+		);
+		assert_eq!(hdr.warnings, [mismatch]);
+	}
+
+	#[test]
+	fn startdate_precedence_and_years_after_2084() {
+		let header = |startdate, recording_id| {
+			TestHeader {
+				recording_id,
+				startdate,
+				starttime: "13.00.00",
+				..TestHeader::default()
+			}
+			.to_bytes()
+		};
+		let read = |bytes: Vec<u8>, precedence: StartdatePrecedence| {
+			ReaderBuilder::new()
+				.lenient(true)
+				.startdate_precedence(precedence)
+				.from_bytes(&bytes)
+				.map(|r| r.into_header().start_datetime.date())
+		};
+		let after_2084 = read(
+			header("02.03.yy", "Startdate 02-MAR-2090 X X X"),
+			StartdatePrecedence::Subfield,
+		);
+		let without_subfield = read(header("02.03.yy", "X"), StartdatePrecedence::Subfield);
+		let day_subfield = read(
+			header("03.03.yy", "Startdate 02-MAR-2090 X X X"),
+			StartdatePrecedence::Subfield,
+		);
+		let day_field = read(
+			header("03.03.yy", "Startdate 02-MAR-2090 X X X"),
+			StartdatePrecedence::Startdate,
+		);
+		let day_strict = Reader::from_bytes(&header("03.03.yy", "Startdate 02-MAR-2090 X X X"))
+			.map(Reader::into_header);
+		let field_preferred = read(
+			header("02.03.02", "Startdate 02-MAR-2012 X X X"),
+			StartdatePrecedence::Startdate,
+		);
+
+		assert_eq!(
+			after_2084.unwrap(),
+			NaiveDate::from_ymd_opt(2090, 3, 2).unwrap()
+		);
+		assert_eq!(
+			without_subfield.unwrap_err().to_string(),
+			r#"invalid startdate: "02.03.yy" (expected dd.mm.yy)"#
+		);
+		assert_eq!(
+			day_subfield.unwrap(),
+			NaiveDate::from_ymd_opt(2090, 3, 2).unwrap()
+		);
+		assert_eq!(
+			day_field.unwrap(),
+			NaiveDate::from_ymd_opt(2090, 3, 3).unwrap()
+		);
+		assert!(matches!(
+			day_strict.unwrap_err().kind(),
+			ErrorKind::Header(HeaderError::StartdateMismatch { .. })
+		));
+		assert_eq!(
+			field_preferred.unwrap(),
+			NaiveDate::from_ymd_opt(2002, 3, 2).unwrap()
 		);
 	}
 }
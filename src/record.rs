@@ -0,0 +1,134 @@
+//! Data records.
+
+use crate::annotation::{parse_tals, parse_timekeeping, Annotation};
+use crate::error::Result;
+use crate::reader::Header;
+
+/// A data record: a fixed duration of samples from every signal.
+///
+/// Samples are digital values as stored in the file. EDF samples are 16-bit
+/// and BDF samples are 24-bit, both little-endian two's complement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+	signals: Vec<Vec<i32>>,
+}
+
+impl Record {
+	/// Decodes a record from its bytes.
+	///
+	/// The buffer must be exactly `Header::record_size` bytes long.
+	pub(crate) fn decode(hdr: &Header, buffer: &[u8]) -> Record {
+		let width = hdr.format.bytes_per_sample();
+		let mut chunks = buffer.chunks_exact(width);
+		let signals = hdr
+			.signals
+			.iter()
+			.map(|signal| {
+				chunks
+					.by_ref()
+					.take(signal.samples_per_record)
+					.map(decode_sample)
+					.collect()
+			})
+			.collect();
+		Record { signals }
+	}
+
+	/// Decodes the samples of some signals, each from its own buffer. The
+	/// other signals are left empty.
+	pub(crate) fn decode_signals(hdr: &Header, buffers: Vec<(usize, Vec<u8>)>) -> Record {
+		let width = hdr.format.bytes_per_sample();
+		let mut signals = vec![Vec::new(); hdr.signals.len()];
+		for (signal, buffer) in buffers {
+			signals[signal] = buffer.chunks_exact(width).map(decode_sample).collect();
+		}
+		Record { signals }
+	}
+
+	/// Returns the samples of the signal at the given index.
+	pub fn samples(&self, signal: usize) -> Option<&[i32]> {
+		self.signals.get(signal).map(Vec::as_slice)
+	}
+
+	/// Returns the samples of the signal at the given index converted to
+	/// physical values.
+	///
+	/// The header must be the one of the file the record was read from.
+	/// Sentinels configured on the reader are not applied.
+	pub fn physical_samples(&self, hdr: &Header, signal: usize) -> Option<Vec<f64>> {
+		let header = hdr.signals.get(signal)?;
+		let samples = self.samples(signal)?;
+		Some(samples.iter().map(|&s| header.to_physical(s)).collect())
+	}
+
+	/// Parses the annotations in the annotation signals of the record,
+	/// merged and ordered by onset.
+	///
+	/// The header must be the one of the file the record was read from.
+	/// Annotations with the same onset keep their order in the record.
+	pub fn annotations(&self, hdr: &Header) -> Result<Vec<Annotation>> {
+		let mut annotations = Vec::new();
+		for bytes in self.annotation_bytes(hdr) {
+			annotations.extend(parse_tals(&bytes)?);
+		}
+		annotations.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+		Ok(annotations)
+	}
+
+	/// Returns the start of the record in seconds from the starttime field,
+	/// as given by the time-keeping TAL of its first annotation signal.
+	///
+	/// The header must be the one of the file the record was read from.
+	/// Returns `None` if the file has no annotation signal. In EDF+D files,
+	/// this is the only way to tell where the gaps are.
+	pub fn onset(&self, hdr: &Header) -> Result<Option<f64>> {
+		match self.annotation_bytes(hdr).next() {
+			Some(bytes) => parse_timekeeping(&bytes),
+			None => Ok(None),
+		}
+	}
+
+	/// Returns the bytes of each annotation signal of the record.
+	fn annotation_bytes<'a>(&'a self, hdr: &'a Header) -> impl Iterator<Item = Vec<u8>> + 'a {
+		let width = hdr.format.bytes_per_sample();
+		hdr.signals
+			.iter()
+			.zip(&self.signals)
+			.filter(|(signal, _)| signal.is_annotation())
+			.map(move |(_, samples)| {
+				samples
+					.iter()
+					.flat_map(|s| s.to_le_bytes().into_iter().take(width))
+					.collect()
+			})
+	}
+
+	/// Returns the samples of every signal, in the order of the signal
+	/// headers.
+	pub fn signals(&self) -> &[Vec<i32>] {
+		&self.signals
+	}
+}
+
+/// Decodes a little-endian two's complement sample of 2 or 3 bytes.
+pub(crate) fn decode_sample(bytes: &[u8]) -> i32 {
+	let mut buffer = [0; 4];
+	buffer[..bytes.len()].copy_from_slice(bytes);
+	// Shift the sign bit into place and back to sign-extend.
+	let shift = 8 * (4 - bytes.len()) as u32;
+	(i32::from_le_bytes(buffer) << shift) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+	use super::decode_sample;
+
+	#[test]
+	fn decode_samples() {
+		assert_eq!(decode_sample(&[0x00, 0x80]), -32768);
+		assert_eq!(decode_sample(&[0xff, 0x7f]), 32767);
+		assert_eq!(decode_sample(&[0xff, 0xff]), -1);
+		assert_eq!(decode_sample(&[0x00, 0x00, 0x80]), -8388608);
+		assert_eq!(decode_sample(&[0x01, 0x02, 0x03]), 0x030201);
+	}
+}
@@ -0,0 +1,102 @@
+//! Playback of data records at the pace they were recorded.
+
+use std::io::{self, Read, Seek};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::reader::{Reader, Records};
+use crate::record::Record;
+
+/// An iterator over the data records of a file that yields each record when
+/// it would have been complete during the recording.
+///
+/// This is meant for testing real-time consumers, such as streaming
+/// dashboards or alarm logic, against recorded data. The first record is
+/// yielded immediately and each next one a record duration later, divided by
+/// the speed. The schedule is fixed at the start, so a slow consumer catches
+/// up rather than drifting. Each record is read just before it is due, so
+/// only one is held in memory at a time.
+#[derive(Debug)]
+pub struct Replay<'r, R> {
+	records: Records<'r, R>,
+	interval: Duration,
+	start: Option<Instant>,
+	emitted: u32,
+}
+
+impl<'r, R: Read + Seek> Replay<'r, R> {
+	/// Prepares the data records of a file for replay.
+	///
+	/// A speed of 2 plays the recording twice as fast as it was recorded. A
+	/// speed so slow that a record would take longer than `Duration::MAX` is
+	/// an `InvalidInput` error.
+	///
+	/// # Panics
+	///
+	/// Panics if the speed is not positive and finite.
+	pub fn new(reader: &'r mut Reader<R>, speed: f64) -> Result<Replay<'r, R>> {
+		assert!(
+			speed.is_finite() && speed > 0.0,
+			"invalid replay speed {}",
+			speed
+		);
+		let interval = Duration::try_from_secs_f64(reader.header().duration.as_secs_f64() / speed)
+			.map_err(|_| {
+				io::Error::new(
+					io::ErrorKind::InvalidInput,
+					format!("replay speed {} is too slow", speed),
+				)
+			})?;
+		Ok(Replay {
+			records: reader.records(),
+			interval,
+			start: None,
+			emitted: 0,
+		})
+	}
+}
+
+impl<R: Read + Seek> Iterator for Replay<'_, R> {
+	type Item = Result<Record>;
+
+	fn next(&mut self) -> Option<Result<Record>> {
+		let record = self.records.next()?;
+		let start = *self.start.get_or_insert_with(Instant::now);
+		// A record too far in the future to represent is not waited for.
+		let due = self
+			.interval
+			.checked_mul(self.emitted)
+			.and_then(|offset| start.checked_add(offset));
+		if let Some(due) = due {
+			thread::sleep(due.saturating_duration_since(Instant::now()));
+		}
+		self.emitted = self.emitted.saturating_add(1);
+		Some(record)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::Replay;
+	use crate::fixture::TestHeader;
+	use crate::reader::Reader;
+
+	#[test]
+	fn replay_paces_records() {
+		let bytes = TestHeader {
+			records_len: "3",
+			..TestHeader::default()
+		}
+		.to_bytes();
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+
+		let start = Instant::now();
+		let replay = Replay::new(&mut reader, 100.0).unwrap();
+		assert_eq!(replay.count(), 3);
+		assert!(start.elapsed() >= Duration::from_millis(20));
+		assert!(Replay::new(&mut reader, 1e-300).is_err());
+	}
+}
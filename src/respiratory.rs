@@ -0,0 +1,286 @@
+//! Detection of candidate respiratory events from airflow, effort and SpO2
+//! signals, for review by a scorer.
+//!
+//! The rules are simplified from the AASM scoring manual and work on one
+//! value per second. They are meant to point a scorer at likely events, not
+//! to replace scoring.
+
+use std::io::{self, Read, Seek};
+
+use crate::annotation::Annotation;
+use crate::error::Result;
+use crate::reader::Reader;
+
+/// The shortest event, in seconds.
+const MIN_EVENT: usize = 10;
+/// The window over which the breathing amplitude is measured, in seconds.
+/// It spans at least one breath.
+const AMPLITUDE_WINDOW: usize = 4;
+/// The window preceding each second that its baseline is taken from, in
+/// seconds.
+const BASELINE_WINDOW: usize = 120;
+/// How long after a hypopnea a desaturation may start, in seconds.
+const DESATURATION_DELAY: usize = 30;
+
+/// The signals to detect events from, by index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RespiratorySignals {
+	/// The airflow, e.g. a nasal pressure or thermistor signal.
+	pub airflow: usize,
+	/// A respiratory effort belt, used to tell obstructive from central
+	/// apneas.
+	pub effort: Option<usize>,
+	/// The oxygen saturation in percent.
+	pub spo2: Option<usize>,
+}
+
+/// Detects candidate apneas, hypopneas and oxygen desaturations.
+///
+/// The events are returned as annotations in onset order, with the texts
+/// `Obstructive apnea`, `Central apnea`, `Apnea` (without an effort
+/// signal), `Hypopnea` and `Oxygen desaturation`:
+///
+/// - An apnea is a drop of the airflow amplitude by at least 90% from its
+///   baseline for at least 10 seconds. It is obstructive if the effort
+///   amplitude stays above half of its baseline, and central otherwise.
+/// - A hypopnea is a drop of at least 30% for at least 10 seconds. If there
+///   is an SpO2 signal, it must be followed by a desaturation within 30
+///   seconds.
+/// - A desaturation is a drop of the SpO2 by at least 3 points from its
+///   baseline for at least 10 seconds.
+///
+/// Baselines are taken over the preceding two minutes. Times assume the
+/// data records are contiguous. Signals sampled below 1 Hz hold their last
+/// sample through the seconds between samples. A signal without a sampling
+/// rate, as in files whose data records have no duration, is an
+/// `InvalidInput` error.
+///
+/// # Panics
+///
+/// Panics if there is no signal at one of the given indices.
+pub fn detect_respiratory_events<R: Read + Seek>(
+	reader: &mut Reader<R>,
+	signals: &RespiratorySignals,
+) -> Result<Vec<Annotation>> {
+	let airflow = amplitude(&per_second(reader, signals.airflow)?);
+	let effort = match signals.effort {
+		Some(i) => {
+			let effort = amplitude(&per_second(reader, i)?);
+			let effort_baseline = baseline(&effort, mean);
+			Some((effort, effort_baseline))
+		}
+		None => None,
+	};
+	let spo2 = match signals.spo2 {
+		Some(i) => Some(
+			per_second(reader, i)?
+				.iter()
+				.map(|s| s.iter().sum::<f64>() / s.len().max(1) as f64)
+				.collect::<Vec<f64>>(),
+		),
+		None => None,
+	};
+
+	let airflow_baseline = baseline(&airflow, mean);
+	let desaturations = match spo2 {
+		Some(ref spo2) => {
+			let spo2_baseline = baseline(spo2, max);
+			runs(spo2.len(), |t| spo2[t] <= spo2_baseline[t] - 3.0)
+		}
+		None => Vec::new(),
+	};
+	let apneas = runs(airflow.len(), |t| airflow[t] <= 0.1 * airflow_baseline[t]);
+	let hypopneas = runs(airflow.len(), |t| {
+		airflow[t] <= 0.7 * airflow_baseline[t] && airflow[t] > 0.1 * airflow_baseline[t]
+	});
+
+	let mut events = Vec::new();
+	for (start, end) in apneas {
+		let text = match effort {
+			Some((ref effort, ref effort_baseline)) => {
+				let breathing = (start..end)
+					.all(|t| effort.get(t).is_some_and(|&e| e > 0.5 * effort_baseline[t]));
+				if breathing {
+					"Obstructive apnea"
+				} else {
+					"Central apnea"
+				}
+			}
+			None => "Apnea",
+		};
+		events.push(event(start, end, text));
+	}
+	for (start, end) in hypopneas {
+		let desaturated = desaturations
+			.iter()
+			.any(|&(s, _)| s >= start && s <= end + DESATURATION_DELAY);
+		if spo2.is_none() || desaturated {
+			events.push(event(start, end, "Hypopnea"));
+		}
+	}
+	for &(start, end) in &desaturations {
+		events.push(event(start, end, "Oxygen desaturation"));
+	}
+	events.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+	Ok(events)
+}
+
+fn event(start: usize, end: usize, text: &str) -> Annotation {
+	Annotation {
+		onset: start as f64,
+		duration: Some((end - start) as f64),
+		text: text.to_string(),
+	}
+}
+
+/// Reads the physical samples of a signal, split into seconds.
+///
+/// Each second holds the samples that overlap it, so that a signal sampled
+/// below 1 Hz still has a sample in every second.
+fn per_second<R: Read + Seek>(reader: &mut Reader<R>, signal: usize) -> Result<Vec<Vec<f64>>> {
+	let rate = match reader.header().sampling_rate(signal) {
+		Some(rate) if rate > 0.0 => rate,
+		_ => {
+			let err = io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("signal {} has no sampling rate", signal),
+			);
+			return Err(err.into());
+		}
+	};
+	let samples = reader.read_signal(signal)?;
+	let seconds = (samples.len() as f64 / rate).floor() as usize;
+	Ok((0..seconds)
+		.map(|t| {
+			let start = (t as f64 * rate).floor() as usize;
+			let end = ((t + 1) as f64 * rate).ceil() as usize;
+			samples[start..end.max(start + 1).min(samples.len())].to_vec()
+		})
+		.collect())
+}
+
+/// The peak-to-peak amplitude around each second.
+fn amplitude(seconds: &[Vec<f64>]) -> Vec<f64> {
+	(0..seconds.len())
+		.map(|t| {
+			let start = t.saturating_sub(AMPLITUDE_WINDOW / 2);
+			let end = (t + AMPLITUDE_WINDOW / 2).min(seconds.len());
+			let samples = seconds[start..end].iter().flatten();
+			let (lo, hi) = samples.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &s| {
+				(lo.min(s), hi.max(s))
+			});
+			if lo <= hi {
+				hi - lo
+			} else {
+				0.0
+			}
+		})
+		.collect()
+}
+
+/// The baseline of each second, from the preceding two minutes. The first
+/// seconds use the first two minutes instead.
+fn baseline(values: &[f64], summary: fn(&[f64]) -> f64) -> Vec<f64> {
+	(0..values.len())
+		.map(|t| {
+			let end = t.max(BASELINE_WINDOW.min(values.len()));
+			summary(&values[end.saturating_sub(BASELINE_WINDOW)..end])
+		})
+		.collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+	values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+fn max(values: &[f64]) -> f64 {
+	values.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+}
+
+/// The runs of at least `MIN_EVENT` consecutive seconds for which the
+/// condition holds, as start and end seconds.
+fn runs(len: usize, condition: impl Fn(usize) -> bool) -> Vec<(usize, usize)> {
+	let mut runs = Vec::new();
+	let mut start = None;
+	for t in 0..=len {
+		match (start, t < len && condition(t)) {
+			(None, true) => start = Some(t),
+			(Some(s), false) => {
+				if t - s >= MIN_EVENT {
+					runs.push((s, t));
+				}
+				start = None;
+			}
+			_ => {}
+		}
+	}
+	runs
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::{amplitude, baseline, detect_respiratory_events, mean, runs, RespiratorySignals};
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::Reader;
+
+	#[test]
+	fn detect_drop_in_amplitude() {
+		// Breathing at 0.25 Hz sampled at 4 Hz, with 15 seconds of reduced
+		// airflow after three minutes.
+		let seconds: Vec<Vec<f64>> = (0..240)
+			.map(|t| {
+				let scale = if (180..195).contains(&t) { 0.05 } else { 1.0 };
+				(0..4)
+					.map(|k| {
+						let phase = (t * 4 + k) as f64 / 16.0 * std::f64::consts::TAU;
+						scale * phase.sin()
+					})
+					.collect()
+			})
+			.collect();
+		let airflow = amplitude(&seconds);
+		let airflow_baseline = baseline(&airflow, mean);
+		let apneas = runs(airflow.len(), |t| airflow[t] <= 0.1 * airflow_baseline[t]);
+		// The amplitude window shortens the drop by two seconds at the start
+		// and one at the end.
+		assert_eq!(apneas, [(182, 194)]);
+	}
+
+	#[test]
+	fn detect_events_in_file() {
+		// Four minutes of breathing at 0.25 Hz sampled at 4 Hz, and an SpO2
+		// of 95% sampled every 20 seconds.
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(12)
+			.duration(Duration::from_secs(20))
+			.channel("Flow", 80, Pattern::Sine { frequency: 0.25 })
+			.channel("SpO2", 1, Pattern::Sine { frequency: 0.0 })
+			.write(&mut bytes)
+			.unwrap();
+		let record_size = 2 * 81;
+		for record in bytes[768..].chunks_mut(record_size) {
+			record[160..].copy_from_slice(&3113i16.to_le_bytes());
+		}
+		// The airflow stops for 15 seconds after three minutes.
+		let apnea = 768 + 9 * record_size;
+		bytes[apnea..apnea + 2 * 60].fill(0);
+		let signals = RespiratorySignals {
+			airflow: 0,
+			effort: None,
+			spo2: Some(1),
+		};
+		let events = detect_respiratory_events(&mut Reader::from_bytes(&bytes).unwrap(), &signals);
+
+		let events = events.unwrap();
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].text, "Apnea");
+		assert_eq!(events[0].onset, 182.0);
+		assert_eq!(events[0].duration, Some(12.0));
+		bytes[244..252].copy_from_slice(b"0       ");
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		assert!(detect_respiratory_events(&mut reader, &signals).is_err());
+	}
+}
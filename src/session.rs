@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::error::{Error, Result};
+use crate::reader::Header;
+use crate::sniff::{open_auto, Format};
+
+/// A set of EDF files that belong together, such as the recordings of a
+/// study.
+///
+/// Headers are read and validated in parallel when the session is opened.
+/// A file that fails to parse does not prevent the others from loading; its
+/// error is kept in the catalog instead.
+pub struct Session {
+	entries: Vec<SessionEntry>,
+}
+
+/// A file of a session.
+#[derive(Debug)]
+pub struct SessionEntry {
+	pub path: PathBuf,
+	/// The header of the file, or the error that occurred while reading it.
+	pub header: Result<Header>,
+}
+
+impl Session {
+	/// Opens the given files.
+	///
+	/// The catalog is ordered by start datetime, with files that failed to
+	/// parse last.
+	pub fn open<I, P>(paths: I) -> Session
+	where
+		I: IntoIterator<Item = P>,
+		P: AsRef<Path>,
+	{
+		let paths: Vec<PathBuf> = paths
+			.into_iter()
+			.map(|p| p.as_ref().to_path_buf())
+			.collect();
+		let headers = read_headers(&paths);
+		let mut entries: Vec<SessionEntry> = paths
+			.into_iter()
+			.zip(headers)
+			.map(|(path, header)| SessionEntry { path, header })
+			.collect();
+		entries.sort_by(|a, b| match (&a.header, &b.header) {
+			(Ok(x), Ok(y)) => x.start_datetime.cmp(&y.start_datetime),
+			(Ok(_), Err(_)) => std::cmp::Ordering::Less,
+			(Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+			(Err(_), Err(_)) => a.path.cmp(&b.path),
+		});
+		Session { entries }
+	}
+
+	/// Opens every EDF or BDF file in a directory.
+	///
+	/// Files are recognized by their magic bytes rather than their
+	/// extension. Files that cannot be read to tell are opened as well, so
+	/// that their error is kept in the catalog. Subdirectories are not
+	/// searched.
+	pub fn open_dir<P: AsRef<Path>>(dir: P) -> Result<Session> {
+		let mut paths = Vec::new();
+		for entry in fs::read_dir(dir)? {
+			let path = entry?.path();
+			if !path.is_file() {
+				continue;
+			}
+			match Format::sniff_path(&path) {
+				Ok(Some(Format::Edf | Format::Bdf)) | Err(_) => paths.push(path),
+				Ok(_) => {}
+			}
+		}
+		Ok(Session::open(paths))
+	}
+
+	/// Returns the catalog of the session.
+	pub fn entries(&self) -> &[SessionEntry] {
+		&self.entries
+	}
+
+	/// Returns the files whose headers were read successfully.
+	pub fn headers(&self) -> impl Iterator<Item = (&Path, &Header)> {
+		self.entries
+			.iter()
+			.filter_map(|e| e.header.as_ref().ok().map(|h| (e.path.as_path(), h)))
+	}
+
+	/// Returns the files whose headers could not be read.
+	pub fn errors(&self) -> impl Iterator<Item = (&Path, &Error)> {
+		self.entries
+			.iter()
+			.filter_map(|e| e.header.as_ref().err().map(|err| (e.path.as_path(), err)))
+	}
+}
+
+/// Reads the headers of the files on as many threads as there are CPUs.
+pub(crate) fn read_headers(paths: &[PathBuf]) -> Vec<Result<Header>> {
+	let threads = thread::available_parallelism()
+		.map_or(1, |n| n.get())
+		.min(paths.len());
+	let next = AtomicUsize::new(0);
+	let results = Mutex::new(Vec::with_capacity(paths.len()));
+	thread::scope(|s| {
+		for _ in 0..threads {
+			s.spawn(|| loop {
+				let i = next.fetch_add(1, Ordering::Relaxed);
+				let path = match paths.get(i) {
+					Some(path) => path,
+					None => break,
+				};
+				let header = open_auto(path);
+				results.lock().unwrap().push((i, header));
+			});
+		}
+	});
+	let mut results = results.into_inner().unwrap();
+	results.sort_by_key(|(i, _)| *i);
+	results.into_iter().map(|(_, header)| header).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::Session;
+	use crate::fixture::TestHeader;
+
+	fn header(startdate: &str) -> Vec<u8> {
+		TestHeader {
+			startdate,
+			..TestHeader::default()
+		}
+		.to_bytes()
+	}
+
+	#[test]
+	fn open_dir_orders_by_start() {
+		let dir = std::env::temp_dir().join(format!("edf-session-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(dir.join("b.edf"), header("01.03.21")).unwrap();
+		fs::write(dir.join("a.EDF"), header("02.03.21")).unwrap();
+		fs::write(dir.join("c.rec"), header("03.03.21")).unwrap();
+		fs::write(dir.join("d.edf"), "0       truncated").unwrap();
+		fs::write(dir.join("e.edf"), "not an edf file").unwrap();
+		fs::write(dir.join("notes.txt"), "").unwrap();
+
+		let session = Session::open_dir(&dir).unwrap();
+		fs::remove_dir_all(&dir).unwrap();
+
+		let names: Vec<_> = session
+			.entries()
+			.iter()
+			.map(|e| e.path.file_name().unwrap().to_str().unwrap())
+			.collect();
+		assert_eq!(names, ["b.edf", "a.EDF", "c.rec", "d.edf"]);
+		assert_eq!(session.headers().count(), 3);
+		assert_eq!(session.errors().count(), 1);
+	}
+}
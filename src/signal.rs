@@ -0,0 +1,125 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// The header of a single signal.
+///
+/// The per-signal section of the header follows the fixed 256 bytes, with
+/// 256 bytes per signal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignalHeader {
+	/// The label of the signal, e.g. `EEG Fpz-Cz`.
+	pub label: String,
+	/// The transducer type, e.g. `AgAgCl electrode`.
+	pub transducer: String,
+	/// The physical dimension, e.g. `uV`.
+	pub physical_dimension: String,
+	pub physical_min: f64,
+	pub physical_max: f64,
+	/// The smallest digital value. This is an `i32` rather than an `i16`
+	/// because BDF samples are 24-bit.
+	pub digital_min: i32,
+	pub digital_max: i32,
+	/// The prefiltering, e.g. `HP:0.1Hz LP:75Hz`.
+	pub prefiltering: String,
+	/// The number of samples of the signal in each data record.
+	pub samples_per_record: usize,
+	pub reserved: String,
+}
+
+impl SignalHeader {
+	/// Whether this is an EDF+ or BDF+ annotation signal, which holds
+	/// time-stamped annotation lists rather than samples.
+	pub fn is_annotation(&self) -> bool {
+		matches!(self.label.trim(), "EDF Annotations" | "BDF Annotations")
+	}
+
+	/// The number of samples per second, given the duration of a data record
+	/// in seconds.
+	///
+	/// Returns `None` if the duration is zero, as for files without signal
+	/// data.
+	pub fn sampling_rate(&self, record_duration: Duration) -> Option<f64> {
+		if record_duration.is_zero() {
+			return None;
+		}
+		Some(self.samples_per_record as f64 / record_duration.as_secs_f64())
+	}
+
+	/// The physical units per digital step.
+	///
+	/// If the digital minimum equals the digital maximum, the gain is not
+	/// finite.
+	pub fn gain(&self) -> f64 {
+		(self.physical_max - self.physical_min)
+			/ (self.digital_max as f64 - self.digital_min as f64)
+	}
+
+	/// The physical value of a digital zero.
+	pub fn offset(&self) -> f64 {
+		self.physical_min - self.gain() * self.digital_min as f64
+	}
+
+	/// Converts a digital sample to its value in the physical dimension.
+	///
+	/// The digital extremes map exactly onto the physical extremes.
+	pub fn to_physical(&self, sample: i32) -> f64 {
+		let steps =
+			(sample as f64 - self.digital_min as f64) * (self.physical_max - self.physical_min);
+		self.physical_min + steps / (self.digital_max as f64 - self.digital_min as f64)
+	}
+}
+
+impl fmt::Display for SignalHeader {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"Label: {}\nTransducer: {}\nPhysical range: {} to {} {}\nDigital range: {} to {}\nPrefiltering: {}\n{} samples per record",
+			self.label,
+			self.transducer,
+			self.physical_min,
+			self.physical_max,
+			self.physical_dimension,
+			self.digital_min,
+			self.digital_max,
+			self.prefiltering,
+			self.samples_per_record
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::SignalHeader;
+
+	#[test]
+	fn to_physical() {
+		let signal = SignalHeader {
+			label: "EEG Fpz-Cz".to_string(),
+			transducer: String::new(),
+			physical_dimension: "uV".to_string(),
+			physical_min: -500.0,
+			physical_max: 500.0,
+			digital_min: -2048,
+			digital_max: 2047,
+			prefiltering: String::new(),
+			samples_per_record: 100,
+			reserved: String::new(),
+		};
+		assert_eq!(signal.to_physical(-2048), -500.0);
+		assert_eq!(signal.to_physical(2047), 500.0);
+		assert!((signal.to_physical(0) - 0.1221).abs() < 1e-4);
+		assert_eq!(
+			signal.sampling_rate(Duration::from_secs(30)),
+			Some(100.0 / 30.0)
+		);
+		assert_eq!(
+			signal.sampling_rate(Duration::from_millis(2)),
+			Some(50000.0)
+		);
+		assert_eq!(signal.sampling_rate(Duration::ZERO), None);
+	}
+}
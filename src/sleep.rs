@@ -0,0 +1,200 @@
+//! Summary metrics of a scored sleep recording, from the hypnogram and the
+//! respiratory events in its annotations.
+
+use serde::Serialize;
+
+use crate::annotation::Annotation;
+
+/// A sleep stage, as scored per the AASM manual.
+///
+/// R&K stages 3 and 4 are both counted as N3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Stage {
+	W,
+	N1,
+	N2,
+	N3,
+	R,
+}
+
+impl Stage {
+	/// Parses the text of a hypnogram annotation, e.g. `Sleep stage N2` or
+	/// `Sleep stage 4`.
+	///
+	/// Unscored epochs (`Sleep stage ?`) and other annotations give `None`.
+	pub fn from_annotation(text: &str) -> Option<Stage> {
+		let stage = text.trim().strip_prefix("Sleep stage ")?;
+		match stage.trim() {
+			"W" => Some(Stage::W),
+			"1" | "N1" => Some(Stage::N1),
+			"2" | "N2" => Some(Stage::N2),
+			"3" | "4" | "N3" | "N4" => Some(Stage::N3),
+			"R" | "REM" => Some(Stage::R),
+			_ => None,
+		}
+	}
+
+	fn is_sleep(self) -> bool {
+		self != Stage::W
+	}
+}
+
+/// The share of total sleep time spent in each stage, in percent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StagePercentages {
+	pub n1: f64,
+	pub n2: f64,
+	pub n3: f64,
+	pub r: f64,
+}
+
+/// Standard summary metrics of a sleep study.
+///
+/// Times are in minutes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SleepReport {
+	/// The time scored as any stage, including W.
+	pub time_in_bed: f64,
+	/// Total sleep time (TST), the time scored as N1, N2, N3 or R.
+	pub total_sleep_time: f64,
+	/// The time from the first staged epoch to the first epoch of sleep. If
+	/// there is no sleep, then it is `None`.
+	pub sleep_latency: Option<f64>,
+	/// Wake after sleep onset (WASO), the time scored as W between the first
+	/// and the last epoch of sleep.
+	pub wake_after_sleep_onset: f64,
+	/// The share of the time in bed spent asleep, in percent.
+	pub sleep_efficiency: f64,
+	pub stages: StagePercentages,
+	/// The number of apneas and hypopneas.
+	pub respiratory_events: usize,
+	/// The apnea-hypopnea index (AHI), respiratory events per hour of sleep.
+	/// If there are no respiratory events or no sleep, then it is `None`.
+	pub apnea_hypopnea_index: Option<f64>,
+}
+
+/// Computes the summary metrics of a recording from its annotations.
+///
+/// Hypnogram annotations are recognized by [`Stage::from_annotation`]. A
+/// stage without a duration lasts until the next hypnogram annotation. Apneas
+/// and hypopneas are recognized by their text, e.g. `Obstructive Apnea` or
+/// `Hypopnea`.
+pub fn sleep_report(annotations: &[Annotation]) -> SleepReport {
+	// Unscored epochs are kept for now, since they end the stage before them.
+	let hypnogram: Vec<&Annotation> = annotations
+		.iter()
+		.filter(|a| a.text.trim().starts_with("Sleep stage "))
+		.collect();
+	let mut epochs: Vec<(f64, f64, Stage)> = Vec::new();
+	for (i, annotation) in hypnogram.iter().enumerate() {
+		let stage = match Stage::from_annotation(&annotation.text) {
+			Some(stage) => stage,
+			None => continue,
+		};
+		let end = match annotation.duration {
+			Some(duration) => annotation.onset + duration,
+			None => hypnogram.get(i + 1).map_or(annotation.onset, |a| a.onset),
+		};
+		epochs.push((annotation.onset, end, stage));
+	}
+
+	let time_in = |stage: Stage| total(epochs.iter().filter(|e| e.2 == stage));
+	let time_in_bed = total(epochs.iter());
+	let total_sleep_time = total(epochs.iter().filter(|e| e.2.is_sleep()));
+	let first_sleep = epochs.iter().position(|e| e.2.is_sleep());
+	let last_sleep = epochs.iter().rposition(|e| e.2.is_sleep());
+	let sleep_latency = first_sleep.map(|i| epochs[i].0 - epochs[0].0);
+	let wake_after_sleep_onset = match (first_sleep, last_sleep) {
+		(Some(first), Some(last)) => total(epochs[first..=last].iter().filter(|e| !e.2.is_sleep())),
+		_ => 0.0,
+	};
+	let percent = |part: f64, whole: f64| {
+		if whole > 0.0 {
+			100.0 * part / whole
+		} else {
+			0.0
+		}
+	};
+
+	let respiratory_events = annotations
+		.iter()
+		.filter(|a| is_respiratory_event(&a.text))
+		.count();
+	let apnea_hypopnea_index = if respiratory_events > 0 && total_sleep_time > 0.0 {
+		Some(respiratory_events as f64 / (total_sleep_time / 3600.0))
+	} else {
+		None
+	};
+
+	SleepReport {
+		time_in_bed: time_in_bed / 60.0,
+		total_sleep_time: total_sleep_time / 60.0,
+		sleep_latency: sleep_latency.map(|s| s / 60.0),
+		wake_after_sleep_onset: wake_after_sleep_onset / 60.0,
+		sleep_efficiency: percent(total_sleep_time, time_in_bed),
+		stages: StagePercentages {
+			n1: percent(time_in(Stage::N1), total_sleep_time),
+			n2: percent(time_in(Stage::N2), total_sleep_time),
+			n3: percent(time_in(Stage::N3), total_sleep_time),
+			r: percent(time_in(Stage::R), total_sleep_time),
+		},
+		respiratory_events,
+		apnea_hypopnea_index,
+	}
+}
+
+/// The total time of the epochs, in seconds.
+///
+/// Unlike `Iterator::sum`, this gives `0.0` rather than `-0.0` for no
+/// epochs.
+fn total<'a, I>(epochs: I) -> f64
+where
+	I: Iterator<Item = &'a (f64, f64, Stage)>,
+{
+	epochs.fold(0.0, |total, e| total + (e.1 - e.0))
+}
+
+/// Whether the text of an annotation names an apnea or a hypopnea, in
+/// either spelling.
+fn is_respiratory_event(text: &str) -> bool {
+	let text = text.to_ascii_lowercase();
+	["apnea", "apnoea", "hypopnea", "hypopnoea"]
+		.iter()
+		.any(|word| text.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{sleep_report, Stage};
+	use crate::annotation::Annotation;
+
+	#[test]
+	fn report_of_short_night() {
+		let annotation = |onset, duration, text: &str| Annotation {
+			onset,
+			duration,
+			text: text.to_string(),
+		};
+		let annotations = [
+			annotation(0.0, Some(600.0), "Sleep stage W"),
+			annotation(600.0, Some(1200.0), "Sleep stage N2"),
+			annotation(1800.0, Some(300.0), "Sleep stage W"),
+			annotation(2100.0, Some(1200.0), "Sleep stage 4"),
+			annotation(2400.0, Some(15.0), "Obstructive Apnea"),
+			annotation(2500.0, Some(12.0), "Hypopnea"),
+			annotation(3300.0, None, "Sleep stage R"),
+			annotation(3900.0, None, "Sleep stage ?"),
+		];
+		let report = sleep_report(&annotations);
+
+		assert_eq!(report.time_in_bed, 65.0);
+		assert_eq!(report.total_sleep_time, 50.0);
+		assert_eq!(report.sleep_latency, Some(10.0));
+		assert_eq!(report.wake_after_sleep_onset, 5.0);
+		assert_eq!(report.stages.n2, 40.0);
+		assert_eq!(report.stages.n3, 40.0);
+		assert_eq!(report.respiratory_events, 2);
+		assert_eq!(report.apnea_hypopnea_index, Some(2.4));
+		assert_eq!(Stage::from_annotation("Sleep stage ?"), None);
+	}
+}
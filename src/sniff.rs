@@ -0,0 +1,183 @@
+//! Detection of file formats from their leading bytes.
+
+use std::fmt;
+use std::fs::File;
+#[cfg(feature = "compression")]
+use std::io::Cursor;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::reader::{Header, Reader, ReaderBuilder};
+
+/// A file format identified by its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// European Data Format, starting with `0` and seven spaces.
+	Edf,
+	/// BioSemi Data Format, starting with `0xFF` and `BIOSEMI`.
+	Bdf,
+	/// General Data Format, starting with `GDF`.
+	Gdf,
+	/// A gzip-compressed file.
+	Gzip,
+	/// A Zstandard-compressed file.
+	Zstd,
+}
+
+impl Format {
+	/// Identifies the format from the first bytes of a file.
+	///
+	/// Eight bytes are enough to tell all formats apart.
+	pub fn sniff(bytes: &[u8]) -> Option<Format> {
+		if bytes.starts_with(b"0       ") {
+			Some(Format::Edf)
+		} else if bytes.starts_with(b"\xffBIOSEMI") {
+			Some(Format::Bdf)
+		} else if bytes.starts_with(b"GDF") {
+			Some(Format::Gdf)
+		} else if bytes.starts_with(&[0x1f, 0x8b]) {
+			Some(Format::Gzip)
+		} else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+			Some(Format::Zstd)
+		} else {
+			None
+		}
+	}
+
+	/// Identifies the format of a file from its first bytes.
+	pub fn sniff_path<P: AsRef<Path>>(path: P) -> Result<Option<Format>> {
+		let mut buffer = Vec::with_capacity(8);
+		File::open(path)?.take(8).read_to_end(&mut buffer)?;
+		Ok(Format::sniff(&buffer))
+	}
+}
+
+impl fmt::Display for Format {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			Format::Edf => "EDF",
+			Format::Bdf => "BDF",
+			Format::Gdf => "GDF",
+			Format::Gzip => "gzip",
+			Format::Zstd => "Zstandard",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+/// The bytes of a file opened by `ReaderBuilder::open_auto`.
+#[derive(Debug)]
+pub enum Source {
+	/// An EDF or BDF file, read as it is.
+	File(File),
+	/// The decompressed contents of a compressed file.
+	#[cfg(feature = "compression")]
+	Memory(Cursor<Vec<u8>>),
+}
+
+impl Read for Source {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match *self {
+			Source::File(ref mut f) => f.read(buf),
+			#[cfg(feature = "compression")]
+			Source::Memory(ref mut cursor) => cursor.read(buf),
+		}
+	}
+}
+
+impl Seek for Source {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		match *self {
+			Source::File(ref mut f) => f.seek(pos),
+			#[cfg(feature = "compression")]
+			Source::Memory(ref mut cursor) => cursor.seek(pos),
+		}
+	}
+}
+
+impl ReaderBuilder {
+	/// Opens a file after identifying its format.
+	///
+	/// EDF and BDF files are read directly. With the `compression` feature,
+	/// gzip and Zstandard files are decompressed in memory and the EDF or
+	/// BDF file inside them is read. A file in any other recognized format,
+	/// such as GDF, fails with `ErrorKind::UnsupportedFormat` naming it.
+	pub fn open_auto<P: AsRef<Path>>(&self, path: P) -> Result<Reader<Source>> {
+		let path = path.as_ref();
+		let source = match Format::sniff_path(path)? {
+			Some(Format::Edf | Format::Bdf) | None => Source::File(File::open(path)?),
+			#[cfg(feature = "compression")]
+			Some(format @ (Format::Gzip | Format::Zstd)) => {
+				Source::Memory(Cursor::new(decompress(File::open(path)?, format)?))
+			}
+			Some(format) => return Err(Error::new(ErrorKind::UnsupportedFormat(format))),
+		};
+		self.from_reader(source)
+	}
+}
+
+/// Reads the header of a file after identifying its format, as
+/// `ReaderBuilder::open_auto` does.
+pub fn open_auto<P: AsRef<Path>>(path: P) -> Result<Header> {
+	ReaderBuilder::new()
+		.open_auto(path)
+		.map(Reader::into_header)
+}
+
+/// Decompresses a gzip or Zstandard stream.
+#[cfg(feature = "compression")]
+fn decompress<R: Read>(rdr: R, format: Format) -> Result<Vec<u8>> {
+	let mut bytes = Vec::new();
+	match format {
+		Format::Gzip => flate2::read::MultiGzDecoder::new(rdr).read_to_end(&mut bytes)?,
+		_ => zstd::stream::read::Decoder::new(rdr)?.read_to_end(&mut bytes)?,
+	};
+	Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Format;
+
+	#[test]
+	fn sniff() {
+		assert_eq!(Format::sniff(b"0       MCH-0234567"), Some(Format::Edf));
+		assert_eq!(Format::sniff(b"\xffBIOSEMI"), Some(Format::Bdf));
+		assert_eq!(Format::sniff(b"GDF 2.20"), Some(Format::Gdf));
+		assert_eq!(Format::sniff(&[0x1f, 0x8b, 0x08, 0x00]), Some(Format::Gzip));
+		assert_eq!(Format::sniff(&[0x28, 0xb5, 0x2f, 0xfd]), Some(Format::Zstd));
+		assert_eq!(Format::sniff(b"0      X"), None);
+		assert_eq!(Format::sniff(b""), None);
+	}
+
+	#[cfg(feature = "compression")]
+	#[test]
+	fn open_compressed() {
+		use std::io::Write;
+
+		use super::open_auto;
+		use crate::error::ErrorKind;
+		use crate::generator::Generator;
+
+		let path = std::env::temp_dir().join(format!("edf-sniff-{}", std::process::id()));
+		let mut edf = Vec::new();
+		Generator::new().records(2).write(&mut edf).unwrap();
+		let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+		gzip.write_all(&edf).unwrap();
+		std::fs::write(&path, gzip.finish().unwrap()).unwrap();
+		let gzip = open_auto(&path);
+		std::fs::write(&path, zstd::encode_all(edf.as_slice(), 0).unwrap()).unwrap();
+		let zstd = open_auto(&path);
+		std::fs::write(&path, b"GDF 2.20").unwrap();
+		let gdf = open_auto(&path);
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(gzip.unwrap().records_len, Some(2));
+		assert_eq!(zstd.unwrap().records_len, Some(2));
+		assert!(matches!(
+			gdf.unwrap_err().kind(),
+			ErrorKind::UnsupportedFormat(Format::Gdf)
+		));
+	}
+}
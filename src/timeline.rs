@@ -0,0 +1,247 @@
+//! The timing of the data records of EDF+D files, which may have gaps
+//! between them.
+
+use std::io::{self, Read, Seek};
+
+use crate::error::Result;
+use crate::reader::{Header, Reader};
+
+/// The largest difference between the end of a data record and the start
+/// of the next, in seconds, that is not taken as a gap. TAL onsets are
+/// written in decimal, so they may round.
+const TOLERANCE: f64 = 1e-6;
+/// The most samples a continuous view of a signal may hold, gaps included,
+/// so that a corrupt onset cannot make it fill terabytes with zeros.
+const MAX_CONTINUOUS_LEN: usize = 1 << 30;
+
+/// The onset of each data record of a file.
+///
+/// EDF+D files may leave out data records, for example while a recorder was
+/// paused, so a record does not necessarily start where the previous one
+/// ended. The onset of each record is then given by its time-keeping TAL.
+/// Times computed from the record index alone are wrong after the first
+/// gap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+	onsets: Vec<f64>,
+	duration: f64,
+}
+
+/// Time between two data records that was not recorded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gap {
+	/// The index of the data record after the gap.
+	pub record: usize,
+	/// The start of the gap in seconds from the starttime, which is the end
+	/// of the data record before it.
+	pub start: f64,
+	/// The duration of the gap in seconds.
+	pub duration: f64,
+}
+
+/// How gaps are represented in a continuous view of a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapFill {
+	/// Leave the gaps out, so the samples of the records follow each other.
+	Skip,
+	/// Fill the gaps with zeros at the sampling rate of the signal.
+	Zero,
+}
+
+impl Gap {
+	/// The end of the gap in seconds from the starttime, which is the onset
+	/// of the data record after it.
+	pub fn end(&self) -> f64 {
+		self.start + self.duration
+	}
+}
+
+impl Timeline {
+	/// The onset of each data record in seconds from the starttime.
+	pub fn onsets(&self) -> &[f64] {
+		&self.onsets
+	}
+
+	/// The onset of the data record at the given index, in seconds from the
+	/// starttime.
+	pub fn onset(&self, record: usize) -> Option<f64> {
+		self.onsets.get(record).copied()
+	}
+
+	/// Lists the gaps between the data records, in order.
+	///
+	/// Records that overlap the one before them are not reported.
+	pub fn gaps(&self) -> Vec<Gap> {
+		self.onsets
+			.windows(2)
+			.enumerate()
+			.filter_map(|(i, pair)| {
+				let start = pair[0] + self.duration;
+				(pair[1] - start > TOLERANCE).then(|| Gap {
+					record: i + 1,
+					start,
+					duration: pair[1] - start,
+				})
+			})
+			.collect()
+	}
+
+	/// The time covered by the data records, in seconds.
+	pub fn recorded(&self) -> f64 {
+		self.onsets.len() as f64 * self.duration
+	}
+
+	/// The time from the starttime to the end of the last data record, in
+	/// seconds, gaps included.
+	pub fn elapsed(&self) -> f64 {
+		self.onsets.last().map_or(0.0, |last| last + self.duration)
+	}
+
+	/// Whether the data records follow each other without gaps.
+	pub fn is_continuous(&self) -> bool {
+		self.gaps().is_empty()
+	}
+
+	/// The time of a sample of a signal, in seconds from the starttime.
+	///
+	/// The header must be the one of the file the timeline was read from.
+	/// Returns `None` if there is no such sample.
+	pub fn sample_onset(&self, hdr: &Header, signal: usize, sample: usize) -> Option<f64> {
+		let samples_per_record = hdr.signals.get(signal)?.samples_per_record;
+		if samples_per_record == 0 {
+			return None;
+		}
+		let onset = self.onset(sample / samples_per_record)?;
+		let offset = (sample % samples_per_record) as f64 / samples_per_record as f64;
+		Some(onset + offset * self.duration)
+	}
+}
+
+impl<R: Read + Seek> Reader<R> {
+	/// Reads the onset of each data record.
+	///
+	/// Onsets are read from the time-keeping TAL of the first annotation
+	/// signal, without decoding the other signals. Files without an
+	/// annotation signal are contiguous, as are records without a TAL, so
+	/// their onsets follow from the record duration.
+	pub fn read_timeline(&mut self) -> Result<Timeline> {
+		let hdr = self.header().clone();
+		let duration = hdr.duration.as_secs_f64();
+		let annotations = hdr.annotation_signals();
+		let records_len = hdr.effective_records_len().unwrap_or(0);
+		let mut onsets: Vec<f64> = Vec::with_capacity(records_len);
+		for i in 0..records_len {
+			let onset = match annotations.first() {
+				Some(&signal) => self.read_record_channels(i, &[signal])?.onset(&hdr)?,
+				None => None,
+			};
+			let next = onsets.last().map_or(0.0, |last| last + duration);
+			onsets.push(onset.unwrap_or(next));
+		}
+		Ok(Timeline { onsets, duration })
+	}
+
+	/// Reads every sample of a signal as a continuous series, converted to
+	/// physical values. Missing samples are NaN.
+	///
+	/// With [`GapFill::Zero`], each gap between data records is filled with
+	/// as many zeros as the signal would have had samples in it, so that the
+	/// index of a sample gives its time. With [`GapFill::Skip`], this is the
+	/// same as `read_signal`.
+	///
+	/// Filling gaps that would make the series longer than 2^30 samples is
+	/// an `InvalidData` error, as such gaps come from corrupt onsets.
+	///
+	/// # Panics
+	///
+	/// Panics if there is no signal at the given index.
+	pub fn read_signal_continuous(&mut self, signal: usize, fill: GapFill) -> Result<Vec<f64>> {
+		let samples = self.read_signal(signal)?;
+		let gaps = match fill {
+			GapFill::Skip => return Ok(samples),
+			GapFill::Zero => self.read_timeline()?.gaps(),
+		};
+		let rate = self.header().sampling_rate(signal).unwrap_or(0.0);
+		let samples_per_record = self.header().signals[signal].samples_per_record;
+		let too_long =
+			|| io::Error::new(io::ErrorKind::InvalidData, "the gaps are too long to fill");
+		let mut len = samples.len();
+		let mut fills = Vec::with_capacity(gaps.len());
+		for gap in gaps {
+			let zeros = (gap.duration * rate).round();
+			if zeros > MAX_CONTINUOUS_LEN as f64 {
+				return Err(too_long().into());
+			}
+			len = len
+				.checked_add(zeros as usize)
+				.filter(|&len| len <= MAX_CONTINUOUS_LEN)
+				.ok_or_else(too_long)?;
+			let end = gap
+				.record
+				.saturating_mul(samples_per_record)
+				.min(samples.len());
+			fills.push((end, zeros as usize));
+		}
+		let mut continuous = Vec::with_capacity(len);
+		let mut copied = 0;
+		for (end, zeros) in fills {
+			continuous.extend_from_slice(&samples[copied..end]);
+			copied = end;
+			continuous.resize(continuous.len() + zeros, 0.0);
+		}
+		continuous.extend_from_slice(&samples[copied..]);
+		Ok(continuous)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Gap, GapFill};
+	use crate::generator::{Generator, Pattern};
+	use crate::reader::Reader;
+
+	#[test]
+	fn discontinuous_records() {
+		let mut bytes = Vec::new();
+		Generator::new()
+			.records(3)
+			.channel("Sine", 4, Pattern::Sine { frequency: 0.25 })
+			.annotation(0.5, "Lights off")
+			.write(&mut bytes)
+			.unwrap();
+		bytes[192..197].copy_from_slice(b"EDF+D");
+		// The third record starts three seconds after the second ends.
+		let tal = bytes.windows(5).position(|w| w == b"+2\x14\x14\0").unwrap();
+		bytes[tal..tal + 5].copy_from_slice(b"+5\x14\x14\0");
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		let hdr = reader.header().clone();
+		let timeline = reader.read_timeline().unwrap();
+		let skipped = reader.read_signal_continuous(0, GapFill::Skip).unwrap();
+		let filled = reader.read_signal_continuous(0, GapFill::Zero).unwrap();
+
+		assert_eq!(timeline.onsets(), [0.0, 1.0, 5.0]);
+		assert_eq!(
+			timeline.gaps(),
+			[Gap {
+				record: 2,
+				start: 2.0,
+				duration: 3.0
+			}]
+		);
+		assert_eq!(timeline.gaps()[0].end(), 5.0);
+		assert_eq!(timeline.recorded(), 3.0);
+		assert_eq!(timeline.elapsed(), 6.0);
+		assert_eq!(timeline.sample_onset(&hdr, 0, 9), Some(5.25));
+		assert_eq!(skipped.len(), 12);
+		assert_eq!(filled.len(), 24);
+		assert_eq!(&filled[..8], &skipped[..8]);
+		assert!(filled[8..20].iter().all(|&s| s == 0.0));
+		assert_eq!(&filled[20..], &skipped[8..]);
+
+		// A gap of over three thousand years is not filled. The record has
+		// zero padding after its TAL to write the longer onset into.
+		bytes[tal..tal + 15].copy_from_slice(b"+99999999999\x14\x14\0");
+		let mut reader = Reader::from_bytes(&bytes).unwrap();
+		assert!(reader.read_signal_continuous(0, GapFill::Zero).is_err());
+	}
+}
@@ -0,0 +1,183 @@
+//! Physical dimensions parsed into units, so that `uV`, `µV` and ` uV` are
+//! recognized as the same unit.
+
+use std::fmt;
+
+use crate::signal::SignalHeader;
+
+/// A decimal prefix of a unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Prefix {
+	Pico,
+	Nano,
+	Micro,
+	Milli,
+	/// No prefix.
+	None,
+	Kilo,
+	Mega,
+}
+
+/// The prefixes and their symbols. Micro is written `u` in EDF, but `µ` is
+/// also accepted.
+const PREFIXES: [(&str, Prefix); 7] = [
+	("p", Prefix::Pico),
+	("n", Prefix::Nano),
+	("u", Prefix::Micro),
+	("m", Prefix::Milli),
+	("", Prefix::None),
+	("k", Prefix::Kilo),
+	("M", Prefix::Mega),
+];
+
+impl Prefix {
+	/// The factor the prefix multiplies the base unit by, e.g. 1e-6 for
+	/// micro.
+	pub fn factor(self) -> f64 {
+		match self {
+			Prefix::Pico => 1e-12,
+			Prefix::Nano => 1e-9,
+			Prefix::Micro => 1e-6,
+			Prefix::Milli => 1e-3,
+			Prefix::None => 1.0,
+			Prefix::Kilo => 1e3,
+			Prefix::Mega => 1e6,
+		}
+	}
+}
+
+/// A unit without its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BaseUnit {
+	Volt,
+	Ampere,
+	Ohm,
+	Hertz,
+	Second,
+	Meter,
+	Gram,
+	Liter,
+	Pascal,
+	/// Written `degC`.
+	DegreeCelsius,
+	Kelvin,
+	Percent,
+	/// Written `mmHg`.
+	MillimeterOfMercury,
+	/// Written `bpm`.
+	BeatsPerMinute,
+}
+
+/// The base units, their symbols and whether they take a prefix.
+const BASE_UNITS: [(&str, BaseUnit, bool); 14] = [
+	("V", BaseUnit::Volt, true),
+	("A", BaseUnit::Ampere, true),
+	("Ohm", BaseUnit::Ohm, true),
+	("Hz", BaseUnit::Hertz, true),
+	("s", BaseUnit::Second, true),
+	("m", BaseUnit::Meter, true),
+	("g", BaseUnit::Gram, true),
+	("l", BaseUnit::Liter, true),
+	("Pa", BaseUnit::Pascal, true),
+	("degC", BaseUnit::DegreeCelsius, false),
+	("K", BaseUnit::Kelvin, false),
+	("%", BaseUnit::Percent, false),
+	("mmHg", BaseUnit::MillimeterOfMercury, false),
+	("bpm", BaseUnit::BeatsPerMinute, false),
+];
+
+/// The physical dimension of a signal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Unit {
+	/// A recognized unit, e.g. `uV` or `degC`.
+	Known { prefix: Prefix, base: BaseUnit },
+	/// A dimension that is not recognized, such as `l/min` or an empty
+	/// field, trimmed.
+	Raw(String),
+}
+
+impl Unit {
+	/// Parses a physical dimension field.
+	///
+	/// Padding is ignored. Symbols are case-sensitive, as `m` and `M` are
+	/// different prefixes.
+	pub fn parse(s: &str) -> Unit {
+		let s = s.trim();
+		let symbol = s.replace(['µ', 'μ'], "u");
+		for &(base_symbol, base, prefixable) in &BASE_UNITS {
+			let prefix = match symbol.strip_suffix(base_symbol) {
+				Some(prefix) if prefixable || prefix.is_empty() => prefix,
+				_ => continue,
+			};
+			if let Some(&(_, prefix)) = PREFIXES.iter().find(|(p, _)| *p == prefix) {
+				return Unit::Known { prefix, base };
+			}
+		}
+		Unit::Raw(s.to_string())
+	}
+
+	/// The factor that converts values in this unit to another unit, e.g.
+	/// 1000 from `mV` to `uV`.
+	///
+	/// Returns `None` if the units have different base units or either is
+	/// not recognized.
+	pub fn factor_to(&self, other: &Unit) -> Option<f64> {
+		match (self, other) {
+			(
+				&Unit::Known { prefix, base },
+				&Unit::Known {
+					prefix: other_prefix,
+					base: other_base,
+				},
+			) if base == other_base => Some(prefix.factor() / other_prefix.factor()),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Unit {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Unit::Known { prefix, base } => {
+				let (prefix, _) = PREFIXES.iter().find(|(_, p)| *p == prefix).unwrap();
+				let (base, _, _) = BASE_UNITS.iter().find(|(_, b, _)| *b == base).unwrap();
+				write!(f, "{}{}", prefix, base)
+			}
+			Unit::Raw(ref s) => s.fmt(f),
+		}
+	}
+}
+
+impl SignalHeader {
+	/// The physical dimension of the signal, parsed into a unit.
+	pub fn unit(&self) -> Unit {
+		Unit::parse(&self.physical_dimension)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{BaseUnit, Prefix, Unit};
+
+	#[test]
+	fn parse_units() {
+		for (s, prefix, base) in [
+			("uV", Prefix::Micro, BaseUnit::Volt),
+			("µV    ", Prefix::Micro, BaseUnit::Volt),
+			("mV", Prefix::Milli, BaseUnit::Volt),
+			("degC", Prefix::None, BaseUnit::DegreeCelsius),
+			("%", Prefix::None, BaseUnit::Percent),
+			("mmHg", Prefix::None, BaseUnit::MillimeterOfMercury),
+			("mm", Prefix::Milli, BaseUnit::Meter),
+			("kOhm", Prefix::Kilo, BaseUnit::Ohm),
+		] {
+			assert_eq!(Unit::parse(s), Unit::Known { prefix, base }, "{:?}", s);
+		}
+		assert_eq!(Unit::parse("l/min "), Unit::Raw("l/min".to_string()));
+		assert_eq!(Unit::parse("m%"), Unit::Raw("m%".to_string()));
+		assert_eq!(Unit::parse("µV").to_string(), "uV");
+		let factor = Unit::parse("mV").factor_to(&Unit::parse("uV")).unwrap();
+		assert!((factor - 1000.0).abs() < 1e-9);
+		assert_eq!(Unit::parse("mV").factor_to(&Unit::parse("%")), None);
+	}
+}
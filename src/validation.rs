@@ -0,0 +1,337 @@
+//! Checks of a file against the EDF and EDF+ specs that report every
+//! violation rather than failing on the first.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::error::{HeaderError, Result};
+use crate::identification::{PatientId, RecordingId};
+use crate::layout::field_offset;
+use crate::reader::{FileType, Header, Reader};
+
+/// The record size the spec recommends not to exceed, in bytes.
+const RECOMMENDED_RECORD_SIZE: usize = 61440;
+
+/// How serious an issue is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Severity {
+	/// The file is allowed but discouraged by the spec, or likely to trip up
+	/// other readers.
+	Warning,
+	/// The file violates the spec.
+	Error,
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Severity::Warning => write!(f, "warning"),
+			Severity::Error => write!(f, "error"),
+		}
+	}
+}
+
+/// A violation of the spec found by validation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Issue {
+	/// Where the issue is, named as by [`offsets`](crate::offsets), e.g.
+	/// `startdate`, `signal 1 physical minimum` or `record 3`.
+	pub field: String,
+	pub severity: Severity,
+	pub message: String,
+	/// The offset of the field from the start of the file.
+	pub offset: u64,
+}
+
+impl fmt::Display for Issue {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} at byte {} ({}): {}",
+			self.severity, self.offset, self.field, self.message
+		)
+	}
+}
+
+impl Header {
+	/// Checks the header against the spec, listing every issue found.
+	///
+	/// This includes the problems recovered from when the header was read in
+	/// lenient mode, so a lenient reader gives the complete picture of a
+	/// non-conformant header. An empty list means the header conforms.
+	pub fn validate(&self) -> Vec<Issue> {
+		let mut issues = Issues::new(self.signals.len());
+		for warning in &self.warnings {
+			issues.header_error(warning, None);
+		}
+
+		for (name, raw) in [
+			("local patient identification", self.raw_patient_info()),
+			("local recording identification", self.raw_recording_id()),
+			("reserved", self.raw_reserved()),
+		] {
+			if !raw.iter().all(|b| (b' '..=b'~').contains(b)) {
+				issues.push_once(name, None, Severity::Error, "not printable ASCII");
+			}
+		}
+		if self.file_type() != FileType::Edf {
+			if let Err(err) = PatientId::parse(&self.patient_info) {
+				let message = err.to_string();
+				issues.push(
+					"local patient identification",
+					None,
+					Severity::Error,
+					message,
+				);
+			}
+			if let Err(err) = RecordingId::parse(&self.recording_id) {
+				let message = err.to_string();
+				issues.push(
+					"local recording identification",
+					None,
+					Severity::Error,
+					message,
+				);
+			}
+			if !self.signals.iter().any(|s| s.is_annotation()) {
+				issues.push(
+					"number of signals",
+					None,
+					Severity::Error,
+					"an EDF+ file has no annotation signal",
+				);
+			}
+		}
+		if self.records_len.is_none() {
+			issues.push(
+				"number of data records",
+				None,
+				Severity::Warning,
+				"-1 is only allowed while recording",
+			);
+		}
+		if let Some(size) = self.record_size() {
+			if size > RECOMMENDED_RECORD_SIZE {
+				issues.push(
+					"duration of a data record",
+					None,
+					Severity::Warning,
+					format!(
+						"data records of {} bytes exceed the recommended {} bytes",
+						size, RECOMMENDED_RECORD_SIZE
+					),
+				);
+			}
+		}
+
+		for (i, signal) in self.signals.iter().enumerate() {
+			for (name, text) in [
+				("label", &signal.label),
+				("transducer type", &signal.transducer),
+				("physical dimension", &signal.physical_dimension),
+				("prefiltering", &signal.prefiltering),
+				("reserved", &signal.reserved),
+			] {
+				if !text.chars().all(|c| (' '..='~').contains(&c)) {
+					issues.push_once(name, Some(i), Severity::Error, "not printable ASCII");
+				}
+			}
+			if signal.physical_min == signal.physical_max {
+				issues.push(
+					"physical maximum",
+					Some(i),
+					Severity::Error,
+					"equals the physical minimum",
+				);
+			}
+			if signal.digital_min >= signal.digital_max {
+				issues.push(
+					"digital maximum",
+					Some(i),
+					Severity::Error,
+					"is not greater than the digital minimum",
+				);
+			}
+			for (name, value) in [
+				("digital minimum", signal.digital_min),
+				("digital maximum", signal.digital_max),
+			] {
+				if !(self.format.digital_min()..=self.format.digital_max()).contains(&value) {
+					issues.push(
+						name,
+						Some(i),
+						Severity::Error,
+						format!("{} does not fit a {} sample", value, self.format),
+					);
+				}
+			}
+			if signal.samples_per_record == 0 {
+				issues.push(
+					"number of samples in each data record",
+					Some(i),
+					Severity::Warning,
+					"the signal has no samples",
+				);
+			}
+		}
+		issues.issues
+	}
+}
+
+/// Checks the file at the given path against the spec, listing every issue
+/// found.
+///
+/// The header is read in lenient mode and checked with
+/// [`Header::validate`]. The size of the file is checked against the number
+/// of data records, and the annotations of EDF+ files are parsed. Only a
+/// header too broken to read at all is an error.
+pub fn validate<P: AsRef<Path>>(path: P) -> Result<Vec<Issue>> {
+	let path = path.as_ref();
+	let reader = Reader::options().lenient(true).from_path(path)?;
+	let hdr = reader.header();
+	let mut issues = hdr.validate();
+
+	let record_size = hdr.record_size().unwrap_or(0);
+	let records_len = hdr.inferred_records_len.unwrap_or(0);
+	if let (Some(declared), Some(inferred)) = (hdr.records_len, hdr.inferred_records_len) {
+		if declared != inferred {
+			issues.push(Issue {
+				field: "number of data records".to_string(),
+				severity: Severity::Error,
+				message: format!("{} declared, but the file holds {}", declared, inferred),
+				offset: field_offset(hdr.signals.len(), "number of data records", None)
+					.unwrap_or(0),
+			});
+		}
+	}
+	let len = std::fs::metadata(path)?.len();
+	let end = hdr.size as u64 + (records_len * record_size) as u64;
+	if record_size > 0 && len > end {
+		issues.push(Issue {
+			field: format!("record {}", records_len),
+			severity: Severity::Error,
+			message: format!(
+				"{} trailing bytes do not make up a data record of {} bytes",
+				len - end,
+				record_size
+			),
+			offset: end,
+		});
+	}
+
+	if hdr.file_type() != FileType::Edf {
+		for i in 0..records_len {
+			let record = reader.read_records_at(i..i + 1)?;
+			if let Err(err) = record[0].annotations(hdr) {
+				issues.push(Issue {
+					field: format!("record {}", i),
+					severity: Severity::Error,
+					message: err.to_string(),
+					offset: hdr.size as u64 + (i * record_size) as u64,
+				});
+			}
+		}
+	}
+	Ok(issues)
+}
+
+/// Collects the issues of a header.
+pub(crate) struct Issues {
+	signals_len: usize,
+	pub(crate) issues: Vec<Issue>,
+}
+
+impl Issues {
+	pub(crate) fn new(signals_len: usize) -> Issues {
+		Issues {
+			signals_len,
+			issues: Vec::new(),
+		}
+	}
+
+	/// Adds an issue with a header field, by its name in the spec.
+	pub(crate) fn push<M: Into<String>>(
+		&mut self,
+		name: &str,
+		signal: Option<usize>,
+		severity: Severity,
+		message: M,
+	) {
+		self.issues.push(Issue {
+			field: match signal {
+				Some(i) => format!("signal {} {}", i, name),
+				None => name.to_string(),
+			},
+			severity,
+			message: message.into(),
+			offset: field_offset(self.signals_len, name, signal).unwrap_or(0),
+		});
+	}
+
+	/// Adds an issue unless the field already has one, as when the same
+	/// problem was recovered from while reading.
+	fn push_once(&mut self, name: &str, signal: Option<usize>, severity: Severity, message: &str) {
+		let offset = field_offset(self.signals_len, name, signal).unwrap_or(0);
+		if !self.issues.iter().any(|issue| issue.offset == offset) {
+			self.push(name, signal, severity, message);
+		}
+	}
+
+	/// Adds an issue for a problem recovered from while reading.
+	fn header_error(&mut self, err: &HeaderError, signal: Option<usize>) {
+		let name = match *err {
+			HeaderError::Signal { index, ref err } => return self.header_error(err, Some(index)),
+			HeaderError::Version => "version",
+			HeaderError::InvalidDate { .. } | HeaderError::StartdateMismatch { .. } => "startdate",
+			HeaderError::InvalidTime { .. } => "starttime",
+			HeaderError::NegativeRecordCount { .. } => "number of data records",
+			HeaderError::SizeMismatch { .. } => "number of bytes in header",
+			HeaderError::RecordTooLarge { .. } => "number of signals",
+			HeaderError::InvalidNumber { field, .. }
+			| HeaderError::InvalidText { field, .. }
+			| HeaderError::InvalidField { field, .. } => field,
+		};
+		self.push(name, signal, Severity::Error, err.to_string());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::{validate, Severity};
+	use crate::generator::{Corruption, Generator, Pattern};
+
+	#[test]
+	fn validate_lists_every_issue() {
+		let path = std::env::temp_dir().join(format!("edf-validate-{}.edf", std::process::id()));
+		Generator::new()
+			.records(3)
+			.channel("EEG Fpz-Cz", 4, Pattern::Noise)
+			.annotation(1.0, "Lights off")
+			.corrupt(Corruption::TruncatedRecord)
+			.to_path(&path)
+			.unwrap();
+		let mut bytes = fs::read(&path).unwrap();
+		bytes[176..184].copy_from_slice(b"22:00:00");
+		// The physical maximum of the first of the two signals.
+		bytes[480..488].copy_from_slice(b"-1000   ");
+		fs::write(&path, &bytes).unwrap();
+		let issues = validate(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		let messages: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+		assert_eq!(
+			messages,
+			[
+				r#"error at byte 176 (starttime): invalid starttime: "22:00:00" (expected hh.mm.ss)"#,
+				"error at byte 480 (signal 0 physical maximum): equals the physical minimum",
+				"error at byte 236 (number of data records): 3 declared, but the file holds 2",
+				"error at byte 824 (record 2): 14 trailing bytes do not make up a data record of 28 bytes",
+			]
+		);
+		assert!(issues.iter().all(|i| i.severity == Severity::Error));
+	}
+}
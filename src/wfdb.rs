@@ -0,0 +1,615 @@
+//! Conversion of WFDB records, the format of most PhysioNet datasets, to
+//! EDF.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::pipeline::{Block, EdfSink, Sink, StreamSignal};
+use crate::reader::{FileFormat, Header};
+
+/// The sampling frequency of a record whose header does not give one.
+const DEFAULT_FREQUENCY: f64 = 250.0;
+/// The gain of a signal whose header does not give one, in ADC units per
+/// physical unit.
+const DEFAULT_GAIN: f64 = 200.0;
+
+/// The header of a WFDB record, from its `.hea` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WfdbHeader {
+	/// The name of the record, which the names of its files start with.
+	pub name: String,
+	/// The number of frames per second.
+	pub frequency: f64,
+	/// The number of frames. If the header does not give it, then it is
+	/// `None` and the signal files are read to their end.
+	pub frames_len: Option<usize>,
+	/// The base date and time. If the header gives no base date, then it is
+	/// `None`.
+	pub start: Option<NaiveDateTime>,
+	pub signals: Vec<WfdbSignal>,
+	/// The comment lines, without the leading `#`.
+	pub comments: Vec<String>,
+}
+
+/// A signal of a WFDB record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WfdbSignal {
+	/// The file that holds the samples, relative to the header.
+	pub file: String,
+	/// The storage format, e.g. 16 or 212.
+	pub format: u32,
+	/// The number of samples of the signal in each frame.
+	pub samples_per_frame: usize,
+	/// The offset of the first sample in the file, in bytes.
+	pub byte_offset: u64,
+	/// The ADC units per physical unit.
+	pub gain: f64,
+	/// The ADC value of a physical zero.
+	pub baseline: i32,
+	pub units: String,
+	/// The resolution of the ADC in bits. If unknown, then it is 0.
+	pub adc_resolution: u32,
+	/// The ADC value in the middle of its range.
+	pub adc_zero: i32,
+	pub description: String,
+}
+
+impl WfdbHeader {
+	/// Parses the text of a `.hea` file.
+	///
+	/// Multi-segment records are not supported.
+	pub fn parse(s: &str) -> Result<WfdbHeader> {
+		let mut comments = Vec::new();
+		let mut lines = Vec::new();
+		for (i, line) in s.lines().enumerate() {
+			let line = line.trim();
+			match line.strip_prefix('#') {
+				Some(comment) => comments.push(comment.trim().to_string()),
+				None if line.is_empty() => {}
+				None => lines.push((i + 1, line)),
+			}
+		}
+		let (&(line, record), signal_lines) = lines.split_first().ok_or_else(|| malformed(1))?;
+		let fields: Vec<&str> = record.split_whitespace().collect();
+		let name = fields[0];
+		if name.contains('/') {
+			return Err(unsupported(format!("the record {} has segments", name)));
+		}
+		let signals_len: usize = fields
+			.get(1)
+			.and_then(|s| s.parse().ok())
+			.ok_or_else(|| malformed(line))?;
+		let frequency = match fields.get(2) {
+			// The counter frequency and base counter value are not needed.
+			Some(s) => s
+				.split(['/', '('])
+				.next()
+				.and_then(|s| s.parse::<f64>().ok())
+				.filter(|&f| f > 0.0)
+				.ok_or_else(|| malformed(line))?,
+			None => DEFAULT_FREQUENCY,
+		};
+		let frames_len = match fields.get(3) {
+			Some(s) => Some(s.parse::<usize>().map_err(|_| malformed(line))?),
+			None => None,
+		}
+		.filter(|&len| len > 0);
+		let start = match (fields.get(4), fields.get(5)) {
+			(Some(time), Some(date)) => {
+				let time = NaiveTime::parse_from_str(time, "%H:%M:%S%.f")
+					.or_else(|_| NaiveTime::parse_from_str(time, "%M:%S%.f"))
+					.map_err(|_| malformed(line))?;
+				let date =
+					NaiveDate::parse_from_str(date, "%d/%m/%Y").map_err(|_| malformed(line))?;
+				Some(NaiveDateTime::new(date, time))
+			}
+			_ => None,
+		};
+		if signal_lines.len() != signals_len {
+			return Err(malformed(line));
+		}
+		let signals = signal_lines
+			.iter()
+			.map(|&(line, s)| parse_signal(s).ok_or_else(|| malformed(line)))
+			.collect::<Result<Vec<_>>>()?;
+		Ok(WfdbHeader {
+			name: name.to_string(),
+			frequency,
+			frames_len,
+			start,
+			signals,
+			comments,
+		})
+	}
+
+	/// Reads the `.hea` file of a record.
+	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<WfdbHeader> {
+		WfdbHeader::parse(&fs::read_to_string(path)?)
+	}
+}
+
+/// Parses a signal line: the file name, the format, the gain with its
+/// baseline and units, the ADC resolution, the ADC zero, the initial value,
+/// the checksum, the block size and the description.
+fn parse_signal(line: &str) -> Option<WfdbSignal> {
+	let fields: Vec<&str> = line.split_whitespace().collect();
+	let file = fields.first()?.to_string();
+
+	// The format may be followed by xSAMPLES, :SKEW and +OFFSET.
+	let spec = fields.get(1)?;
+	let end = spec.find(['x', ':', '+']).unwrap_or(spec.len());
+	let format = spec[..end].parse().ok()?;
+	let mut samples_per_frame = 1;
+	let mut byte_offset = 0;
+	let mut rest = &spec[end..];
+	while let Some(kind) = rest.chars().next() {
+		let end = rest[1..]
+			.find(['x', ':', '+'])
+			.map_or(rest.len(), |i| i + 1);
+		let value = &rest[1..end];
+		match kind {
+			'x' => samples_per_frame = value.parse().ok().filter(|&n| n > 0)?,
+			'+' => byte_offset = value.parse().ok()?,
+			// Skew is rare and only shifts a signal by a few samples.
+			_ => {}
+		}
+		rest = &rest[end..];
+	}
+
+	let adc_resolution = match fields.get(3) {
+		Some(s) => s.parse().ok()?,
+		None => 0,
+	};
+	let adc_zero = match fields.get(4) {
+		Some(s) => s.parse().ok()?,
+		None => 0,
+	};
+	let (mut gain, mut baseline, mut units) = (DEFAULT_GAIN, adc_zero, "mV".to_string());
+	if let Some(s) = fields.get(2) {
+		let (s, unit) = match s.split_once('/') {
+			Some((s, unit)) => (s, Some(unit)),
+			None => (*s, None),
+		};
+		let s = match s.split_once('(') {
+			Some((s, base)) => {
+				baseline = base.strip_suffix(')')?.parse().ok()?;
+				s
+			}
+			None => s,
+		};
+		gain = s.parse().ok()?;
+		if gain == 0.0 {
+			gain = DEFAULT_GAIN;
+		}
+		if let Some(unit) = unit {
+			units = unit.to_string();
+		}
+	}
+	Some(WfdbSignal {
+		file,
+		format,
+		samples_per_frame,
+		byte_offset,
+		gain,
+		baseline,
+		units,
+		adc_resolution,
+		adc_zero,
+		description: fields.get(8..).unwrap_or_default().join(" "),
+	})
+}
+
+/// Converts a WFDB record to a plain EDF file, returning the number of data
+/// records written.
+///
+/// The record is given by the path of its `.hea` file, and its signal files
+/// are looked up next to it. Formats 16, 212 and 80 are supported. Each
+/// data record holds one second, so the sampling frequency must be a whole
+/// number. The last data record is padded with missing samples, and the
+/// comments of the header are carried over to the recording
+/// identification, as far as they fit.
+pub fn wfdb_to_edf<P: AsRef<Path>, Q: AsRef<Path>>(hea: P, output: Q) -> Result<usize> {
+	let hea = hea.as_ref();
+	let wfdb = WfdbHeader::from_path(hea)?;
+	if wfdb.frequency.fract() != 0.0 {
+		return Err(unsupported(format!(
+			"the sampling frequency {} is not a whole number",
+			wfdb.frequency
+		)));
+	}
+	let frames_per_record = wfdb.frequency as usize;
+	let dir = hea.parent().unwrap_or_else(|| Path::new(""));
+	let mut files = SignalFiles::open(dir, &wfdb.signals)?;
+
+	// EDF needs a start, so records without a base date start at the
+	// earliest date it can hold.
+	let start = wfdb.start.unwrap_or_else(|| {
+		NaiveDate::from_ymd_opt(1985, 1, 1)
+			.unwrap()
+			.and_hms_opt(0, 0, 0)
+			.unwrap()
+	});
+	let hdr = Header::new(
+		FileFormat::Edf,
+		"X X X X".to_string(),
+		recording_id(&wfdb),
+		start.date(),
+		start.time(),
+		256 * (wfdb.signals.len() + 1),
+		String::new(),
+		None,
+		Duration::from_secs(1),
+		wfdb.signals.len() as u32,
+	);
+	let signals: Vec<StreamSignal> = wfdb
+		.signals
+		.iter()
+		.map(|s| {
+			let (min, max) = s.digital_range();
+			let (a, b) = (s.to_physical(min), s.to_physical(max));
+			StreamSignal {
+				label: s.description.chars().take(16).collect(),
+				physical_dimension: s.units.clone(),
+				physical_min: a.min(b),
+				physical_max: a.max(b),
+				samples_per_record: frames_per_record * s.samples_per_frame,
+			}
+		})
+		.collect();
+
+	let mut sink = EdfSink::new(BufWriter::new(File::create(output)?));
+	sink.begin(&hdr, &signals)?;
+	let mut frames = 0;
+	let mut records_len = 0;
+	loop {
+		let mut block = Block {
+			onset: records_len as f64,
+			duration: 1.0,
+			signals: signals
+				.iter()
+				.map(|s| Vec::with_capacity(s.samples_per_record))
+				.collect(),
+		};
+		let mut read = 0;
+		while read < frames_per_record && frames < wfdb.frames_len.unwrap_or(usize::MAX) {
+			if !files.read_frame(&wfdb.signals, &mut block.signals)? {
+				break;
+			}
+			read += 1;
+			frames += 1;
+		}
+		if read == 0 {
+			break;
+		}
+		for (samples, signal) in block.signals.iter_mut().zip(&signals) {
+			samples.resize(signal.samples_per_record, f64::NAN);
+		}
+		sink.write(&block)?;
+		records_len += 1;
+	}
+	sink.finish()?;
+	Ok(records_len)
+}
+
+/// Converts every record listed in the `RECORDS` file of a PhysioNet
+/// dataset, writing each to the output directory as its name followed by
+/// `.edf`.
+///
+/// Records in subdirectories keep their path below the output directory.
+/// Returns the name of each record with the number of data records written
+/// or the error that stopped its conversion, so that one bad record does not
+/// stop the others. Only failing to read the `RECORDS` file is an error.
+pub fn import_wfdb<P: AsRef<Path>, Q: AsRef<Path>>(
+	dir: P,
+	output: Q,
+) -> Result<Vec<(String, Result<usize>)>> {
+	let (dir, output) = (dir.as_ref(), output.as_ref());
+	let records = fs::read_to_string(dir.join("RECORDS"))?;
+	Ok(records
+		.lines()
+		.map(str::trim)
+		.filter(|name| !name.is_empty())
+		.map(|name| (name.to_string(), import_record(dir, output, name)))
+		.collect())
+}
+
+fn import_record(dir: &Path, output: &Path, name: &str) -> Result<usize> {
+	let path = output.join(format!("{}.edf", name));
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	wfdb_to_edf(dir.join(format!("{}.hea", name)), path)
+}
+
+impl WfdbSignal {
+	/// The lowest and highest ADC values the signal can take.
+	fn digital_range(&self) -> (i32, i32) {
+		let bits = match (self.adc_resolution, self.format) {
+			(bits @ 1..=16, _) => bits,
+			(_, 80) => 8,
+			(_, 212) => 12,
+			_ => 16,
+		};
+		let half = 1 << (bits - 1);
+		let min = self.adc_zero.saturating_sub(half);
+		(min, min.saturating_add(2 * half - 1))
+	}
+
+	fn to_physical(&self, digital: i32) -> f64 {
+		(digital - self.baseline) as f64 / self.gain
+	}
+}
+
+/// The recording identification: the name of the record followed by the
+/// comments of its header, cut to fit the field.
+fn recording_id(wfdb: &WfdbHeader) -> String {
+	let mut id = wfdb.name.clone();
+	for comment in &wfdb.comments {
+		id.push(' ');
+		id.extend(comment.chars().map(|c| {
+			if c.is_ascii() && !c.is_ascii_control() {
+				c
+			} else {
+				' '
+			}
+		}));
+	}
+	id.truncate(80);
+	id.trim_end().to_string()
+}
+
+/// The signal files of a record, each read in the order of its frames.
+struct SignalFiles {
+	files: Vec<SampleReader>,
+	/// The index of the file of each signal.
+	signal_files: Vec<usize>,
+}
+
+impl SignalFiles {
+	fn open(dir: &Path, signals: &[WfdbSignal]) -> Result<SignalFiles> {
+		let mut indices: HashMap<&str, usize> = HashMap::new();
+		let mut files: Vec<SampleReader> = Vec::new();
+		let mut signal_files = Vec::with_capacity(signals.len());
+		for signal in signals {
+			let i = match indices.get(signal.file.as_str()) {
+				Some(&i) => {
+					if files[i].format != signal.format {
+						return Err(unsupported(format!(
+							"{} holds signals of different formats",
+							signal.file
+						)));
+					}
+					i
+				}
+				None => {
+					if !matches!(signal.format, 16 | 80 | 212) {
+						return Err(unsupported(format!(
+							"format {} is not supported",
+							signal.format
+						)));
+					}
+					let mut f = BufReader::new(File::open(dir.join(&signal.file))?);
+					f.seek(SeekFrom::Start(signal.byte_offset))?;
+					files.push(SampleReader {
+						rdr: f,
+						format: signal.format,
+						pending: None,
+					});
+					indices.insert(&signal.file, files.len() - 1);
+					files.len() - 1
+				}
+			};
+			signal_files.push(i);
+		}
+		Ok(SignalFiles {
+			files,
+			signal_files,
+		})
+	}
+
+	/// Reads the next frame, appending the physical samples of each signal.
+	/// Samples past the end of a file, and those WFDB marks as invalid, are
+	/// NaN. Returns `false`, without appending anything, if every file has
+	/// ended.
+	fn read_frame(&mut self, signals: &[WfdbSignal], samples: &mut [Vec<f64>]) -> Result<bool> {
+		let mut frame = Vec::with_capacity(signals.len());
+		let mut any = false;
+		for (signal, &file) in signals.iter().zip(&self.signal_files) {
+			let reader = &mut self.files[file];
+			let mut values = Vec::with_capacity(signal.samples_per_frame);
+			for _ in 0..signal.samples_per_frame {
+				let value = reader.next()?;
+				any |= value.is_some();
+				values.push(match value {
+					Some(d) if d != reader.invalid() => signal.to_physical(d),
+					_ => f64::NAN,
+				});
+			}
+			frame.push(values);
+		}
+		if !any {
+			return Ok(false);
+		}
+		for (samples, values) in samples.iter_mut().zip(frame) {
+			samples.extend(values);
+		}
+		Ok(true)
+	}
+}
+
+/// Reads the samples of a signal file in order, whichever signals they are
+/// of.
+struct SampleReader {
+	rdr: BufReader<File>,
+	format: u32,
+	/// The second sample of a format 212 pair.
+	pending: Option<i32>,
+}
+
+impl SampleReader {
+	/// Reads the next sample, or `None` at the end of the file.
+	fn next(&mut self) -> io::Result<Option<i32>> {
+		if let Some(sample) = self.pending.take() {
+			return Ok(Some(sample));
+		}
+		match self.format {
+			16 => {
+				let mut buf = [0; 2];
+				Ok(read_or_eof(&mut self.rdr, &mut buf)?.then(|| i16::from_le_bytes(buf) as i32))
+			}
+			80 => {
+				let mut buf = [0; 1];
+				Ok(read_or_eof(&mut self.rdr, &mut buf)?.then(|| buf[0] as i32 - 128))
+			}
+			_ => {
+				// Two 12-bit samples in three bytes, the high bits of both in
+				// the middle byte.
+				let mut buf = [0; 3];
+				if !read_or_eof(&mut self.rdr, &mut buf)? {
+					return Ok(None);
+				}
+				let first = buf[0] as i32 | (buf[1] as i32 & 0x0f) << 8;
+				let second = buf[2] as i32 | (buf[1] as i32 & 0xf0) << 4;
+				self.pending = Some(sign_extend_12(second));
+				Ok(Some(sign_extend_12(first)))
+			}
+		}
+	}
+
+	/// The value WFDB writes for a missing sample.
+	fn invalid(&self) -> i32 {
+		match self.format {
+			80 => -128,
+			212 => -2048,
+			_ => -32768,
+		}
+	}
+}
+
+fn sign_extend_12(value: i32) -> i32 {
+	(value << 20) >> 20
+}
+
+/// Fills the buffer, returning `false` if the reader ends before it is full.
+fn read_or_eof<R: Read>(rdr: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+	match rdr.read_exact(buf) {
+		Ok(()) => Ok(true),
+		Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+		Err(err) => Err(err),
+	}
+}
+
+fn malformed(line: usize) -> Error {
+	Error::new(ErrorKind::Wfdb { line })
+}
+
+fn unsupported(message: String) -> Error {
+	io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use chrono::NaiveDate;
+
+	use super::{import_wfdb, WfdbHeader};
+	use crate::reader::Reader;
+
+	#[test]
+	fn parse_header() {
+		let wfdb = WfdbHeader::parse(
+			"# Age: 69 Sex: M\n\
+			 100 2 360 650000 12:30:00 25/03/1998\n\
+			 100.dat 212 200(1024)/mV 11 1024 995 -22131 0 MLII\n\
+			 100.dat 212x2+16 400 11 1024 1011 20052 0 V5 lead\n",
+		)
+		.unwrap();
+
+		assert_eq!(wfdb.name, "100");
+		assert_eq!(wfdb.frequency, 360.0);
+		assert_eq!(wfdb.frames_len, Some(650000));
+		assert_eq!(
+			wfdb.start,
+			NaiveDate::from_ymd_opt(1998, 3, 25)
+				.unwrap()
+				.and_hms_opt(12, 30, 0)
+		);
+		assert_eq!(wfdb.comments, ["Age: 69 Sex: M"]);
+		assert_eq!(wfdb.signals[0].baseline, 1024);
+		assert_eq!(wfdb.signals[0].description, "MLII");
+		assert_eq!(wfdb.signals[1].samples_per_frame, 2);
+		assert_eq!(wfdb.signals[1].byte_offset, 16);
+		assert_eq!(wfdb.signals[1].gain, 400.0);
+		assert_eq!(wfdb.signals[1].units, "mV");
+		assert_eq!(wfdb.signals[1].description, "V5 lead");
+		assert_eq!(
+			WfdbHeader::parse("100 3 360\n100.dat 212\n")
+				.unwrap_err()
+				.to_string(),
+			"malformed WFDB header on line 1"
+		);
+		assert!(WfdbHeader::parse("multi/2 1 360\n").is_err());
+	}
+
+	#[test]
+	fn import_dataset() {
+		let dir = std::env::temp_dir().join(format!("edf-wfdb-{}", std::process::id()));
+		let output = dir.join("edf");
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(dir.join("RECORDS"), "a01\nmissing\n").unwrap();
+		fs::write(
+			dir.join("a01.hea"),
+			"a01 2 4 6 08:00:00 01/02/2000\n\
+			 a01.dat 212 100/uV 12 0 0 0 0 ECG\n\
+			 a01.dat 212 100/uV 12 0 0 0 0 Resp\n\
+			 # Apnea study\n",
+		)
+		.unwrap();
+		// Six frames of two signals in format 212: the ECG counts up from -3
+		// and the respiration is 100 throughout, but for one invalid sample.
+		let mut samples = Vec::new();
+		for i in 0..6 {
+			samples.push(i - 3);
+			samples.push(if i == 4 { -2048 } else { 100 });
+		}
+		let mut dat = Vec::new();
+		for pair in samples.chunks(2) {
+			let (a, b) = (pair[0] & 0xfff, pair[1] & 0xfff);
+			dat.extend([a as u8, ((a >> 8) | (b >> 8) << 4) as u8, b as u8]);
+		}
+		fs::write(dir.join("a01.dat"), dat).unwrap();
+
+		let results = import_wfdb(&dir, &output).unwrap();
+		let mut reader = Reader::from_path(output.join("a01.edf")).unwrap();
+		let hdr = reader.header().clone();
+		let ecg = reader.read_signal(0).unwrap();
+		let resp = reader.read_signal(1).unwrap();
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(results.len(), 2);
+		assert_eq!(results[0].0, "a01");
+		assert_eq!(results[0].1.as_ref().unwrap(), &2);
+		assert!(results[1].1.is_err());
+		assert_eq!(hdr.recording_id, "a01 Apnea study");
+		assert_eq!(hdr.start_datetime.to_string(), "2000-02-01 08:00:00");
+		assert_eq!(hdr.signals[1].label, "Resp");
+		assert_eq!(hdr.signals[1].physical_dimension, "uV");
+		assert_eq!(hdr.signals[0].samples_per_record, 4);
+		let step = 40.95 * 2.0 / 65535.0;
+		for (i, sample) in ecg[..6].iter().enumerate() {
+			assert!((sample - (i as f64 - 3.0) / 100.0).abs() <= step);
+		}
+		assert!((resp[0] - 1.0).abs() <= step);
+		// The invalid sample and the padding of the last record are missing,
+		// so they are written as the digital minimum.
+		assert_eq!(resp[4], hdr.signals[1].physical_min);
+		assert_eq!(resp[7], hdr.signals[1].physical_min);
+	}
+}